@@ -0,0 +1,67 @@
+// Any copyright is dedicated to the Public Domain.
+// https://creativecommons.org/publicdomain/zero/1.0/
+
+// Drive a Duplo train until it runs onto a red track marker, honking at
+// every yellow one on the way.
+
+use lego_powered_up::{
+    consts::Color,
+    iodevice::duplo::{DuploMotor, TrackSensor},
+    iodevice::hubled::{self, HubLed},
+    iodevice::speaker::{DuploTrainBaseSound, Speaker},
+    IoDevice, IoTypeId,
+};
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let hub = lego_powered_up::setup::single_hub().await?;
+
+    // Devices to be used
+    let light: IoDevice;
+    let motor: IoDevice;
+    let speaker: IoDevice;
+    let sensor: IoDevice;
+    {
+        let lock = hub.mutex.lock().await;
+        light = lock.io_from_kind(IoTypeId::HubLed)?;
+        motor = lock.io_from_kind(IoTypeId::DuploTrainBaseMotor)?;
+        speaker = lock.io_from_kind(IoTypeId::DuploTrainBaseSpeaker)?;
+        sensor = lock.io_from_kind(IoTypeId::DuploTrainBaseColorSensor)?;
+    }
+
+    let (mut markers, _task) = sensor.markers().await?;
+
+    println!("Departing");
+    light.set_hubled_mode(hubled::HubLedMode::Colour).await?;
+    light.set_hubled_color(Color::Green).await?;
+    speaker
+        .play_sound(DuploTrainBaseSound::StationDeparture)
+        .await?;
+    motor.drive(50).await?;
+
+    while let Ok(color) = markers.recv().await {
+        match color {
+            Color::Red => {
+                println!("Red marker, stopping");
+                motor.stop().await?;
+                light.set_hubled_color(Color::Red).await?;
+                speaker.play_sound(DuploTrainBaseSound::Brake).await?;
+                break;
+            }
+            Color::Yellow => {
+                println!("Yellow marker, honk!");
+                speaker.play_sound(DuploTrainBaseSound::Horn).await?;
+            }
+            _ => (),
+        }
+    }
+
+    println!("Disconnect from hub `{}`", hub.name);
+    {
+        let lock = hub.mutex.lock().await;
+        lock.disconnect().await?;
+    }
+    println!("Done!");
+
+    Ok(())
+}
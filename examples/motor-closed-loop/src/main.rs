@@ -67,17 +67,13 @@ async fn main() -> anyhow::Result<()> {
                             let _ = motor.start_power(Power::Brake).await;
                             cmd = (false, false);
                         }
-                        RcButtonState::Aminus => {
-                            if !at_limit.0 {
-                                cmd.0 = true;
-                                let _ = motor.start_speed(-set_speed, MAX_POWER).await;
-                            }
+                        RcButtonState::Aminus if !at_limit.0 => {
+                            cmd.0 = true;
+                            let _ = motor.start_speed(-set_speed, MAX_POWER).await;
                         }
-                        RcButtonState::Aplus => {
-                            if !at_limit.1 {
-                                cmd.1 = true;
-                                let _ = motor.start_speed(set_speed, MAX_POWER).await;
-                            }
+                        RcButtonState::Aplus if !at_limit.1 => {
+                            cmd.1 = true;
+                            let _ = motor.start_speed(set_speed, MAX_POWER).await;
                         }
                         RcButtonState::Ared => {
                             match set_limit.0 {
@@ -103,17 +99,13 @@ async fn main() -> anyhow::Result<()> {
                                 }
                             }
                         }
-                        RcButtonState::Bplus => {
-                            if set_speed < 96 {
-                                set_speed += 5;
-                                println!("Set speed: {}", set_speed);
-                            }
+                        RcButtonState::Bplus if set_speed < 96 => {
+                            set_speed += 5;
+                            println!("Set speed: {}", set_speed);
                         }
-                        RcButtonState::Bminus => {
-                            if set_speed > 4 {
-                                set_speed -= 5;
-                                println!("Set speed: {}", set_speed);
-                            }
+                        RcButtonState::Bminus if set_speed > 4 => {
+                            set_speed -= 5;
+                            println!("Set speed: {}", set_speed);
                         }
 
                         // RcButtonState::Bup => { println!("B side released"); }
@@ -0,0 +1,9 @@
+#![no_main]
+
+use lego_powered_up::notifications::NotificationMessage;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // Anything a hub sends must parse to a message or an error, never panic
+    let _ = NotificationMessage::parse(data);
+});
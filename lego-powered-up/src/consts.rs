@@ -35,7 +35,7 @@ use std::fmt::{self, Display};
 /// @property {number} CONTROL_PLUS_ACCELEROMETER 58
 /// @property {number} CONTROL_PLUS_TILT 59
 /// ```
-
+///
 // Added more IDs, some observed and some from
 // https://github.com/nathankellenicki/node-poweredup/blob/master/src/consts.ts
 #[repr(u8)]
@@ -141,7 +141,7 @@ impl Display for HubType {
 /// @property {number} NONE 255
 /// ```
 #[repr(u8)]
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, FromPrimitive)]
 pub enum Color {
     Black = 0,
     Pink = 1,
@@ -217,6 +217,26 @@ pub enum DuploTrainBaseSound {
     Steam = 10,
 }
 
+/// ```text,ignore
+/// @typedef TiltDirection
+/// @property {number} NEUTRAL 0
+/// @property {number} BACKWARD 3
+/// @property {number} RIGHT 5
+/// @property {number} LEFT 7
+/// @property {number} FORWARD 9
+/// @property {number} UNKNOWN 10
+/// ```
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, FromPrimitive)]
+pub enum TiltDirection {
+    Neutral = 0,
+    Backward = 3,
+    Right = 5,
+    Left = 7,
+    Forward = 9,
+    Unknown = 10,
+}
+
 #[repr(u8)]
 #[derive(Copy, Clone, Debug, PartialEq, Eq, FromPrimitive)]
 pub enum BLEManufacturerData {
@@ -549,7 +569,7 @@ pub enum PortInputFormatSetupSubCommand {
 /// @param {number} BUILDER 0x22
 /// ```
 #[repr(u8)]
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, FromPrimitive)]
 pub enum MarioPantsType {
     None = 0x00,
     Propeller = 0x06,
@@ -571,7 +591,7 @@ pub enum MarioPantsType {
 /// @param {number} CYAN 0x4201
 /// ```
 #[repr(u16)]
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, FromPrimitive)]
 pub enum MarioColor {
     White = 0x1300,
     Red = 0x1500,
@@ -0,0 +1,278 @@
+//! Watching for hubs while scanning.
+//!
+//! `PoweredUp::discover` starts a scan and returns a `Discovery`, a stream
+//! of `DiscoveryEvent`s: each hub is reported `Found` once, then `Updated`
+//! whenever its signal strength changes, and `Lost` once it hasn't been
+//! heard from for a while. A hub that is lost and heard from again is
+//! found again. Hubs stop advertising when connected, so they are reported
+//! lost then.
+//!
+//! Scanning stops when the `Discovery` is stopped or dropped, unless
+//! another scan started through the library is still running.
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use btleplug::api::{Central, CentralEvent, Peripheral as _};
+use btleplug::platform::{Adapter, PeripheralId};
+use core::hash::Hash;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use core::time::Duration;
+use futures::stream::{Stream, StreamExt};
+use std::collections::HashMap;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use crate::error::Result;
+use crate::{
+    identify_hub, scan_stopped, scanfilter, start_scan, stop_scan, targets,
+    DiscoveredHub,
+};
+
+#[cfg(test)]
+mod test;
+
+/// Time after which a hub that hasn't advertised is reported lost
+pub const DEFAULT_LOST_AFTER: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug)]
+pub enum DiscoveryEvent {
+    /// A hub was heard from for the first time, or again after being lost
+    Found(DiscoveredHub),
+    /// The signal strength of a found hub changed, in dBm
+    Updated { addr: PeripheralId, rssi: i16 },
+    /// A found hub hasn't been heard from for the lost timeout
+    Lost(PeripheralId),
+}
+
+/// A running scan, reporting the hubs it finds. Scanning stops when
+/// dropped; use `stop` to wait for the adapter to confirm.
+pub struct Discovery {
+    adapter: Adapter,
+    events: mpsc::UnboundedReceiver<DiscoveryEvent>,
+    task: JoinHandle<()>,
+    scanning: bool,
+}
+
+impl Discovery {
+    pub(crate) async fn start(
+        adapter: Adapter,
+        lost_after: Duration,
+    ) -> Result<Self> {
+        let central_events = adapter.events().await?;
+        start_scan(&adapter, scanfilter()).await?;
+        let (sender, events) = mpsc::unbounded_channel();
+        let task = tokio::spawn(watch(
+            adapter.clone(),
+            central_events,
+            sender,
+            lost_after,
+        ));
+        Ok(Self {
+            adapter,
+            events,
+            task,
+            scanning: true,
+        })
+    }
+
+    /// Stop scanning. Events not yet received are dropped.
+    pub async fn stop(mut self) -> Result<()> {
+        self.scanning = false;
+        self.task.abort();
+        stop_scan(&self.adapter).await
+    }
+}
+
+impl Stream for Discovery {
+    type Item = DiscoveryEvent;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<DiscoveryEvent>> {
+        self.events.poll_recv(cx)
+    }
+}
+
+impl Drop for Discovery {
+    fn drop(&mut self) {
+        self.task.abort();
+        if !self.scanning || !scan_stopped() {
+            return;
+        }
+        // Drop can't wait for the adapter, so leave that to the runtime
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let adapter = self.adapter.clone();
+            runtime.spawn(async move {
+                if let Err(e) = adapter.stop_scan().await {
+                    warn!(target: targets::SCAN, "Failed to stop scanning: {}", e);
+                }
+            });
+        }
+    }
+}
+
+/// Report the hubs heard from in `central_events` until the receiver of
+/// `sender` is gone
+async fn watch(
+    adapter: Adapter,
+    mut central_events: Pin<Box<dyn Stream<Item = CentralEvent> + Send>>,
+    sender: mpsc::UnboundedSender<DiscoveryEvent>,
+    lost_after: Duration,
+) {
+    let mut tracker = Tracker::new(lost_after);
+    let mut check =
+        tokio::time::interval((lost_after / 4).max(Duration::from_millis(10)));
+    loop {
+        let event = tokio::select! {
+            event = central_events.next() => match event {
+                Some(event) => event,
+                None => break,
+            },
+            _ = check.tick() => {
+                for addr in tracker.expire(Instant::now()) {
+                    if sender.send(DiscoveryEvent::Lost(addr)).is_err() {
+                        return;
+                    }
+                }
+                continue;
+            }
+        };
+        let Some(id) = advertiser(event) else {
+            continue;
+        };
+        let Ok(peripheral) = adapter.peripheral(&id).await else {
+            continue;
+        };
+        let Ok(Some(props)) = peripheral.properties().await else {
+            continue;
+        };
+        let event = match tracker.heard(id.clone(), props.rssi, Instant::now())
+        {
+            Heard::Again => continue,
+            Heard::Rssi(rssi) => DiscoveryEvent::Updated { addr: id, rssi },
+            Heard::New => {
+                // Retried whenever heard from, as the first advertisement
+                // may lack the data identifying the hub
+                let Ok(Some(hub_type)) = identify_hub(&props).await else {
+                    continue;
+                };
+                tracker.found(&id);
+                debug!(target: targets::SCAN, "{:?}", props);
+                DiscoveryEvent::Found(DiscoveredHub {
+                    hub_type,
+                    addr: id,
+                    name: props
+                        .local_name
+                        .unwrap_or_else(|| "unknown".to_string()),
+                })
+            }
+        };
+        if sender.send(event).is_err() {
+            return;
+        }
+    }
+}
+
+/// The peripheral a central event shows to be advertising, if any
+fn advertiser(event: CentralEvent) -> Option<PeripheralId> {
+    use CentralEvent::*;
+    match event {
+        DeviceDiscovered(id)
+        | DeviceUpdated(id)
+        | ManufacturerDataAdvertisement { id, .. }
+        | ServiceDataAdvertisement { id, .. }
+        | ServicesAdvertisement { id, .. } => Some(id),
+        _ => None,
+    }
+}
+
+/// What hearing from a peripheral means for the discovery
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Heard {
+    /// Not identified as a hub yet: not heard from before, not since it
+    /// was lost, or not from a hub so far
+    New,
+    /// Its signal strength changed
+    Rssi(i16),
+    /// Nothing to report
+    Again,
+}
+
+#[derive(Debug)]
+struct Seen {
+    last: Instant,
+    rssi: Option<i16>,
+    hub: bool,
+}
+
+/// When peripherals were last heard from, their signal strength and
+/// whether they are hubs
+#[derive(Debug)]
+struct Tracker<Id> {
+    lost_after: Duration,
+    seen: HashMap<Id, Seen>,
+}
+
+impl<Id: Eq + Hash + Clone> Tracker<Id> {
+    fn new(lost_after: Duration) -> Self {
+        Self {
+            lost_after,
+            seen: HashMap::new(),
+        }
+    }
+
+    fn heard(&mut self, id: Id, rssi: Option<i16>, now: Instant) -> Heard {
+        let Some(seen) = self.seen.get_mut(&id) else {
+            self.seen.insert(
+                id,
+                Seen {
+                    last: now,
+                    rssi,
+                    hub: false,
+                },
+            );
+            return Heard::New;
+        };
+        seen.last = now;
+        if !seen.hub {
+            seen.rssi = rssi.or(seen.rssi);
+            return Heard::New;
+        }
+        match rssi {
+            Some(rssi) if seen.rssi != Some(rssi) => {
+                seen.rssi = Some(rssi);
+                Heard::Rssi(rssi)
+            }
+            _ => Heard::Again,
+        }
+    }
+
+    /// Report on a peripheral identified as a hub until it expires
+    fn found(&mut self, id: &Id) {
+        if let Some(seen) = self.seen.get_mut(id) {
+            seen.hub = true;
+        }
+    }
+
+    /// Forget the peripherals not heard from for the lost timeout,
+    /// returning the hubs among them
+    fn expire(&mut self, now: Instant) -> Vec<Id> {
+        let expired: Vec<Id> = self
+            .seen
+            .iter()
+            .filter(|(_, seen)| {
+                now.duration_since(seen.last) >= self.lost_after
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+        expired
+            .into_iter()
+            .filter(|id| self.seen.remove(id).is_some_and(|seen| seen.hub))
+            .collect()
+    }
+}
@@ -0,0 +1,65 @@
+use super::*;
+
+const LOST_AFTER: Duration = Duration::from_secs(10);
+
+#[test]
+fn hubs_are_found_once_and_updated_on_rssi_changes() {
+    let start = Instant::now();
+    let mut tracker = Tracker::new(LOST_AFTER);
+    assert_eq!(tracker.heard(1, Some(-60), start), Heard::New);
+    tracker.found(&1);
+    assert_eq!(tracker.heard(2, None, start), Heard::New);
+    tracker.found(&2);
+    assert_eq!(tracker.heard(1, Some(-60), start), Heard::Again);
+    assert_eq!(tracker.heard(1, Some(-55), start), Heard::Rssi(-55));
+    // An advertisement without a strength keeps the last one
+    assert_eq!(tracker.heard(1, None, start), Heard::Again);
+    assert_eq!(tracker.heard(1, Some(-55), start), Heard::Again);
+    assert_eq!(tracker.heard(2, Some(-70), start), Heard::Rssi(-70));
+}
+
+#[test]
+fn hubs_not_heard_from_are_lost_and_found_again() {
+    let start = Instant::now();
+    let mut tracker = Tracker::new(LOST_AFTER);
+    tracker.heard(1, Some(-60), start);
+    tracker.found(&1);
+    tracker.heard(2, Some(-60), start);
+    tracker.found(&2);
+    assert!(tracker.expire(start + LOST_AFTER / 2).is_empty());
+
+    tracker.heard(2, Some(-60), start + LOST_AFTER / 2);
+    assert_eq!(tracker.expire(start + LOST_AFTER), [1]);
+    assert!(tracker.expire(start + LOST_AFTER).is_empty());
+    assert_eq!(tracker.expire(start + LOST_AFTER * 2), [2]);
+
+    assert_eq!(
+        tracker.heard(1, Some(-60), start + LOST_AFTER * 2),
+        Heard::New
+    );
+}
+
+#[test]
+fn non_hubs_are_identified_again_and_never_lost() {
+    let start = Instant::now();
+    let mut tracker = Tracker::new(LOST_AFTER);
+    assert_eq!(tracker.heard(1, Some(-60), start), Heard::New);
+    let later = start + LOST_AFTER / 2;
+    assert_eq!(tracker.heard(1, Some(-50), later), Heard::New);
+    assert!(tracker.expire(start + LOST_AFTER).is_empty());
+    assert!(tracker.expire(later + LOST_AFTER).is_empty());
+    assert!(tracker.seen.is_empty());
+}
+
+#[test]
+fn peripherals_become_hubs_once_identified() {
+    let start = Instant::now();
+    let mut tracker = Tracker::new(LOST_AFTER);
+    // The first advertisement lacks the manufacturer data
+    assert_eq!(tracker.heard(1, Some(-60), start), Heard::New);
+    assert_eq!(tracker.heard(1, None, start), Heard::New);
+    tracker.found(&1);
+    assert_eq!(tracker.heard(1, Some(-60), start), Heard::Again);
+    assert_eq!(tracker.heard(1, Some(-65), start), Heard::Rssi(-65));
+    assert_eq!(tracker.expire(start + LOST_AFTER), [1]);
+}
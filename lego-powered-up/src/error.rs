@@ -37,6 +37,6 @@ impl<T> OptionContext<T> for Option<T> {
 
 impl<T> OptionContext<T> for Result<T> {
     fn context<D: Display>(self, _ctx: D) -> Result<T> {
-        self.map_err(Error::from)
+        self
     }
 }
@@ -18,28 +18,30 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use btleplug::api::{Characteristic, Peripheral as _, WriteType};
-use btleplug::platform::Peripheral;
 use std::collections::BTreeMap;
 use std::fmt::Debug;
 use std::sync::Arc;
 use tokio_util::sync::CancellationToken;
 
 use crate::consts::{HubPropertyOperation, HubPropertyRef, HubType};
-use crate::error::{Error, OptionContext, Result};
+use crate::error::{Error, Result};
 use crate::notifications::{
     AlertOperation, AlertPayload, AlertType, ErrorMessageFormat, HubAction,
     HubActionRequest, HubAlert, HubProperty, HubPropertyValue,
     InformationRequest, InformationType, InputSetupSingle,
     ModeInformationRequest, ModeInformationType, NetworkCommand,
     NotificationMessage, PortOutputCommandFeedbackFormat,
-    PortValueCombinedFormat, PortValueSingleFormat,
+    PortValueCombinedFormat, PortValueSingleFormat, VirtualPortSetupFormat,
 };
+use crate::transport::Transport;
 use crate::{IoDevice, IoTypeId};
-pub type Tokens = Arc<(Peripheral, Characteristic)>;
+pub type Tokens = Arc<dyn Transport>;
 
 pub mod generic_hub;
 pub mod io_event;
+pub mod properties;
+pub mod readiness;
+pub mod wedo2_hub;
 
 /// Trait describing a generic hub.
 #[async_trait::async_trait]
@@ -51,20 +53,25 @@ pub trait Hub: Debug + Send + Sync {
     // The init function cannot be a trait method until we have GAT :(
     //fn init(peripheral: P);
     fn properties(&self) -> &HubProperties;
+    fn properties_mut(&mut self) -> &mut HubProperties;
     fn kind(&self) -> HubType;
     fn connected_io(&self) -> &BTreeMap<u8, IoDevice>;
     fn connected_io_mut(&mut self) -> &mut BTreeMap<u8, IoDevice>;
     fn channels(&mut self) -> &mut crate::hubs::Channels;
     // fn detach_io(&mut self, ) -> Result<()>;
-    async fn subscribe(&self, char: Characteristic) -> Result<()>;
     fn io_from_port(&self, port_id: u8) -> Result<IoDevice>;
     fn io_from_kind(&self, kind: IoTypeId) -> Result<IoDevice>;
     fn io_multi_from_kind(&self, kind: IoTypeId) -> Result<Vec<IoDevice>>;
 
     fn tokens(&self) -> Tokens;
     fn attach_io(&mut self, io_type_id: IoTypeId, port_id: u8) -> Result<()>;
-    fn peripheral(&self) -> Arc<Peripheral>;
-    fn characteristic(&self) -> Arc<Characteristic>;
+    fn attach_virtual_io(
+        &mut self,
+        io_type_id: IoTypeId,
+        port_id: u8,
+        port_a: u8,
+        port_b: u8,
+    ) -> Result<()>;
     fn device_cache(&self, d: IoDevice) -> IoDevice;
     fn cancel_token(&self) -> CancellationToken;
 
@@ -123,11 +130,29 @@ pub trait Hub: Debug + Send + Sync {
         let msg = NotificationMessage::HubProperties(HubProperty {
             reference,
             operation,
-            property: HubPropertyValue::SecondaryMacAddress, // Not used in request
+            property: None,
         });
         self.send(msg).await
     }
 
+    /// Ask the hub to combine two ports into a virtual port. The hub
+    /// reports the new port as attached; ConnectedHub::create_virtual_port
+    /// waits for that.
+    async fn connect_virtual_port(&self, port_a: u8, port_b: u8) -> Result<()> {
+        let msg = NotificationMessage::VirtualPortSetup(
+            VirtualPortSetupFormat::Connect { port_a, port_b },
+        );
+        self.send(msg).await
+    }
+
+    /// Split a virtual port back into its ports
+    async fn disconnect_virtual_port(&self, port_id: u8) -> Result<()> {
+        let msg = NotificationMessage::VirtualPortSetup(
+            VirtualPortSetupFormat::Disconnect { port_id },
+        );
+        self.send(msg).await
+    }
+
     /// Perform Hub actions
     async fn hub_action(&self, action_type: HubAction) -> Result<()> {
         let msg =
@@ -150,17 +175,12 @@ pub trait Hub: Debug + Send + Sync {
     }
 
     async fn send(&self, msg: NotificationMessage) -> Result<()> {
-        let buf = msg.serialise();
-        let tokens = self.tokens();
-        tokens
-            .0
-            .write(&tokens.1, &buf, WriteType::WithoutResponse)
-            .await?;
-        Ok(())
+        send(self.tokens(), msg).await
     }
 
-    // Cannot provide a default implementation without access to the Peripheral trait from here
-    async fn send_raw(&self, msg: &[u8]) -> Result<()>;
+    async fn send_raw(&self, msg: &[u8]) -> Result<()> {
+        self.tokens().write(msg).await
+    }
 }
 
 pub type VersionNumber = u8;
@@ -179,16 +199,14 @@ pub struct HubProperties {
     pub battery_level: usize,
     /// BLE signal strength
     pub rssi: i16,
+    /// Hub button pressed
+    pub button: bool,
 }
 
 /// Devices can use this with cached tokens and not need to mutex-lock hub
 pub async fn send(tokens: Tokens, msg: NotificationMessage) -> Result<()> {
     let buf = msg.serialise();
-    tokens
-        .0
-        .write(&tokens.1, &buf, WriteType::WithoutResponse)
-        .await?;
-    Ok(())
+    tokens.write(&buf).await
 }
 
 #[derive(Debug, Default, Clone)]
@@ -199,16 +217,41 @@ pub struct Channels {
         Option<tokio::sync::broadcast::Sender<PortValueCombinedFormat>>,
     pub networkcmd_sender:
         Option<tokio::sync::broadcast::Sender<NetworkCommand>>,
-    pub hubnotification_sender:
-        Option<tokio::sync::broadcast::Sender<HubNotification>>,
+    pub hubevent_sender: Option<tokio::sync::broadcast::Sender<HubEvent>>,
     pub commandfeedback_sender:
         Option<tokio::sync::broadcast::Sender<PortOutputCommandFeedbackFormat>>,
 }
 
-#[derive(Debug, Default, Clone)]
-pub struct HubNotification {
-    pub hub_property: Option<HubProperty>,
-    pub hub_action: Option<HubActionRequest>,
-    pub hub_alert: Option<HubAlert>,
-    pub hub_error: Option<ErrorMessageFormat>,
+/// Something that happened on the hub, cf. `ConnectedHub::events`
+#[derive(Debug, Clone, PartialEq)]
+pub enum HubEvent {
+    /// The hub reported a property value
+    Property(HubPropertyValue),
+    /// The hub button was pressed or released. Also reported as a `Button`
+    /// property.
+    Button { pressed: bool },
+    /// The hub is about to act, e.g. `HubWillSwitchOff`
+    Action(HubAction),
+    /// The hub raised or cleared an alert
+    Alert {
+        alert_type: AlertType,
+        payload: AlertPayload,
+    },
+    /// The hub rejected a command
+    Error(ErrorMessageFormat),
+    /// A device was plugged in
+    Attached { port_id: u8, io_type_id: IoTypeId },
+    /// The hub combined two ports into a virtual port
+    AttachedVirtual {
+        port_id: u8,
+        io_type_id: IoTypeId,
+        port_a: u8,
+        port_b: u8,
+    },
+    /// A device was unplugged. `io_type_id` is the kind of device that was
+    /// attached, if it was known.
+    Detached {
+        port_id: u8,
+        io_type_id: Option<IoTypeId>,
+    },
 }
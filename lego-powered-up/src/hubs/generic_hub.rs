@@ -14,8 +14,8 @@
 use tokio_util::sync::CancellationToken;
 
 use super::*;
+use crate::transport::ResumableTransport;
 use std::collections::BTreeMap;
-use std::sync::Arc;
 
 #[derive(Debug)]
 pub struct GenericHub {
@@ -30,23 +30,13 @@ pub struct GenericHub {
 #[async_trait::async_trait]
 impl Hub for GenericHub {
     async fn name(&self) -> Result<String> {
-        Ok(self
-            .tokens
-            .0 //peripheral
-            .properties()
-            .await?
-            .context("No properties found for hub")?
-            .local_name
-            .unwrap_or_default())
+        Ok(self.tokens.properties().await?.name)
     }
     fn properties(&self) -> &HubProperties {
         &self.properties
     }
-    fn peripheral(&self) -> Arc<Peripheral> {
-        Arc::new(self.tokens.0.clone())
-    }
-    fn characteristic(&self) -> Arc<Characteristic> {
-        Arc::new(self.tokens.1.clone())
+    fn properties_mut(&mut self) -> &mut HubProperties {
+        &mut self.properties
     }
     fn connected_io(&self) -> &BTreeMap<u8, IoDevice> {
         &self.connected_io
@@ -64,25 +54,18 @@ impl Hub for GenericHub {
     async fn disconnect(&self) -> Result<()> {
         if self.is_connected().await? {
             self.cancel.cancel();
-            self.tokens.0.disconnect().await?;
+            self.tokens.disconnect().await?;
         }
         Ok(())
     }
     async fn is_connected(&self) -> Result<bool> {
-        Ok(self.tokens.0.is_connected().await?)
+        self.tokens.is_connected().await
     }
     async fn shutdown(&self) -> Result<()> {
         self.cancel.cancel();
         self.hub_action(crate::notifications::HubAction::SwitchOffHub)
             .await
     }
-    async fn send_raw(&self, msg: &[u8]) -> Result<()> {
-        let write_type = WriteType::WithoutResponse;
-        Ok(self.tokens.0.write(&self.tokens.1, msg, write_type).await?)
-    }
-    async fn subscribe(&self, char: Characteristic) -> Result<()> {
-        Ok(self.tokens.0.subscribe(&char).await?)
-    }
     fn cancel_token(&self) -> CancellationToken {
         self.cancel.clone()
     }
@@ -97,6 +80,25 @@ impl Hub for GenericHub {
         Ok(())
     }
 
+    fn attach_virtual_io(
+        &mut self,
+        io_type_id: IoTypeId,
+        port_id: u8,
+        port_a: u8,
+        port_b: u8,
+    ) -> Result<()> {
+        let device = IoDevice::new_virtual(
+            io_type_id,
+            port_id,
+            port_a,
+            port_b,
+            self.tokens.clone(),
+        );
+        self.connected_io.insert(port_id, device);
+
+        Ok(())
+    }
+
     /// Cache handles held by hub on device so we don't need to lock hub mutex as often    
     fn device_cache(&self, mut d: IoDevice) -> IoDevice {
         // Channels that forward some notification message types
@@ -177,27 +179,15 @@ impl Hub for GenericHub {
 impl GenericHub {
     /// Initialisation method
     pub async fn init(
-        peripheral: Peripheral,
-        lpf_characteristic: Characteristic,
+        transport: Tokens,
         kind: crate::consts::HubType,
         cancel: CancellationToken,
     ) -> Result<Self> {
-        // Peripheral is already connected before we get here
-
-        let props = peripheral
-            .properties()
-            .await?
-            .context("No properties found for hub")?;
-
-        let properties = HubProperties {
-            mac_address: props.address.to_string(),
-            name: props.local_name.unwrap_or_default(),
-            rssi: props.tx_power_level.unwrap_or_default(),
-            ..Default::default()
-        };
+        // Transport is already connected before we get here
+        let properties = transport.properties().await?;
 
         Ok(Self {
-            tokens: Arc::new((peripheral, lpf_characteristic)),
+            tokens: Arc::new(ResumableTransport::new(transport)),
             properties,
             connected_io: Default::default(),
             kind,
@@ -2,69 +2,40 @@
 //! notification module. The 3 main messagetypes for
 //! device comms are forwarded to channels for devices
 //! to subscribe. Device info messagetypes are handed
-//! to iodevice::definition mod. The rest are available
-//! as error messages for now.
+//! to iodevice::definition mod. Hub properties, actions,
+//! alerts, errors and attach/detach are sent as HubEvents.
 
-use core::pin::Pin;
-use futures::stream::{Stream, StreamExt};
+use futures::stream::StreamExt;
+use tracing::{Instrument, Span};
 
-use btleplug::api::ValueNotification;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tokio_util::sync::CancellationToken;
 
+use crate::consts::HubPropertyOperation;
 use crate::error::Result;
-use crate::hubs::HubNotification;
+use crate::hubs::HubEvent;
 use crate::notifications::*;
+use crate::targets;
+use crate::transport::FrameStream;
 
+use super::properties::HubPropertyChannels;
+use super::readiness::{InfoRequest, Readiness, FENCE_PROPERTY};
 use super::Channels;
 
 type HubMutex = Arc<Mutex<Box<dyn crate::Hub>>>;
-type PinnedStream = Pin<Box<dyn Stream<Item = ValueNotification> + Send>>;
-
-pub struct Verbosity {
-    attached: bool,
-    _hub: bool,
-    _input: bool,
-    _output: bool,
-    _values: bool,
-}
-impl Verbosity {
-    pub fn new() -> Self {
-        Self {
-            attached: true,
-            _hub: false,
-            _input: false,
-            _output: true,
-            _values: false,
-        }
-    }
-    pub fn set(
-        &mut self,
-        attached: bool,
-        _hub: bool,
-        _input: bool,
-        _output: bool,
-        _values: bool,
-    ) {
-        self.attached = attached;
-    }
-}
-impl Default for Verbosity {
-    fn default() -> Self {
-        Self::new()
-    }
-}
 
 pub async fn io_event_handler(
-    mut stream: PinnedStream,
+    mut stream: FrameStream,
     mutex: HubMutex,
     senders: Channels,
+    readiness: Readiness,
+    properties: HubPropertyChannels,
     cancel: CancellationToken,
 ) -> Result<()> {
     if senders.networkcmd_sender.is_none()
         | senders.combinedvalue_sender.is_none()
-        | senders.hubnotification_sender.is_none()
+        | senders.hubevent_sender.is_none()
         | senders.singlevalue_sender.is_none()
         | senders.commandfeedback_sender.is_none()
     {
@@ -72,38 +43,38 @@ pub async fn io_event_handler(
     }
     let combinedvalue_sender = senders.combinedvalue_sender.unwrap();
     let commandfeedback_sender = senders.commandfeedback_sender.unwrap();
-    let hubnotification_sender = senders.hubnotification_sender.unwrap();
+    let hubevent_sender = senders.hubevent_sender.unwrap();
     let networkcmd_sender = senders.networkcmd_sender.unwrap();
     let singlevalue_sender = senders.singlevalue_sender.unwrap();
 
-    // Verbosity
-    const ATTACHED: bool = true;
-    const HUB: bool = false;
-    const INPUT: bool = false;
-    const OUTPUT: bool = true;
-    const _VALUES: bool = false;
     loop {
         tokio::select! {
             _ = cancel.cancelled() => {
                 break;
             }
 
-            Some(data) = stream.next() => {
-            let n = match NotificationMessage::parse(&data.value) {
+            data = stream.next() => {
+            // The stream ends when the link to the hub is lost
+            let Some(data) = data else {
+                break;
+            };
+            let n = match NotificationMessage::parse(&data) {
                 Ok(n) => n,
                 Err(e) => {
-                    eprintln!("Parse error: {e}");
+                    warn!(target: targets::HUB, "Parse error: {e}");
                     continue;
                 }
             };
 
+            let span = port_span(&n);
+            async {
             match n {
                 // Forwarded
                 NotificationMessage::PortValueSingle(val) => {
                     match singlevalue_sender.send(val) {
                         Ok(_) => (),
                         Err(e) => {
-                            eprintln!("No receiver? Error forwarding PortValueSingle: {:?}", e);
+                            trace!(target: targets::INPUT, "No receiver for PortValueSingle: {:?}", e);
                         }
                     }
                 }
@@ -111,29 +82,27 @@ pub async fn io_event_handler(
                     match combinedvalue_sender.send(val) {
                         Ok(_) => (),
                         Err(e) => {
-                            eprintln!("No receiver? Error forwarding PortValueCombined: {:?}", e);
+                            trace!(target: targets::INPUT, "No receiver for PortValueCombined: {:?}", e);
                         }
                     }
                 }
-                NotificationMessage::HwNetworkCommands(val) => {
-                    if networkcmd_sender.receiver_count() > 0 {
-                        match networkcmd_sender.send(val) {
-                            Ok(_) => (),
-                            Err(e) => {
-                                eprintln!("No receiver? Error forwarding HwNetworkCommands: {:?}", e);
-                            }
+                NotificationMessage::HwNetworkCommands(val)
+                    if networkcmd_sender.receiver_count() > 0 =>
+                {
+                    match networkcmd_sender.send(val) {
+                        Ok(_) => (),
+                        Err(e) => {
+                            trace!(target: targets::INPUT, "No receiver for HwNetworkCommands: {:?}", e);
                         }
                     }
                 }
                 NotificationMessage::PortOutputCommandFeedback(val) => {
-                    if OUTPUT {
-                        eprintln!("{:?}", val);
-                    }
+                    debug!(target: targets::OUTPUT, "{:?}", val);
                     if commandfeedback_sender.receiver_count() > 0 {
                         match commandfeedback_sender.send(val) {
                             Ok(_) => (),
                             Err(e) => {
-                                eprintln!("No receiver? Error forwarding PortOutputCommandFeedback: {:?}", e);
+                                trace!(target: targets::INPUT, "No receiver for PortOutputCommandFeedback: {:?}", e);
                             }
                         }
                     }
@@ -152,51 +121,60 @@ pub async fn io_event_handler(
                             } => {
                                 {
                                     let mut hub = mutex.lock().await;
+                                    readiness.forget_port(port_id);
                                     hub.attach_io(io_type_id, port_id)?;
-                                    hub.request_port_info(
-                                        port_id,
-                                        InformationType::ModeInfo,
+                                    request_info(
+                                        &**hub,
+                                        &readiness,
+                                        mode_info_request(port_id),
                                     )
                                     .await?;
                                 }
-                                if ATTACHED {
-                                    eprintln!(
-                                        "AttachedIo: {:?} {:?}",
-                                        port_id, event
-                                    );
-                                }
+                                info!(target: targets::ATTACHED, "AttachedIo: {:?}", event);
+                                let _ = hubevent_sender.send(HubEvent::Attached {
+                                    port_id,
+                                    io_type_id,
+                                });
                             }
                             IoAttachEvent::DetachedIo {} => {
-                                {
+                                let io_type_id = {
                                     let mut hub = mutex.lock().await;
-                                    hub.connected_io_mut().remove(&port_id);
-                                }
-                                if ATTACHED {
-                                    eprintln!(
-                                        "DetachedIo: {:?} {:?}",
-                                        port_id, event
-                                    );
-                                }
+                                    readiness.forget_port(port_id);
+                                    hub.connected_io_mut()
+                                        .remove(&port_id)
+                                        .map(|device| *device.kind())
+                                };
+                                info!(target: targets::ATTACHED, "DetachedIo: {:?}", event);
+                                let _ = hubevent_sender.send(HubEvent::Detached {
+                                    port_id,
+                                    io_type_id,
+                                });
                             }
                             IoAttachEvent::AttachedVirtualIo {
                                 io_type_id,
-                                port_a:_,
-                                port_b:_,
+                                port_a,
+                                port_b,
                             } => {
                                 {
                                     let mut hub = mutex.lock().await;
-                                    hub.attach_io(io_type_id, port_id)?;
-                                    hub.request_port_info(
-                                        port_id,
-                                        InformationType::ModeInfo,
-                                    ).await?;
-                                }
-                                if ATTACHED {
-                                    eprintln!(
-                                        "AttachedVirtualIo: {:?} {:?}",
-                                        port_id, event
-                                    );
+                                    readiness.forget_port(port_id);
+                                    hub.attach_virtual_io(io_type_id, port_id, port_a, port_b)?;
+                                    request_info(
+                                        &**hub,
+                                        &readiness,
+                                        mode_info_request(port_id),
+                                    )
+                                    .await?;
                                 }
+                                info!(target: targets::ATTACHED, "AttachedVirtualIo: {:?}", event);
+                                let _ = hubevent_sender.send(
+                                    HubEvent::AttachedVirtual {
+                                        port_id,
+                                        io_type_id,
+                                        port_a,
+                                        port_b,
+                                    },
+                                );
                             }
                         }
                     }
@@ -206,37 +184,46 @@ pub async fn io_event_handler(
                         port_id,
                         information_type,
                     } = val;
-                    {
-                        match information_type {
-                                PortInformationType::ModeInfo{capabilities, mode_count, input_modes, output_modes} => {
-                                    {
-                                        let mut hub = mutex.lock().await;
-                                        let device = hub.connected_io_mut().get_mut(&port_id).unwrap();
-                                        device.def.set_mode_count(mode_count);
-                                        device.def.set_capabilities(capabilities.0);
-                                        device.def.set_modes(input_modes, output_modes);
+                    let information_type_answered = information_type.information_type();
+                    let mut hub = mutex.lock().await;
+                    let Some(device) = hub.connected_io_mut().get_mut(&port_id) else {
+                        warn!(target: targets::ATTACHED, "PortInformation for unknown port");
+                        return Ok(());
+                    };
+                    match information_type {
+                        PortInformationType::ModeInfo{capabilities, mode_count, input_modes, output_modes} => {
+                            device.def.set_mode_count(mode_count);
+                            device.def.set_capabilities(capabilities.0);
+                            device.def.set_modes(input_modes, output_modes);
 
-                                        // Req combinations if capability LogicalCombinable
-                                        if (capabilities.0 >> 2) & 1 == 1  {
-                                            hub.request_port_info(port_id, InformationType::PossibleModeCombinations).await?;
-                                        }
+                            // Req combinations if capability LogicalCombinable
+                            if (capabilities.0 >> 2) & 1 == 1  {
+                                let request = InfoRequest::Port {
+                                    port_id,
+                                    information_type: InformationType::PossibleModeCombinations,
+                                };
+                                request_info(&**hub, &readiness, request).await?;
+                            }
 
-                                        for mode_id in 0..mode_count {
-                                            hub.req_mode_info(port_id, mode_id, ModeInformationType::Name).await?;
-                                            hub.req_mode_info(port_id, mode_id, ModeInformationType::Raw).await?;
-                                            hub.req_mode_info(port_id, mode_id, ModeInformationType::Pct).await?;
-                                            hub.req_mode_info(port_id, mode_id, ModeInformationType::Si).await?;
-                                            hub.req_mode_info(port_id, mode_id, ModeInformationType::Symbol).await?;
-                                            hub.req_mode_info(port_id, mode_id, ModeInformationType::Mapping).await?;
-                                            hub.req_mode_info(port_id, mode_id, ModeInformationType::ValueFormat).await?;
-                                        }
-                                    }
+                            for mode in 0..mode_count {
+                                for information_type in MODE_INFO_REQUESTS {
+                                    let request = InfoRequest::Mode {
+                                        port_id,
+                                        mode,
+                                        information_type,
+                                    };
+                                    request_info(&**hub, &readiness, request).await?;
                                 }
-                                PortInformationType::PossibleModeCombinations(combs) => {
-                                    let mut hub = mutex.lock().await;
-                                    hub.connected_io_mut().get_mut(&port_id).unwrap().def.set_valid_combos(combs);                                    }
                             }
+                        }
+                        PortInformationType::PossibleModeCombinations(combs) => {
+                            device.def.set_valid_combos(combs);
+                        }
                     }
+                    readiness.answered(InfoRequest::Port {
+                        port_id,
+                        information_type: information_type_answered,
+                    });
                 }
                 NotificationMessage::PortModeInformation(val) => {
                     let PortModeInformationValue {
@@ -244,73 +231,44 @@ pub async fn io_event_handler(
                         mode,
                         information_type,
                     } = val;
+                    let mut hub = mutex.lock().await;
+                    let Some(device) = hub.connected_io_mut().get_mut(&port_id) else {
+                        warn!(target: targets::ATTACHED, "PortModeInformation for unknown port");
+                        return Ok(());
+                    };
+                    readiness.answered(InfoRequest::Mode {
+                        port_id,
+                        mode,
+                        information_type: information_type.information_type(),
+                    });
+                    let def = &mut device.def;
                     match information_type {
                         PortModeInformationType::Name(name) => {
-                            let mut hub = mutex.lock().await;
-                            hub.connected_io_mut()
-                                .get_mut(&port_id)
-                                .unwrap()
-                                .def
-                                .set_mode_name(mode, name);
+                            def.set_mode_name(mode, name);
                         }
                         PortModeInformationType::RawRange { min, max } => {
-                            let mut hub = mutex.lock().await;
-                            hub.connected_io_mut()
-                                .get_mut(&port_id)
-                                .unwrap()
-                                .def
-                                .set_mode_raw(mode, min, max);
+                            def.set_mode_raw(mode, min, max);
                         }
                         PortModeInformationType::PctRange { min, max } => {
-                            let mut hub = mutex.lock().await;
-                            hub.connected_io_mut()
-                                .get_mut(&port_id)
-                                .unwrap()
-                                .def
-                                .set_mode_pct(mode, min, max);
+                            def.set_mode_pct(mode, min, max);
                         }
                         PortModeInformationType::SiRange { min, max } => {
-                            let mut hub = mutex.lock().await;
-                            hub.connected_io_mut()
-                                .get_mut(&port_id)
-                                .unwrap()   // panic
-                                .def
-                                .set_mode_si(mode, min, max);
+                            def.set_mode_si(mode, min, max);
                         }
                         PortModeInformationType::Symbol(symbol) => {
-                            let mut hub = mutex.lock().await;
-                            hub.connected_io_mut()
-                                .get_mut(&port_id)
-                                .unwrap()   // panic
-                                .def
-                                .set_mode_symbol(mode, symbol);
+                            def.set_mode_symbol(mode, symbol);
                         }
                         PortModeInformationType::Mapping {
                             input,
                             output,
                         } => {
-                            let mut hub = mutex.lock().await;
-                            hub.connected_io_mut()
-                                .get_mut(&port_id)
-                                .unwrap()
-                                .def
-                                .set_mode_mapping(mode, input, output);
+                            def.set_mode_mapping(mode, input, output);
                         }
                         PortModeInformationType::MotorBias(bias) => {
-                            let mut hub = mutex.lock().await;
-                            hub.connected_io_mut()
-                                .get_mut(&port_id)
-                                .unwrap()
-                                .def
-                                .set_mode_motor_bias(mode, bias);
+                            def.set_mode_motor_bias(mode, bias);
                         }
                         PortModeInformationType::ValueFormat(format) => {
-                            let mut hub = mutex.lock().await;
-                            hub.connected_io_mut()
-                                .get_mut(&port_id)
-                                .unwrap()
-                                .def
-                                .set_mode_valueformat(mode, format);
+                            def.set_mode_valueformat(mode, format);
                         }
                         _ => (),
                     }
@@ -318,100 +276,109 @@ pub async fn io_event_handler(
 
                 // Forward hub notifications
                 NotificationMessage::HubProperties(val) => {
-                    if HUB {
-                        eprintln!("{:?}", &val);
+                    debug!(target: targets::HUB, "{:?}", &val);
+                    let (HubPropertyOperation::UpdateUpstream, Some(property)) =
+                        (val.operation, val.property)
+                    else {
+                        return Ok(());
+                    };
+                    {
+                        let mut hub = mutex.lock().await;
+                        hub.properties_mut().update(&property);
                     }
-                    match hubnotification_sender.send(HubNotification {
-                        hub_property: Some(val),
-                        hub_action: None,
-                        hub_alert: None,
-                        hub_error: None,
-                    }) {
-                        Ok(_) => (),
-                        Err(e) => {
-                            if !HUB {
-                                eprintln!("No receiver? Error forwarding HubProperties: {:?}", e)
-                            };
-                        }
+                    properties.update(&property);
+                    if val.reference == FENCE_PROPERTY {
+                        readiness.announced();
+                    }
+                    if let HubPropertyValue::Button(state) = property {
+                        let _ = hubevent_sender.send(HubEvent::Button {
+                            pressed: state != 0,
+                        });
                     }
+                    let _ = hubevent_sender.send(HubEvent::Property(property));
                 }
                 NotificationMessage::HubActions(val) => {
-                    if HUB {
-                        eprintln!("{:?}", &val);
-                    }
-                    match hubnotification_sender.send(HubNotification {
-                        hub_property: None,
-                        hub_action: Some(val),
-                        hub_alert: None,
-                        hub_error: None,
-                    }) {
-                        Ok(_) => (),
-                        Err(e) => {
-                            if !HUB {
-                                eprintln!("No receiver? Error forwarding HubActions: {:?}", e)
-                            };
-                        }
-                    }
+                    debug!(target: targets::HUB, "{:?}", &val);
+                    let _ = hubevent_sender.send(HubEvent::Action(val.action_type));
                 }
                 NotificationMessage::HubAlerts(val) => {
-                    if HUB {
-                        eprintln!("{:?}", &val);
-                    }
-                    match hubnotification_sender.send(HubNotification {
-                        hub_property: None,
-                        hub_action: None,
-                        hub_alert: Some(val),
-                        hub_error: None,
-                    }) {
-                        Ok(_) => (),
-                        Err(e) => {
-                            if !HUB {
-                                eprintln!("No receiver? Error forwarding HubAlerts: {:?}", e)
-                            };
-                        }
+                    debug!(target: targets::HUB, "{:?}", &val);
+                    if val.operation == AlertOperation::Update {
+                        let _ = hubevent_sender.send(HubEvent::Alert {
+                            alert_type: val.alert_type,
+                            payload: val.payload,
+                        });
                     }
                 }
                 NotificationMessage::GenericErrorMessages(val) => {
-                    if HUB {
-                        eprintln!("{:?}", &val);
-                    }
-                    match hubnotification_sender.send(HubNotification {
-                        hub_property: None,
-                        hub_action: None,
-                        hub_alert: None,
-                        hub_error: Some(val),
-                    }) {
-                        Ok(_) => (),
-                        Err(e) => {
-                            if !HUB {
-                                eprintln!("No receiver? Error forwarding GenericErrorMessages: {:?}", e)
-                            };
-                        }
-                    }
+                    debug!(target: targets::HUB, "{:?}", &val);
+                    readiness.rejected(val.command_type);
+                    let _ = hubevent_sender.send(HubEvent::Error(val));
                 }
 
                 // Not doing anything with these yet.
                 NotificationMessage::FwLockStatus(val) => {
-                    if HUB {
-                        eprintln!("{:?}", val);
-                    }
+                    debug!(target: targets::HUB, "{:?}", val);
                 }
                 NotificationMessage::PortInputFormatSingle(val) => {
-                    if INPUT {
-                        eprintln!("{:?}", val);
-                    }
+                    debug!(target: targets::INPUT, "{:?}", val);
                 }
                 NotificationMessage::PortInputFormatCombinedmode(val) => {
-                    if INPUT {
-                        eprintln!("{:?}", val);
-                    }
+                    debug!(target: targets::INPUT, "{:?}", val);
                 }
 
                 _ => (),
-
-
-            }}
+            }
+            Ok::<_, crate::Error>(())
+            }
+            .instrument(span)
+            .await?;
+            }
         }
     }
     Ok(())
 }
+
+/// Span for handling a message about a port
+fn port_span(msg: &NotificationMessage) -> Span {
+    use NotificationMessage::*;
+    let port_id = match msg {
+        HubAttachedIo(io) => io.port,
+        PortInformation(info) => info.port_id,
+        PortModeInformation(info) => info.port_id,
+        PortValueSingle(value) => value.port_id,
+        PortValueCombined(value) => value.port_id,
+        PortInputFormatSingle(format) => format.port_id,
+        PortInputFormatCombinedmode(format) => format.port_id,
+        _ => return Span::none(),
+    };
+    debug_span!("port", id = port_id)
+}
+
+/// Mode information requested for every mode of an attached device
+const MODE_INFO_REQUESTS: [ModeInformationType; 7] = [
+    ModeInformationType::Name,
+    ModeInformationType::Raw,
+    ModeInformationType::Pct,
+    ModeInformationType::Si,
+    ModeInformationType::Symbol,
+    ModeInformationType::Mapping,
+    ModeInformationType::ValueFormat,
+];
+
+fn mode_info_request(port_id: u8) -> InfoRequest {
+    InfoRequest::Port {
+        port_id,
+        information_type: InformationType::ModeInfo,
+    }
+}
+
+/// Send a request and note that a reply is outstanding
+async fn request_info(
+    hub: &dyn crate::Hub,
+    readiness: &Readiness,
+    request: InfoRequest,
+) -> Result<()> {
+    readiness.expect(request);
+    request.send(hub).await
+}
@@ -0,0 +1,222 @@
+//! Live hub properties. The hub is asked for its properties at setup, and
+//! sends battery level, signal strength and button state whenever they
+//! change. Each property has a watch channel holding its latest value,
+//! `None` until the hub has reported it.
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use core::time::Duration;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+use super::{Hub, HubProperties};
+use crate::consts::{HubPropertyOperation, HubPropertyRef};
+use crate::error::{Error, Result};
+use crate::notifications::{HubBatteryType, HubPropertyValue, VersionNumber};
+
+/// Properties requested once at setup
+pub const REQUESTED_PROPERTIES: [HubPropertyRef; 8] = [
+    HubPropertyRef::AdvertisingName,
+    HubPropertyRef::FwVersion,
+    HubPropertyRef::HwVersion,
+    HubPropertyRef::PrimaryMacAddress,
+    HubPropertyRef::BatteryType,
+    HubPropertyRef::ManufacturerName,
+    HubPropertyRef::RadioFirmwareVersion,
+    HubPropertyRef::LegoWirelessProtocolVersion,
+];
+
+/// Properties the hub reports whenever they change
+pub const UPDATED_PROPERTIES: [HubPropertyRef; 3] = [
+    HubPropertyRef::BatteryVoltage,
+    HubPropertyRef::Rssi,
+    HubPropertyRef::Button,
+];
+
+/// Ask the hub for its properties and enable updates for those that change
+pub async fn request_properties(hub: &dyn Hub) -> Result<()> {
+    for reference in REQUESTED_PROPERTIES {
+        hub.hub_props(reference, HubPropertyOperation::RequestUpdateDownstream)
+            .await?;
+    }
+    for reference in UPDATED_PROPERTIES {
+        hub.hub_props(reference, HubPropertyOperation::EnableUpdatesDownstream)
+            .await?;
+    }
+    Ok(())
+}
+
+macro_rules! property_channels {
+    ($($getter:ident / $watcher:ident: $ty:ty, $doc:literal;)*) => {
+        #[derive(Debug)]
+        struct Senders {
+            $($getter: watch::Sender<Option<$ty>>,)*
+        }
+
+        impl Default for Senders {
+            fn default() -> Self {
+                Self {
+                    $($getter: watch::channel(None).0,)*
+                }
+            }
+        }
+
+        impl HubPropertyChannels {
+            $(
+                #[doc = $doc]
+                #[doc = ""]
+                #[doc = "Waits for the hub to report it if it hasn't yet."]
+                pub async fn $getter(&self) -> Result<$ty> {
+                    self.get(self.senders.$getter.subscribe(), stringify!($getter))
+                        .await
+                }
+
+                #[doc = $doc]
+                #[doc = ""]
+                #[doc = "`None` until the hub has reported it."]
+                pub fn $watcher(&self) -> watch::Receiver<Option<$ty>> {
+                    self.senders.$getter.subscribe()
+                }
+            )*
+        }
+    };
+}
+
+property_channels! {
+    name / watch_name: String, "Advertised name";
+    fw_version / watch_fw_version: String, "Firmware version";
+    hw_version / watch_hw_version: String, "Hardware version";
+    mac_address / watch_mac_address: String, "Primary MAC address";
+    battery_level / watch_battery_level: usize, "Battery level in percent";
+    battery_type / watch_battery_type: HubBatteryType, "Battery type";
+    rssi / watch_rssi: i16, "Signal strength in dBm";
+    button / watch_button: bool, "Whether the hub button is pressed";
+    manufacturer_name / watch_manufacturer_name: String, "Manufacturer";
+    radio_fw_version / watch_radio_fw_version: String,
+        "Radio firmware version";
+    lwp_version / watch_lwp_version: u16,
+        "LEGO Wireless Protocol version, BCD coded";
+}
+
+/// Watch channels holding the latest value of each hub property
+#[derive(Debug, Clone)]
+pub struct HubPropertyChannels {
+    senders: Arc<Senders>,
+    timeout: Duration,
+}
+
+impl HubPropertyChannels {
+    /// Getters wait at most `timeout` for a property to be reported
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            senders: Default::default(),
+            timeout,
+        }
+    }
+
+    /// Store a value reported by the hub
+    pub fn update(&self, value: &HubPropertyValue) {
+        use HubPropertyValue::*;
+        let s = &self.senders;
+        match value {
+            AdvertisingName(name) => {
+                s.name.send_replace(Some(text(name)));
+            }
+            FwVersion(v) => {
+                s.fw_version.send_replace(Some(version(*v)));
+            }
+            HwVersion(v) => {
+                s.hw_version.send_replace(Some(version(*v)));
+            }
+            PrimaryMacAddress(mac) => {
+                s.mac_address.send_replace(Some(mac_address(mac)));
+            }
+            BatteryVoltage(level) => {
+                s.battery_level.send_replace(Some(*level as usize));
+            }
+            BatteryType(kind) => {
+                s.battery_type.send_replace(Some(*kind));
+            }
+            Rssi(rssi) => {
+                s.rssi.send_replace(Some(*rssi as i16));
+            }
+            Button(state) => {
+                s.button.send_replace(Some(*state != 0));
+            }
+            ManufacturerName(name) => {
+                s.manufacturer_name.send_replace(Some(text(name)));
+            }
+            RadioFirmwareVersion(v) => {
+                s.radio_fw_version.send_replace(Some(text(v)));
+            }
+            LegoWirelessProtocolVersion(v) => {
+                s.lwp_version.send_replace(Some(*v));
+            }
+            SystemTypeId(_)
+            | HwNetworkId(_)
+            | SecondaryMacAddress
+            | HardwareNetworkFamily(_) => (),
+        }
+    }
+
+    async fn get<T: Clone>(
+        &self,
+        mut rx: watch::Receiver<Option<T>>,
+        property: &str,
+    ) -> Result<T> {
+        let value = tokio::time::timeout(self.timeout, async {
+            rx.wait_for(Option::is_some).await.map(|v| v.clone())
+        })
+        .await;
+        match value {
+            Ok(Ok(Some(value))) => Ok(value),
+            Ok(_) => Err(Error::HubError(format!(
+                "Hub property {} no longer tracked",
+                property
+            ))),
+            Err(_) => Err(Error::TimeoutError(format!(
+                "Hub didn't report {} within {:?}",
+                property, self.timeout
+            ))),
+        }
+    }
+}
+
+impl HubProperties {
+    /// Store a value reported by the hub
+    pub fn update(&mut self, value: &HubPropertyValue) {
+        use HubPropertyValue::*;
+        match value {
+            AdvertisingName(name) => self.name = text(name),
+            FwVersion(v) => self.fw_version = version(*v),
+            HwVersion(v) => self.hw_version = version(*v),
+            PrimaryMacAddress(mac) => self.mac_address = mac_address(mac),
+            BatteryVoltage(level) => self.battery_level = *level as usize,
+            Rssi(rssi) => self.rssi = *rssi as i16,
+            Button(state) => self.button = *state != 0,
+            _ => (),
+        }
+    }
+}
+
+fn text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes)
+        .trim_end_matches('\0')
+        .to_string()
+}
+
+/// Version numbers are packed as in attached IO messages
+fn version(v: i32) -> String {
+    VersionNumber::parse(v.to_le_bytes().iter())
+        .map(|v| v.to_string())
+        .unwrap_or_default()
+}
+
+fn mac_address(mac: &[u8; 6]) -> String {
+    mac.iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
@@ -0,0 +1,214 @@
+//! Tracks the information requests sent while collecting the hub's
+//! devices, so that setup can finish as soon as every attached device is
+//! described instead of after a fixed delay.
+//!
+//! The IO event handler registers each request it sends and marks it
+//! answered when the reply arrives, or when it is clear that the hub
+//! rejected it with an error message. The hub is ready once it has answered
+//! the property request sent after subscribing, which it does after
+//! announcing its attached devices, and no request is outstanding.
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+use crate::consts::{HubPropertyRef, MessageType};
+use crate::error::Result;
+use crate::notifications::{InformationType, ModeInformationType};
+use crate::targets;
+use crate::Hub;
+
+#[cfg(test)]
+mod test;
+
+/// Property requested after subscribing. All hubs support it, and the
+/// reply can only come after the attached devices have been announced.
+pub const FENCE_PROPERTY: HubPropertyRef = HubPropertyRef::FwVersion;
+
+/// An information request the hub hasn't answered yet
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum InfoRequest {
+    Port {
+        port_id: u8,
+        information_type: InformationType,
+    },
+    Mode {
+        port_id: u8,
+        mode: u8,
+        information_type: ModeInformationType,
+    },
+}
+
+impl InfoRequest {
+    pub fn port_id(&self) -> u8 {
+        match self {
+            InfoRequest::Port { port_id, .. } => *port_id,
+            InfoRequest::Mode { port_id, .. } => *port_id,
+        }
+    }
+
+    /// The type of the message the request is sent as
+    pub fn message_type(&self) -> MessageType {
+        match self {
+            InfoRequest::Port { .. } => MessageType::PortInformationRequest,
+            InfoRequest::Mode { .. } => MessageType::PortModeInformationRequest,
+        }
+    }
+
+    pub async fn send(&self, hub: &dyn Hub) -> Result<()> {
+        match *self {
+            InfoRequest::Port {
+                port_id,
+                information_type,
+            } => hub.request_port_info(port_id, information_type).await,
+            InfoRequest::Mode {
+                port_id,
+                mode,
+                information_type,
+            } => hub.req_mode_info(port_id, mode, information_type).await,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ReadinessState {
+    /// In the order sent
+    pending: Vec<InfoRequest>,
+    /// Rejections not yet known to be about a particular request, by
+    /// command type
+    rejections: BTreeMap<u8, usize>,
+    announced: bool,
+}
+
+impl ReadinessState {
+    /// All attached devices have been announced and described
+    pub fn is_ready(&self) -> bool {
+        self.announced && self.pending.is_empty()
+    }
+
+    /// No request about the port is outstanding
+    pub fn is_port_ready(&self, port_id: u8) -> bool {
+        !self.pending.iter().any(|r| r.port_id() == port_id)
+    }
+
+    /// Whether the hub has answered the request sent after subscribing
+    pub fn is_announced(&self) -> bool {
+        self.announced
+    }
+
+    pub fn pending(&self) -> impl Iterator<Item = &InfoRequest> {
+        self.pending.iter()
+    }
+
+    /// Drop the outstanding requests of `command_type` if each of them
+    /// has been rejected. Returns whether any were.
+    fn drop_rejected(&mut self, command_type: u8) -> bool {
+        let Some(&count) = self.rejections.get(&command_type) else {
+            return false;
+        };
+        let of_type = |r: &InfoRequest| r.message_type() as u8 == command_type;
+        if self.pending.iter().filter(|r| of_type(r)).count() > count {
+            return false;
+        }
+        self.rejections.remove(&command_type);
+        let rejected: Vec<InfoRequest>;
+        (rejected, self.pending) = self.pending.drain(..).partition(of_type);
+        for request in &rejected {
+            warn!(target: targets::ATTACHED, "Hub rejected {:?}", request);
+        }
+        !rejected.is_empty()
+    }
+}
+
+/// Shared handle to the readiness state of a hub
+#[derive(Debug, Clone)]
+pub struct Readiness {
+    state: Arc<watch::Sender<ReadinessState>>,
+}
+
+impl Readiness {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(watch::channel(Default::default()).0),
+        }
+    }
+
+    pub fn expect(&self, request: InfoRequest) {
+        self.state.send_modify(|s| {
+            if !s.pending.contains(&request) {
+                s.pending.push(request);
+            }
+        });
+    }
+
+    pub fn answered(&self, request: InfoRequest) {
+        self.state.send_modify(|s| {
+            s.pending.retain(|r| *r != request);
+            s.drop_rejected(request.message_type() as u8);
+        });
+    }
+
+    /// The hub answered a request of `command_type` with an error. Error
+    /// messages don't say which request they are about, so the outstanding
+    /// requests of that type are only dropped once there have been as many
+    /// rejections as there are of them, e.g. when one is outstanding. The
+    /// others are answered or asked again in the meantime.
+    pub fn rejected(&self, command_type: u8) {
+        self.state.send_if_modified(|s| {
+            if !s
+                .pending
+                .iter()
+                .any(|r| r.message_type() as u8 == command_type)
+            {
+                return false;
+            }
+            *s.rejections.entry(command_type).or_default() += 1;
+            s.drop_rejected(command_type)
+        });
+    }
+
+    /// The outstanding requests were sent again, so the rejections so far
+    /// are about requests that will be answered or rejected anew
+    pub fn repeated(&self) {
+        self.state.send_if_modified(|s| {
+            s.rejections.clear();
+            false
+        });
+    }
+
+    /// Drop outstanding requests for a port whose device went away
+    pub fn forget_port(&self, port_id: u8) {
+        self.state.send_modify(|s| {
+            s.pending.retain(|r| r.port_id() != port_id);
+            // They may be what was rejected
+            s.rejections.clear();
+        });
+    }
+
+    pub fn announced(&self) {
+        self.state
+            .send_if_modified(|s| !std::mem::replace(&mut s.announced, true));
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<ReadinessState> {
+        self.state.subscribe()
+    }
+
+    pub fn pending(&self) -> Vec<InfoRequest> {
+        self.state.borrow().pending.clone()
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.state.borrow().is_ready()
+    }
+}
+
+impl Default for Readiness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,53 @@
+use super::*;
+
+fn symbol(port_id: u8) -> InfoRequest {
+    InfoRequest::Mode {
+        port_id,
+        mode: 0,
+        information_type: ModeInformationType::Symbol,
+    }
+}
+
+const MODE_REQUEST: u8 = MessageType::PortModeInformationRequest as u8;
+
+#[test]
+fn sole_outstanding_request_is_rejected() {
+    let readiness = Readiness::new();
+    readiness.announced();
+    readiness.expect(symbol(0));
+    readiness.rejected(MessageType::PortInformationRequest as u8);
+    assert_eq!(readiness.pending(), [symbol(0)]);
+    readiness.rejected(MODE_REQUEST);
+    assert!(readiness.is_ready());
+}
+
+#[test]
+fn rejection_waits_for_the_other_requests_of_its_type() {
+    let readiness = Readiness::new();
+    readiness.announced();
+    readiness.expect(symbol(0));
+    readiness.expect(symbol(1));
+    readiness.expect(symbol(2));
+
+    // The hub rejects the second request before answering the first
+    readiness.rejected(MODE_REQUEST);
+    assert_eq!(readiness.pending(), [symbol(0), symbol(1), symbol(2)]);
+    readiness.answered(symbol(0));
+    assert_eq!(readiness.pending(), [symbol(1), symbol(2)]);
+    readiness.answered(symbol(2));
+    assert!(readiness.is_ready());
+}
+
+#[test]
+fn rejections_before_repeating_the_requests_are_forgotten() {
+    let readiness = Readiness::new();
+    readiness.expect(symbol(0));
+    readiness.expect(symbol(1));
+    readiness.rejected(MODE_REQUEST);
+    readiness.repeated();
+    // Only one of the two was rejected since
+    readiness.rejected(MODE_REQUEST);
+    assert_eq!(readiness.pending(), [symbol(0), symbol(1)]);
+    readiness.rejected(MODE_REQUEST);
+    assert!(readiness.pending().is_empty());
+}
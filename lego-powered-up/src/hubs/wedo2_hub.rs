@@ -0,0 +1,96 @@
+//! WeDo 2.0 Smart Hub
+//! https://rebrickable.com/parts/bb0961c01/wedo-20-smart-hub/
+//!
+//! The hub doesn't speak LWP3. It is driven through a `Wedo2Transport`,
+//! which translates its characteristics into LWP3 messages, so once the
+//! transport is in place it behaves like the other hubs.
+
+use tokio_util::sync::CancellationToken;
+
+use super::generic_hub::GenericHub;
+use super::*;
+use crate::transport::Wedo2Transport;
+
+#[derive(Debug)]
+pub struct Wedo2Hub {
+    hub: GenericHub,
+}
+
+impl Wedo2Hub {
+    /// Initialisation method, the transport is already connected
+    pub async fn init(
+        transport: Arc<Wedo2Transport>,
+        cancel: CancellationToken,
+    ) -> Result<Self> {
+        Ok(Self {
+            hub: GenericHub::init(transport, HubType::Wedo2SmartHub, cancel)
+                .await?,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl Hub for Wedo2Hub {
+    async fn name(&self) -> Result<String> {
+        self.hub.name().await
+    }
+    async fn disconnect(&self) -> Result<()> {
+        self.hub.disconnect().await
+    }
+    async fn shutdown(&self) -> Result<()> {
+        self.hub.shutdown().await
+    }
+    async fn is_connected(&self) -> Result<bool> {
+        self.hub.is_connected().await
+    }
+    fn properties(&self) -> &HubProperties {
+        self.hub.properties()
+    }
+    fn properties_mut(&mut self) -> &mut HubProperties {
+        self.hub.properties_mut()
+    }
+    fn kind(&self) -> HubType {
+        HubType::Wedo2SmartHub
+    }
+    fn connected_io(&self) -> &BTreeMap<u8, IoDevice> {
+        self.hub.connected_io()
+    }
+    fn connected_io_mut(&mut self) -> &mut BTreeMap<u8, IoDevice> {
+        self.hub.connected_io_mut()
+    }
+    fn channels(&mut self) -> &mut Channels {
+        self.hub.channels()
+    }
+    fn io_from_port(&self, port_id: u8) -> Result<IoDevice> {
+        self.hub.io_from_port(port_id)
+    }
+    fn io_from_kind(&self, kind: IoTypeId) -> Result<IoDevice> {
+        self.hub.io_from_kind(kind)
+    }
+    fn io_multi_from_kind(&self, kind: IoTypeId) -> Result<Vec<IoDevice>> {
+        self.hub.io_multi_from_kind(kind)
+    }
+    fn tokens(&self) -> Tokens {
+        self.hub.tokens()
+    }
+    fn attach_io(&mut self, io_type_id: IoTypeId, port_id: u8) -> Result<()> {
+        self.hub.attach_io(io_type_id, port_id)
+    }
+    fn attach_virtual_io(
+        &mut self,
+        _io_type_id: IoTypeId,
+        _port_id: u8,
+        _port_a: u8,
+        _port_b: u8,
+    ) -> Result<()> {
+        Err(Error::HubError(String::from(
+            "The WeDo 2.0 hub has no virtual ports",
+        )))
+    }
+    fn device_cache(&self, d: IoDevice) -> IoDevice {
+        self.hub.device_cache(d)
+    }
+    fn cancel_token(&self) -> CancellationToken {
+        self.hub.cancel_token()
+    }
+}
@@ -8,28 +8,50 @@ use crate::notifications::{
 use crate::IoTypeId;
 use crate::{Error, Result};
 use basic::Basic;
+use colorsensor::ColorSensor;
 use definition::Definition;
+use distancesensor::DistanceSensor;
+use duplo::{DuploMotor, Speedometer, TrackSensor};
+use forcesensor::ForceSensor;
 use hubled::HubLed;
-use motor::EncoderMotor;
+use imu::{Accelerometer, Gyro, Temperature, TiltSensor};
+use lightmatrix::LightMatrix;
+use mario::{MarioAccelerometer, MarioPants, MarioScanner};
+use motor::{EncoderMotor, SyncedMotorPair};
 use remote::RcDevice;
 use sensor::GenericSensor;
+use speaker::Speaker;
+use tilt::InternalTilt;
 use visionsensor::VisionSensor;
+use wedo2::{Buzzer, ExternalTilt, MotionSensor, SimpleMotor};
 
 pub mod basic;
+pub mod colorsensor;
 pub mod definition;
+pub mod distancesensor;
+pub mod duplo;
+pub mod forcesensor;
 pub mod headlight;
 pub mod hubled;
+pub mod imu;
+pub mod known_devices;
+pub mod lightmatrix;
+pub mod mario;
 pub mod modes;
 pub mod motor;
 pub mod remote;
 pub mod sensor;
+pub mod speaker;
+pub mod tilt;
 pub mod visionsensor;
+pub mod wedo2;
 
 #[derive(Debug, Clone)]
 pub struct IoDevice {
     pub def: Definition,
     tokens: Tokens,
     channels: Channels,
+    virtual_ports: Option<(u8, u8)>,
 }
 
 impl IoDevice {
@@ -45,11 +67,28 @@ impl IoDevice {
     pub fn channels(&self) -> &Channels {
         &self.channels
     }
+    /// The ports combined into this device, if it's on a virtual port
+    pub fn virtual_ports(&self) -> Option<(u8, u8)> {
+        self.virtual_ports
+    }
     pub fn new(kind: IoTypeId, port: u8, tokens: Tokens) -> Self {
         Self {
             def: Definition::new(kind, port),
             tokens,
             channels: Default::default(),
+            virtual_ports: None,
+        }
+    }
+    pub fn new_virtual(
+        kind: IoTypeId,
+        port: u8,
+        port_a: u8,
+        port_b: u8,
+        tokens: Tokens,
+    ) -> Self {
+        Self {
+            virtual_ports: Some((port_a, port_b)),
+            ..Self::new(kind, port, tokens)
         }
     }
     pub fn cache_channels(&mut self, channels: Channels) {
@@ -65,6 +104,9 @@ impl Basic for IoDevice {
     fn tokens(&self) -> Tokens {
         self.tokens.clone()
     }
+    fn def(&self) -> &Definition {
+        &self.def
+    }
     fn get_rx(&self) -> Result<broadcast::Receiver<PortValueSingleFormat>> {
         if let Some(sender) = &self.channels.singlevalue_sender {
             Ok(sender.subscribe())
@@ -72,6 +114,15 @@ impl Basic for IoDevice {
             Err(Error::NoneError(String::from("Sender not found")))
         }
     }
+    fn get_rx_combined(
+        &self,
+    ) -> Result<broadcast::Receiver<PortValueCombinedFormat>> {
+        if let Some(sender) = &self.channels.combinedvalue_sender {
+            Ok(sender.subscribe())
+        } else {
+            Err(Error::NoneError(String::from("Sender not found")))
+        }
+    }
 }
 impl GenericSensor for IoDevice {
     fn check(&self) -> Result<()> {
@@ -117,15 +168,6 @@ impl RcDevice for IoDevice {
 }
 
 impl EncoderMotor for IoDevice {
-    fn get_rx_combined(
-        &self,
-    ) -> Result<broadcast::Receiver<PortValueCombinedFormat>> {
-        if let Some(sender) = &self.channels.combinedvalue_sender {
-            Ok(sender.subscribe())
-        } else {
-            Err(Error::NoneError(String::from("Sender not found")))
-        }
-    }
     fn get_rx_feedback(
         &self,
     ) -> Result<broadcast::Receiver<PortOutputCommandFeedbackFormat>> {
@@ -147,6 +189,22 @@ impl EncoderMotor for IoDevice {
     }
 }
 
+impl SyncedMotorPair for IoDevice {
+    fn get_rx_pair_feedback(
+        &self,
+    ) -> Result<broadcast::Receiver<PortOutputCommandFeedbackFormat>> {
+        EncoderMotor::get_rx_feedback(self)
+    }
+    fn motor_ports(&self) -> Result<(u8, u8)> {
+        self.virtual_ports
+            .ok_or_else(|| Error::HubError(String::from("Not a virtual port")))
+    }
+    fn check(&self) -> Result<()> {
+        self.motor_ports()?;
+        EncoderMotor::check(self)
+    }
+}
+
 impl HubLed for IoDevice {
     fn check(&self) -> Result<()> {
         match self.def.kind() {
@@ -156,6 +214,206 @@ impl HubLed for IoDevice {
     }
 }
 
+impl Accelerometer for IoDevice {
+    fn check(&self) -> Result<()> {
+        match self.def.kind() {
+            IoTypeId::TechnicHubAccelerometer => Ok(()),
+            _ => Err(Error::HubError(String::from("Not an accelerometer"))),
+        }
+    }
+}
+
+impl Gyro for IoDevice {
+    fn check(&self) -> Result<()> {
+        match self.def.kind() {
+            IoTypeId::TechnicHubGyroSensor => Ok(()),
+            _ => Err(Error::HubError(String::from("Not a gyro sensor"))),
+        }
+    }
+}
+
+impl TiltSensor for IoDevice {
+    fn check(&self) -> Result<()> {
+        match self.def.kind() {
+            IoTypeId::TechnicHubTiltSensor => Ok(()),
+            _ => Err(Error::HubError(String::from("Not a tilt sensor"))),
+        }
+    }
+}
+
+impl Temperature for IoDevice {
+    fn check(&self) -> Result<()> {
+        match self.def.kind() {
+            IoTypeId::TechnicHubTemperatureSensor => Ok(()),
+            _ => Err(Error::HubError(String::from("Not a temperature sensor"))),
+        }
+    }
+}
+
+impl InternalTilt for IoDevice {
+    fn check(&self) -> Result<()> {
+        match self.def.kind() {
+            IoTypeId::InternalTilt => Ok(()),
+            _ => {
+                Err(Error::HubError(String::from("Not a Move hub tilt sensor")))
+            }
+        }
+    }
+}
+
+impl ColorSensor for IoDevice {
+    fn check(&self) -> Result<()> {
+        match self.def.kind() {
+            IoTypeId::TechnicColorSensor => Ok(()),
+            _ => {
+                Err(Error::HubError(String::from("Not a Technic color sensor")))
+            }
+        }
+    }
+}
+
+impl DistanceSensor for IoDevice {
+    fn check(&self) -> Result<()> {
+        match self.def.kind() {
+            IoTypeId::TechnicDistanceSensor => Ok(()),
+            _ => Err(Error::HubError(String::from(
+                "Not a Technic distance sensor",
+            ))),
+        }
+    }
+}
+
+impl ForceSensor for IoDevice {
+    fn check(&self) -> Result<()> {
+        match self.def.kind() {
+            IoTypeId::TechnicForceSensor => Ok(()),
+            _ => {
+                Err(Error::HubError(String::from("Not a Technic force sensor")))
+            }
+        }
+    }
+}
+
+impl DuploMotor for IoDevice {
+    fn check(&self) -> Result<()> {
+        match self.def.kind() {
+            IoTypeId::DuploTrainBaseMotor => Ok(()),
+            _ => Err(Error::HubError(String::from(
+                "Not a Duplo train base motor",
+            ))),
+        }
+    }
+}
+
+impl TrackSensor for IoDevice {
+    fn check(&self) -> Result<()> {
+        match self.def.kind() {
+            IoTypeId::DuploTrainBaseColorSensor => Ok(()),
+            _ => Err(Error::HubError(String::from(
+                "Not a Duplo train base color sensor",
+            ))),
+        }
+    }
+}
+
+impl Speedometer for IoDevice {
+    fn check(&self) -> Result<()> {
+        match self.def.kind() {
+            IoTypeId::DuploTrainBaseSpeedometer => Ok(()),
+            _ => Err(Error::HubError(String::from(
+                "Not a Duplo train base speedometer",
+            ))),
+        }
+    }
+}
+
+impl LightMatrix for IoDevice {
+    fn check(&self) -> Result<()> {
+        match self.def.kind() {
+            IoTypeId::Technic3x3ColorLightMatrix => Ok(()),
+            _ => Err(Error::HubError(String::from("Not a 3x3 light matrix"))),
+        }
+    }
+}
+
+impl Speaker for IoDevice {
+    fn check(&self) -> Result<()> {
+        match self.def.kind() {
+            IoTypeId::DuploTrainBaseSpeaker => Ok(()),
+            _ => Err(Error::HubError(String::from(
+                "Not a Duplo train base speaker",
+            ))),
+        }
+    }
+}
+
+impl MarioPants for IoDevice {
+    fn check(&self) -> Result<()> {
+        match self.def.kind() {
+            IoTypeId::MarioPantsSensor => Ok(()),
+            _ => Err(Error::HubError(String::from("Not a Mario pants sensor"))),
+        }
+    }
+}
+
+impl MarioScanner for IoDevice {
+    fn check(&self) -> Result<()> {
+        match self.def.kind() {
+            IoTypeId::MarioBarcodeSensor => Ok(()),
+            _ => {
+                Err(Error::HubError(String::from("Not a Mario barcode sensor")))
+            }
+        }
+    }
+}
+
+impl MarioAccelerometer for IoDevice {
+    fn check(&self) -> Result<()> {
+        match self.def.kind() {
+            IoTypeId::MarioAccelerometer => Ok(()),
+            _ => {
+                Err(Error::HubError(String::from("Not a Mario accelerometer")))
+            }
+        }
+    }
+}
+
+impl SimpleMotor for IoDevice {
+    fn check(&self) -> Result<()> {
+        match self.def.kind() {
+            IoTypeId::Motor | IoTypeId::SystemTrainMotor => Ok(()),
+            _ => Err(Error::HubError(String::from("Not a simple motor"))),
+        }
+    }
+}
+
+impl ExternalTilt for IoDevice {
+    fn check(&self) -> Result<()> {
+        match self.def.kind() {
+            IoTypeId::ExternalTiltSensor => Ok(()),
+            _ => Err(Error::HubError(String::from("Not a WeDo tilt sensor"))),
+        }
+    }
+}
+
+impl MotionSensor for IoDevice {
+    fn check(&self) -> Result<()> {
+        match self.def.kind() {
+            IoTypeId::MotionSensor => Ok(()),
+            _ => Err(Error::HubError(String::from("Not a WeDo motion sensor"))),
+        }
+    }
+}
+
+impl Buzzer for IoDevice {
+    fn check(&self) -> Result<()> {
+        match self.def.kind() {
+            IoTypeId::PiezoToneSound => Ok(()),
+            _ => Err(Error::HubError(String::from("Not a piezo buzzer"))),
+        }
+    }
+}
+
 impl VisionSensor for IoDevice {
     fn check(&self) -> Result<()> {
         match self.def.kind() {
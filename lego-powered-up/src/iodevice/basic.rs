@@ -4,20 +4,36 @@
 use async_trait::async_trait;
 use core::fmt::Debug;
 use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
 
-use crate::error::Result;
+use super::definition::Definition;
+use crate::error::{Error, Result};
 use crate::hubs::Tokens;
 use crate::notifications::{
-    CompletionInfo, InputSetupCombined, InputSetupCombinedSubcommand,
-    InputSetupSingle, NotificationMessage, PortOutputCommandFormat,
-    PortOutputSubcommand, PortValueSingleFormat, StartupInfo,
+    CompletionInfo, DatasetType, InputSetupCombined,
+    InputSetupCombinedSubcommand, InputSetupSingle, NotificationMessage,
+    PortOutputCommandFormat, PortOutputSubcommand, PortValueCombinedFormat,
+    PortValueSingleFormat, StartupInfo, TypedValue,
 };
 
+/// A value reported in combined mode, for one mode/dataset entry of the
+/// combination
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CombinedValue {
+    pub mode: u8,
+    pub dataset: u8,
+    pub value: TypedValue,
+}
+
 #[async_trait]
 pub trait Basic: Debug + Send + Sync {
     fn port(&self) -> u8;
     fn tokens(&self) -> Tokens;
+    fn def(&self) -> &Definition;
     fn get_rx(&self) -> Result<broadcast::Receiver<PortValueSingleFormat>>;
+    fn get_rx_combined(
+        &self,
+    ) -> Result<broadcast::Receiver<PortValueCombinedFormat>>;
     async fn commit(&self, msg: NotificationMessage) -> Result<()> {
         match crate::hubs::send(self.tokens(), msg).await {
             Ok(()) => Ok(()),
@@ -54,6 +70,79 @@ pub trait Basic: Debug + Send + Sync {
         self.commit(msg).await
     }
 
+    /// Report several modes at once. Entries are (mode, dataset) pairs, at
+    /// most 8, whose modes must be part of one of the combinations the
+    /// device supports. Each update carries the entries that changed by
+    /// more than `delta`, all of them when the first entry changed.
+    async fn enable_combined(
+        &self,
+        entries: &[(u8, u8)],
+        delta: u32,
+    ) -> Result<(broadcast::Receiver<Vec<CombinedValue>>, JoinHandle<()>)> {
+        let (combination_index, dataset_types) =
+            combination(self.def(), entries)?;
+        let mut rx_from_main = self.get_rx_combined()?;
+
+        // Lock device, set up modes and combination, then unlock
+        self.device_mode_combined(
+            InputSetupCombinedSubcommand::LockLpf2DeviceForSetup,
+        )
+        .await?;
+        let mut modes: Vec<u8> = Vec::new();
+        for (mode, _) in entries {
+            if !modes.contains(mode) {
+                modes.push(*mode);
+            }
+        }
+        for mode in modes {
+            self.device_mode(mode, delta, true).await?;
+        }
+        let mut mode_dataset = [255_u8; 8];
+        for (slot, (mode, dataset)) in mode_dataset.iter_mut().zip(entries) {
+            *slot = (mode << 4) | dataset;
+        }
+        self.device_mode_combined(
+            InputSetupCombinedSubcommand::SetModeanddatasetCombinations {
+                combination_index,
+                mode_dataset,
+            },
+        )
+        .await?;
+        self.device_mode_combined(
+            InputSetupCombinedSubcommand::UnlockAndStartMultiEnabled,
+        )
+        .await?;
+
+        // Set up channel
+        let port_id = self.port();
+        let entries = entries.to_vec();
+        let (tx, rx) = broadcast::channel::<Vec<CombinedValue>>(64);
+        let task = tokio::spawn(async move {
+            while let Ok(msg) = rx_from_main.recv().await {
+                if msg.port_id != port_id {
+                    continue;
+                }
+                let values = match msg.process(&dataset_types) {
+                    Ok(values) => values,
+                    Err(e) => {
+                        warn!(target: crate::targets::INPUT, "Combined value: {e}");
+                        continue;
+                    }
+                };
+                let values = values
+                    .into_iter()
+                    .map(|(index, value)| CombinedValue {
+                        mode: entries[index].0,
+                        dataset: entries[index].1,
+                        value,
+                    })
+                    .collect();
+                let _ = tx.send(values);
+            }
+        });
+        Ok((rx, task))
+    }
+
     async fn device_command(
         &self,
         subcommand: PortOutputSubcommand,
@@ -70,3 +159,36 @@ pub trait Basic: Debug + Send + Sync {
         self.commit(msg).await
     }
 }
+
+/// Find the combination the entries belong to, and the dataset type of each
+/// entry
+fn combination(
+    def: &Definition,
+    entries: &[(u8, u8)],
+) -> Result<(u8, Vec<DatasetType>)> {
+    if entries.is_empty() || entries.len() > 8 {
+        return Err(Error::NoneError(String::from(
+            "A combination has 1 to 8 entries",
+        )));
+    }
+    let mut dataset_types = Vec::with_capacity(entries.len());
+    for (mode, dataset) in entries {
+        let Some(pm) = def.modes().get(mode) else {
+            return Err(Error::NoneError(format!("Mode {mode} not found")));
+        };
+        if *dataset >= pm.value_format.number_of_datasets {
+            return Err(Error::NoneError(format!(
+                "Mode {mode} has no dataset {dataset}"
+            )));
+        }
+        dataset_types.push(pm.value_format.dataset_type);
+    }
+    let index = def
+        .valid_combos()
+        .iter()
+        .position(|combo| entries.iter().all(|(mode, _)| combo.contains(mode)))
+        .ok_or_else(|| {
+            Error::NoneError(String::from("Modes can't be combined"))
+        })?;
+    Ok((index as u8, dataset_types))
+}
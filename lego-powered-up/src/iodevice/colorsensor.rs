@@ -0,0 +1,114 @@
+//! Support for
+//! https://rebrickable.com/parts/37308/sensor-color-spike-prime/
+
+use async_trait::async_trait;
+use core::fmt::Debug;
+use num_traits::FromPrimitive;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use super::modes;
+use super::sensor::readings;
+use super::Basic;
+pub use crate::consts::Color;
+use crate::device_trait;
+use crate::error::Result;
+use crate::notifications::{
+    CompletionInfo, PortOutputSubcommand, StartupInfo,
+    WriteDirectModeDataPayload,
+};
+
+/// Red, green and blue light intensities, 0 to 1024
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Rgb {
+    pub red: u16,
+    pub green: u16,
+    pub blue: u16,
+}
+
+/// Hue in degrees, saturation and value in percent
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Hsv {
+    pub hue: u16,
+    pub saturation: u8,
+    pub value: u8,
+}
+
+device_trait!(ColorSensor, [
+    /// The detected color, `None` if no color is detected
+    async fn color(
+        &self,
+    ) -> Result<(broadcast::Receiver<Option<Color>>, JoinHandle<()>)> {
+        self.check()?;
+        readings(self, modes::TechnicColorSensor::COLOR, 1, |v| {
+            let index = v.first()?.raw.to_f32() as i8;
+            Some(Color::from_i8(index))
+        })
+        .await
+    },
+
+    /// Light reflected from the sensor's own lights, in percent
+    async fn reflected_light(
+        &self,
+        delta: u32,
+    ) -> Result<(broadcast::Receiver<u8>, JoinHandle<()>)> {
+        self.check()?;
+        readings(self, modes::TechnicColorSensor::REFLT, delta, |v| {
+            v.first().map(|pct| pct.raw.to_f32() as u8)
+        })
+        .await
+    },
+
+    /// Ambient light intensity in percent
+    async fn ambient_light(
+        &self,
+        delta: u32,
+    ) -> Result<(broadcast::Receiver<u8>, JoinHandle<()>)> {
+        self.check()?;
+        readings(self, modes::TechnicColorSensor::AMBI, delta, |v| {
+            v.first().map(|pct| pct.raw.to_f32() as u8)
+        })
+        .await
+    },
+
+    async fn rgb(
+        &self,
+        delta: u32,
+    ) -> Result<(broadcast::Receiver<Rgb>, JoinHandle<()>)> {
+        self.check()?;
+        readings(self, modes::TechnicColorSensor::RGB_I, delta, |v| match v {
+            [red, green, blue, ..] => Some(Rgb {
+                red: red.raw.to_f32() as u16,
+                green: green.raw.to_f32() as u16,
+                blue: blue.raw.to_f32() as u16,
+            }),
+            _ => None,
+        })
+        .await
+    },
+
+    async fn hsv(
+        &self,
+        delta: u32,
+    ) -> Result<(broadcast::Receiver<Hsv>, JoinHandle<()>)> {
+        self.check()?;
+        readings(self, modes::TechnicColorSensor::HSV, delta, |v| match v {
+            [hue, saturation, value, ..] => Some(Hsv {
+                hue: hue.raw.to_f32() as u16,
+                saturation: saturation.raw.to_f32() as u8,
+                value: value.raw.to_f32() as u8,
+            }),
+            _ => None,
+        })
+        .await
+    },
+
+    /// Set the brightness of the three lights around the sensor, in percent
+    async fn set_color_sensor_lights(&self, brightness: [u8; 3]) -> Result<()> {
+        self.check()?;
+        let subcommand = PortOutputSubcommand::WriteDirectModeData(
+            WriteDirectModeDataPayload::SetColorSensorLights(brightness),
+        );
+        self.device_command(subcommand, StartupInfo::ExecuteImmediately, CompletionInfo::NoAction).await
+    }
+]);
@@ -5,6 +5,7 @@
 use std::collections::BTreeMap;
 use std::fmt;
 
+use crate::error::Result;
 use crate::notifications::*;
 use crate::IoTypeId;
 
@@ -113,17 +114,17 @@ impl Definition {
         self.capabilities = r;
     }
 
+    /// Combinations are sent as 16 bit masks of the modes they include.
+    /// Some devices end the list with an empty mask.
     pub fn set_valid_combos(&mut self, valid: Vec<u8>) {
-        for combo in valid {
-            let mut v: Vec<u8> = Vec::new();
-            for mode in 0..7 {
-                if (combo >> mode as u8) & 1 == 1 {
-                    v.push(mode as u8);
-                }
-            }
-            self.valid_combos.push(v);
-        }
-        self.valid_combos.pop(); // Last one is empty end-marker
+        self.valid_combos = valid
+            .chunks_exact(2)
+            .map(|mask| u16::from_le_bytes([mask[0], mask[1]]))
+            .take_while(|mask| *mask != 0)
+            .map(|mask| {
+                (0..16).filter(|mode| (mask >> mode) & 1 == 1).collect()
+            })
+            .collect();
     }
 
     pub fn set_mode_name(&mut self, mode_id: u8, chars_as_bytes: Vec<u8>) {
@@ -146,10 +147,6 @@ impl Definition {
                     "Found name without matching mode. Port:{} Mode:{} Name:{}",
                     self.port, &mode_id, &name
                 );
-                println!(
-                    "Found name without matching mode. Port:{} Mode:{} Name:{}",
-                    self.port, &mode_id, &name
-                );
 
                 // Some devices have modes that  count towards mode_count but are not listed in available modes.
                 // For example the TecnhicLargeLinearMotor has the "hidden" modes CALIB and STATS in addition to
@@ -185,7 +182,6 @@ impl Definition {
             Some(m) => m.symbol = symbol,
             None => {
                 error!("Found symbol without matching mode. Port:{} Mode:{} Symbol:{}", self.port, &mode_id, &symbol);
-                println!("Found symbol without matching mode. Port:{} Mode:{} Symbol:{}", self.port, &mode_id, &symbol);
             }
         }
     }
@@ -300,6 +296,48 @@ impl PortMode {
     pub fn name(&self) -> &str {
         &self.name
     }
+
+    /// Scale a raw value from the mode's raw range to its percent and SI
+    /// ranges
+    pub fn scale(&self, raw: TypedValue) -> ScaledValue {
+        let value = raw.to_f32();
+        ScaledValue {
+            raw,
+            pct: rescale(value, self.raw, self.pct),
+            si: rescale(value, self.raw, self.si),
+        }
+    }
+
+    /// Decode and scale the datasets of a value reported in this mode
+    pub fn decode(
+        &self,
+        msg: &PortValueSingleFormat,
+    ) -> Result<Vec<ScaledValue>> {
+        Ok(msg
+            .process(&self.value_format)?
+            .into_iter()
+            .map(|raw| self.scale(raw))
+            .collect())
+    }
+}
+
+/// A dataset value along with its equivalents in percent and in the
+/// mode's SI unit
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ScaledValue {
+    pub raw: TypedValue,
+    pub pct: f32,
+    pub si: f32,
+}
+
+/// Linear mapping between ranges. Values in modes with an empty raw range,
+/// like calibration modes, are passed through.
+fn rescale(value: f32, from: (f32, f32), to: (f32, f32)) -> f32 {
+    let span = from.1 - from.0;
+    if span == 0.0 {
+        return value;
+    }
+    to.0 + (value - from.0) * (to.1 - to.0) / span
 }
 
 #[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
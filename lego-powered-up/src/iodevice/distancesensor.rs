@@ -0,0 +1,43 @@
+//! Support for
+//! https://rebrickable.com/parts/37316/sensor-distance-spike-prime/
+
+use async_trait::async_trait;
+use core::fmt::Debug;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use super::modes;
+use super::sensor::readings;
+use super::Basic;
+use crate::device_trait;
+use crate::error::Result;
+use crate::notifications::{
+    CompletionInfo, PortOutputSubcommand, StartupInfo,
+    WriteDirectModeDataPayload,
+};
+
+device_trait!(DistanceSensor, [
+    /// Distance to the nearest object in mm, `None` if nothing is in range
+    async fn distance(
+        &self,
+        delta: u32,
+    ) -> Result<(broadcast::Receiver<Option<u16>>, JoinHandle<()>)> {
+        self.check()?;
+        // The raw value is in mm, and -1 when nothing is detected
+        readings(self, modes::TechnicDistanceSensor::DISTL, delta, |v| {
+            let mm = v.first()?.raw.to_f32();
+            Some((mm >= 0.0).then_some(mm as u16))
+        })
+        .await
+    },
+
+    /// Set the brightness of the four lights around the "eyes", in
+    /// percent, in the order the sensor takes them
+    async fn set_eye_lights(&self, brightness: [u8; 4]) -> Result<()> {
+        self.check()?;
+        let subcommand = PortOutputSubcommand::WriteDirectModeData(
+            WriteDirectModeDataPayload::SetDistanceSensorLights(brightness),
+        );
+        self.device_command(subcommand, StartupInfo::ExecuteImmediately, CompletionInfo::NoAction).await
+    }
+]);
@@ -0,0 +1,113 @@
+//! Support for the motor and sensors built into the
+//! https://rebrickable.com/sets/10874-1/steam-train/ Duplo train base.
+//! Its speaker is driven with `speaker::Speaker` and its light with
+//! `hubled::HubLed`.
+
+use async_trait::async_trait;
+use core::fmt::Debug;
+use num_traits::FromPrimitive;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use super::modes;
+use super::sensor::readings;
+use super::Basic;
+pub use crate::consts::Color;
+use crate::device_trait;
+use crate::error::Result;
+use crate::notifications::{
+    CompletionInfo, PortOutputSubcommand, Power, StartupInfo,
+    WriteDirectModeDataPayload,
+};
+
+device_trait!(DuploMotor, [
+    /// Run the train at `speed`, -100 to 100. Negative speeds run it
+    /// backwards, 0 lets it roll.
+    async fn drive(&self, speed: i8) -> Result<()> {
+        self.check()?;
+        self.duplo_power(Power::from_i8(speed.clamp(-100, 100))?).await
+    },
+
+    /// Brake to a stop
+    async fn stop(&self) -> Result<()> {
+        self.check()?;
+        self.duplo_power(Power::Brake).await
+    },
+
+    async fn duplo_power(&self, power: Power) -> Result<()> {
+        let subcommand = PortOutputSubcommand::WriteDirectModeData(
+            WriteDirectModeDataPayload::StartPower(power),
+        );
+        self.device_command(subcommand, StartupInfo::ExecuteImmediately, CompletionInfo::NoAction).await
+    }
+]);
+
+device_trait!(TrackSensor, [
+    /// The color under the train, `None` if no color is detected,
+    /// reported when it changes
+    async fn track_color(
+        &self,
+    ) -> Result<(broadcast::Receiver<Option<Color>>, JoinHandle<()>)> {
+        self.check()?;
+        readings(self, modes::DuploTrainBaseColorSensor::COLOR, 1, |v| {
+            Some(Color::from_i8(v.first()?.raw.to_f32() as i8))
+        })
+        .await
+    },
+
+    /// Color track markers: the color of each marker the train runs onto.
+    /// Staying on a marker doesn't report it again.
+    async fn markers(
+        &self,
+    ) -> Result<(broadcast::Receiver<Color>, JoinHandle<()>)> {
+        self.check()?;
+        let mut last = None;
+        readings(self, modes::DuploTrainBaseColorSensor::COLOR, 1, move |v| {
+            let color = Color::from_i8(v.first()?.raw.to_f32() as i8);
+            if std::mem::replace(&mut last, color) == color {
+                return None;
+            }
+            color
+        })
+        .await
+    },
+
+    /// Light reflected from the track in percent
+    async fn track_reflectivity(
+        &self,
+        delta: u32,
+    ) -> Result<(broadcast::Receiver<u8>, JoinHandle<()>)> {
+        self.check()?;
+        readings(self, modes::DuploTrainBaseColorSensor::REFLT, delta, |v| {
+            v.first().map(|pct| pct.raw.to_f32() as u8)
+        })
+        .await
+    }
+]);
+
+device_trait!(Speedometer, [
+    /// Speed of the train, negative when going backwards, in the units
+    /// the train base reports
+    async fn train_speed(
+        &self,
+        delta: u32,
+    ) -> Result<(broadcast::Receiver<i16>, JoinHandle<()>)> {
+        self.check()?;
+        readings(self, modes::DuploTrainBaseSpeedometer::SPEED, delta, |v| {
+            v.first().map(|speed| speed.raw.to_f32() as i16)
+        })
+        .await
+    },
+
+    /// Distance the train has run, in the units the train base reports
+    async fn odometer(
+        &self,
+        delta: u32,
+    ) -> Result<(broadcast::Receiver<i32>, JoinHandle<()>)> {
+        self.check()?;
+        readings(self, modes::DuploTrainBaseSpeedometer::COUNT, delta, |v| {
+            v.first().map(|count| count.raw.to_f32() as i32)
+        })
+        .await
+    }
+]);
@@ -0,0 +1,53 @@
+//! Support for
+//! https://rebrickable.com/parts/37312/sensor-force-spike-prime/
+
+use async_trait::async_trait;
+use core::fmt::Debug;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use super::modes;
+use super::sensor::readings;
+use super::Basic;
+use crate::device_trait;
+use crate::error::Result;
+
+device_trait!(ForceSensor, [
+    /// Force on the button in newtons
+    async fn force(
+        &self,
+        delta: u32,
+    ) -> Result<(broadcast::Receiver<f32>, JoinHandle<()>)> {
+        self.check()?;
+        readings(self, modes::TechnicForceSensor::FORCE, delta, |v| {
+            v.first().map(|newtons| newtons.si)
+        })
+        .await
+    },
+
+    /// Whether the button is touched, reported when it changes
+    async fn touched(
+        &self,
+    ) -> Result<(broadcast::Receiver<bool>, JoinHandle<()>)> {
+        self.check()?;
+        readings(self, modes::TechnicForceSensor::TOUCH, 1, |v| {
+            Some(v.first()?.raw.to_f32() != 0.0)
+        })
+        .await
+    },
+
+    /// Whether the button is pressed with at least `threshold` newtons,
+    /// reported when it changes
+    async fn pressed(
+        &self,
+        threshold: f32,
+    ) -> Result<(broadcast::Receiver<bool>, JoinHandle<()>)> {
+        self.check()?;
+        let mut last = None;
+        readings(self, modes::TechnicForceSensor::FORCE, 1, move |v| {
+            let pressed = v.first()?.si >= threshold;
+            (last.replace(pressed) != Some(pressed)).then_some(pressed)
+        })
+        .await
+    }
+]);
@@ -0,0 +1,151 @@
+//! Support for the motion and temperature sensors built into the
+//! https://rebrickable.com/parts/85824/hub-powered-up-4-port-technic-control-screw-opening/
+//!
+//! Readings are scaled to physical units using the SI ranges the hub
+//! reports for each mode.
+//!
+//! Unlike the Move hub's tilt sensor, cf. `tilt::InternalTilt`, this one
+//! can't be told which side is the bottom: its one config mode, CFG, only
+//! takes the impact setup.
+
+use async_trait::async_trait;
+use core::fmt::Debug;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use super::definition::ScaledValue;
+use super::modes;
+use super::sensor::readings;
+use super::Basic;
+use crate::device_trait;
+use crate::error::Result;
+use crate::notifications::{
+    CompletionInfo, PortOutputSubcommand, StartupInfo,
+    WriteDirectModeDataPayload,
+};
+
+/// A reading along or about each of the hub's axes
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Vector3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+impl Vector3 {
+    fn from_si(x: ScaledValue, y: ScaledValue, z: ScaledValue) -> Self {
+        Self {
+            x: x.si,
+            y: y.si,
+            z: z.si,
+        }
+    }
+}
+
+device_trait!(Accelerometer, [
+    /// Acceleration in mG (1/1000 of standard gravity)
+    async fn acceleration(
+        &self,
+        delta: u32,
+    ) -> Result<(broadcast::Receiver<Vector3>, JoinHandle<()>)> {
+        self.check()?;
+        readings(self, modes::TechnicHubAccelerometer::GRV, delta, |v| {
+            match *v {
+                [x, y, z, ..] => Some(Vector3::from_si(x, y, z)),
+                _ => None,
+            }
+        })
+        .await
+    }
+]);
+
+device_trait!(Gyro, [
+    /// Rotation rate in degrees per second
+    async fn rotation(
+        &self,
+        delta: u32,
+    ) -> Result<(broadcast::Receiver<Vector3>, JoinHandle<()>)> {
+        self.check()?;
+        readings(self, modes::TechnicHubGyroSensor::ROT, delta, |v| {
+            match *v {
+                [x, y, z, ..] => Some(Vector3::from_si(x, y, z)),
+                _ => None,
+            }
+        })
+        .await
+    }
+]);
+
+device_trait!(TiltSensor, [
+    /// Tilt angles about each axis in degrees
+    async fn tilt(
+        &self,
+        delta: u32,
+    ) -> Result<(broadcast::Receiver<Vector3>, JoinHandle<()>)> {
+        self.check()?;
+        // The hub reports the angles about the z, y and x axes, in that
+        // order, with z inverted
+        readings(self, modes::TechnicHubTiltSensor::POS, delta, |v| {
+            match *v {
+                [z, y, x, ..] => Some(Vector3 {
+                    x: x.si,
+                    y: y.si,
+                    z: -z.si,
+                }),
+                _ => None,
+            }
+        })
+        .await
+    },
+
+    /// Number of impacts detected
+    async fn impacts(
+        &self,
+        delta: u32,
+    ) -> Result<(broadcast::Receiver<u32>, JoinHandle<()>)> {
+        self.check()?;
+        readings(self, modes::TechnicHubTiltSensor::IMP, delta, |v| {
+            v.first().map(|count| count.si as u32)
+        })
+        .await
+    },
+
+    /// Set the impact count
+    async fn preset_impacts(&self, count: i32) -> Result<()> {
+        self.check()?;
+        let subcommand = PortOutputSubcommand::WriteDirectModeData(
+            WriteDirectModeDataPayload::TechnicTiltImpactPreset(count),
+        );
+        self.device_command(subcommand, StartupInfo::ExecuteImmediately, CompletionInfo::NoAction).await
+    },
+
+    /// Set how hard a knock must be to count as an impact, and how long
+    /// to wait before counting another one. Written to the CFG mode.
+    async fn configure_impact(
+        &self,
+        impact_threshold: i8,
+        bump_holdoff: i8,
+    ) -> Result<()> {
+        self.check()?;
+        let subcommand = PortOutputSubcommand::WriteDirectModeData(
+            WriteDirectModeDataPayload::TechnicTiltConfigImpact {
+                impact_threshold,
+                bump_holdoff,
+            },
+        );
+        self.device_command(subcommand, StartupInfo::ExecuteImmediately, CompletionInfo::NoAction).await
+    }
+]);
+
+device_trait!(Temperature, [
+    /// Temperature in degrees Celsius
+    async fn temperature(
+        &self,
+        delta: u32,
+    ) -> Result<(broadcast::Receiver<f32>, JoinHandle<()>)> {
+        self.check()?;
+        readings(self, modes::TechnicHubTemperatureSensor::TEMP, delta, |v| {
+            v.first().map(|degrees| degrees.si)
+        })
+        .await
+    }
+]);
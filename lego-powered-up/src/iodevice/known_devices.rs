@@ -0,0 +1,503 @@
+//! Mode tables for the devices the crate knows. Values follow what real
+//! devices report in their PortInformation and PortModeInformation
+//! replies, rounded where the hubs report odd float ranges.
+//!
+//! The WeDo 2.0 transport answers information requests from these tables,
+//! since WeDo devices can't describe themselves, and the simulated hub
+//! describes its devices with them.
+
+use crate::notifications::{
+    DatasetType, InformationType, MappingValue, ModeInformationType,
+    PortCapabilities, PortInformationType, PortModeInformationType,
+    ValueFormatType,
+};
+use crate::IoTypeId;
+
+#[derive(Debug, Clone, Copy)]
+pub struct KnownMode {
+    pub name: &'static str,
+    pub input: bool,
+    pub output: bool,
+    pub raw: (f32, f32),
+    pub pct: (f32, f32),
+    pub si: (f32, f32),
+    pub symbol: &'static str,
+    pub mapping: (u8, u8),
+    pub datasets: u8,
+    pub dataset_type: DatasetType,
+    pub figures: u8,
+    pub decimals: u8,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct KnownDevice {
+    pub capabilities: u8,
+    pub modes: &'static [KnownMode],
+    pub combos: &'static [u16],
+}
+
+impl KnownDevice {
+    pub fn mode_count(&self) -> u8 {
+        self.modes.len() as u8
+    }
+    pub fn input_modes(&self) -> u16 {
+        self.modes
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| m.input)
+            .fold(0, |acc, (i, _)| acc | (1 << i))
+    }
+    pub fn output_modes(&self) -> u16 {
+        self.modes
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| m.output)
+            .fold(0, |acc, (i, _)| acc | (1 << i))
+    }
+    pub fn is_motor(&self) -> bool {
+        // The Technic hub's tilt sensor has a POS mode too, and the Duplo
+        // speedometer a SPEED mode that can't be written
+        self.modes.iter().any(|m| m.name == "SPEED" && m.output)
+    }
+
+    /// The reply to a port information request, `None` for the port
+    /// value which depends on the device's state
+    pub fn port_information(
+        &self,
+        information_type: InformationType,
+    ) -> Option<PortInformationType> {
+        match information_type {
+            InformationType::ModeInfo => Some(PortInformationType::ModeInfo {
+                capabilities: PortCapabilities(self.capabilities),
+                mode_count: self.mode_count(),
+                input_modes: self.input_modes(),
+                output_modes: self.output_modes(),
+            }),
+            InformationType::PossibleModeCombinations => {
+                Some(PortInformationType::PossibleModeCombinations(
+                    self.combos.iter().flat_map(|c| c.to_le_bytes()).collect(),
+                ))
+            }
+            InformationType::PortValue => None,
+        }
+    }
+
+    /// The reply to a mode information request, `None` if there is no
+    /// such mode or the information is internal
+    pub fn mode_information(
+        &self,
+        mode: u8,
+        information_type: ModeInformationType,
+    ) -> Option<PortModeInformationType> {
+        use PortModeInformationType::*;
+        let mode = self.modes.get(mode as usize)?;
+        Some(match information_type {
+            ModeInformationType::Name => Name(mode.name.as_bytes().to_vec()),
+            ModeInformationType::Raw => RawRange {
+                min: mode.raw.0,
+                max: mode.raw.1,
+            },
+            ModeInformationType::Pct => PctRange {
+                min: mode.pct.0,
+                max: mode.pct.1,
+            },
+            ModeInformationType::Si => SiRange {
+                min: mode.si.0,
+                max: mode.si.1,
+            },
+            ModeInformationType::Symbol => {
+                Symbol(mode.symbol.as_bytes().to_vec())
+            }
+            ModeInformationType::Mapping => Mapping {
+                input: MappingValue(mode.mapping.0),
+                output: MappingValue(mode.mapping.1),
+            },
+            ModeInformationType::MotorBias => MotorBias(0),
+            ModeInformationType::CapabilityBits => CapabilityBits([0; 6]),
+            ModeInformationType::ValueFormat => ValueFormat(ValueFormatType {
+                number_of_datasets: mode.datasets,
+                dataset_type: mode.dataset_type,
+                total_figures: mode.figures,
+                decimals: mode.decimals,
+            }),
+            ModeInformationType::UsedInternally => return None,
+        })
+    }
+}
+
+const IN: u8 = 0b01;
+const OUT: u8 = 0b10;
+const IN_OUT: u8 = IN | OUT;
+const HIDDEN: u8 = 0b00;
+
+const fn mode(
+    name: &'static str,
+    direction: u8,
+    raw: (f32, f32),
+    si: (f32, f32),
+    symbol: &'static str,
+    (datasets, dataset_type, figures, decimals): (u8, DatasetType, u8, u8),
+) -> KnownMode {
+    let input = direction & IN != 0;
+    let output = direction & OUT != 0;
+    KnownMode {
+        name,
+        input,
+        output,
+        raw,
+        pct: (-100.0, 100.0),
+        si,
+        symbol,
+        mapping: (
+            if input { 0x10 } else { 0x00 },
+            if output { 0x10 } else { 0x00 },
+        ),
+        datasets,
+        dataset_type,
+        figures,
+        decimals,
+    }
+}
+
+use DatasetType::*;
+
+#[rustfmt::skip]
+const TECHNIC_MOTOR: &[KnownMode] = &[
+    mode("POWER", OUT, (-100.0, 100.0), (-100.0, 100.0), "PCT", (1, Bits8, 4, 0)),
+    mode("SPEED", IN_OUT, (-100.0, 100.0), (-100.0, 100.0), "PCT", (1, Bits8, 4, 0)),
+    mode("POS", IN_OUT, (-360.0, 360.0), (-360.0, 360.0), "DEG", (1, Bits32, 11, 0)),
+    mode("APOS", IN_OUT, (-180.0, 179.0), (-180.0, 179.0), "DEG", (1, Bits16, 3, 0)),
+    mode("LOAD", IN_OUT, (0.0, 127.0), (0.0, 127.0), "PCT", (1, Bits8, 1, 0)),
+    mode("STATS", HIDDEN, (0.0, 65535.0), (0.0, 65535.0), "MIN", (14, Bits16, 5, 0)),
+];
+
+#[rustfmt::skip]
+const INTERNAL_MOTOR: &[KnownMode] = &[
+    mode("POWER", OUT, (-100.0, 100.0), (-100.0, 100.0), "PCT", (1, Bits8, 4, 0)),
+    mode("SPEED", IN_OUT, (-100.0, 100.0), (-100.0, 100.0), "PCT", (1, Bits8, 4, 0)),
+    mode("POS", IN_OUT, (-360.0, 360.0), (-360.0, 360.0), "DEG", (1, Bits32, 4, 0)),
+];
+
+#[rustfmt::skip]
+const HUB_LED: &[KnownMode] = &[
+    mode("COL O", OUT, (0.0, 10.0), (0.0, 10.0), "", (1, Bits8, 1, 0)),
+    mode("RGB O", OUT, (0.0, 255.0), (0.0, 255.0), "", (3, Bits8, 3, 0)),
+];
+
+#[rustfmt::skip]
+const CURRENT: &[KnownMode] = &[
+    mode("CUR L", IN, (0.0, 4095.0), (0.0, 4175.0), "mA", (1, Bits16, 4, 0)),
+    mode("CUR S", IN, (0.0, 4095.0), (0.0, 4175.0), "mA", (1, Bits16, 4, 0)),
+];
+
+#[rustfmt::skip]
+const VOLTAGE: &[KnownMode] = &[
+    mode("VLT L", IN, (0.0, 3893.0), (0.0, 9615.0), "mV", (1, Bits16, 4, 0)),
+    mode("VLT S", IN, (0.0, 3893.0), (0.0, 9615.0), "mV", (1, Bits16, 4, 0)),
+];
+
+#[rustfmt::skip]
+const TEMPERATURE: &[KnownMode] = &[
+    mode("TEMP", IN, (-900.0, 900.0), (-90.0, 90.0), "DEG", (1, Bits16, 5, 1)),
+];
+
+#[rustfmt::skip]
+const ACCELEROMETER: &[KnownMode] = &[
+    mode("GRV", IN, (-32768.0, 32768.0), (-8000.0, 8000.0), "mG", (3, Bits16, 5, 0)),
+    mode("CAL", IN, (1.0, 1.0), (1.0, 1.0), "", (1, Bits8, 1, 0)),
+];
+
+#[rustfmt::skip]
+const GYRO: &[KnownMode] = &[
+    mode("ROT", IN, (-28571.0, 28571.0), (-2000.0, 2000.0), "DPS", (3, Bits16, 5, 0)),
+];
+
+#[rustfmt::skip]
+const TILT: &[KnownMode] = &[
+    mode("POS", IN, (-180.0, 180.0), (-180.0, 180.0), "DEG", (3, Bits16, 3, 0)),
+    mode("IMP", IN_OUT, (0.0, 100.0), (0.0, 100.0), "CNT", (1, Bits32, 3, 0)),
+    mode("CFG", OUT, (0.0, 255.0), (0.0, 255.0), "", (2, Bits8, 3, 0)),
+];
+
+#[rustfmt::skip]
+const COLOR_SENSOR: &[KnownMode] = &[
+    mode("COLOR", IN, (0.0, 10.0), (0.0, 10.0), "IDX", (1, Bits8, 2, 0)),
+    mode("REFLT", IN, (0.0, 100.0), (0.0, 100.0), "PCT", (1, Bits8, 3, 0)),
+    mode("AMBI", IN, (0.0, 100.0), (0.0, 100.0), "PCT", (1, Bits8, 3, 0)),
+    mode("LIGHT", OUT, (0.0, 100.0), (0.0, 100.0), "PCT", (3, Bits8, 3, 0)),
+    mode("RREFL", IN, (0.0, 1024.0), (0.0, 1024.0), "RAW", (2, Bits16, 4, 0)),
+    mode("RGB I", IN, (0.0, 1024.0), (0.0, 1024.0), "RAW", (4, Bits16, 4, 0)),
+    mode("HSV", IN, (0.0, 360.0), (0.0, 360.0), "RAW", (3, Bits16, 4, 0)),
+    mode("SHSV", IN, (0.0, 360.0), (0.0, 360.0), "RAW", (4, Bits16, 4, 0)),
+    mode("DEBUG", HIDDEN, (0.0, 1023.0), (0.0, 1023.0), "RAW", (2, Bits16, 4, 0)),
+    mode("CALIB", HIDDEN, (0.0, 65535.0), (0.0, 65535.0), "RAW", (7, Bits16, 5, 0)),
+];
+
+#[rustfmt::skip]
+const DISTANCE_SENSOR: &[KnownMode] = &[
+    mode("DISTL", IN, (0.0, 2500.0), (0.0, 250.0), "CM", (1, Bits16, 5, 1)),
+    mode("DISTS", IN, (0.0, 320.0), (0.0, 32.0), "CM", (1, Bits16, 4, 1)),
+    mode("SINGL", IN, (0.0, 2500.0), (0.0, 250.0), "CM", (1, Bits16, 5, 1)),
+    mode("LISTN", IN, (0.0, 1.0), (0.0, 1.0), "ST", (1, Bits8, 1, 0)),
+    mode("TRAW", IN, (0.0, 14577.0), (0.0, 14577.0), "US", (1, Bits32, 5, 0)),
+    mode("LIGHT", OUT, (0.0, 100.0), (0.0, 100.0), "PCT", (4, Bits8, 3, 0)),
+    mode("PING", HIDDEN, (0.0, 1.0), (0.0, 1.0), "PCT", (1, Bits8, 1, 0)),
+    mode("ADRAW", HIDDEN, (0.0, 1024.0), (0.0, 1024.0), "PCT", (1, Bits16, 4, 0)),
+    mode("CALIB", HIDDEN, (0.0, 255.0), (0.0, 255.0), "PCT", (7, Bits8, 3, 0)),
+];
+
+#[rustfmt::skip]
+const FORCE_SENSOR: &[KnownMode] = &[
+    mode("FORCE", IN, (0.0, 100.0), (0.0, 10.0), "N", (1, Bits8, 4, 1)),
+    mode("TOUCH", IN, (0.0, 1.0), (0.0, 1.0), "IDX", (1, Bits8, 1, 0)),
+    mode("TAP", IN, (0.0, 3.0), (0.0, 3.0), "IDX", (1, Bits8, 1, 0)),
+    mode("FPEAK", IN, (0.0, 100.0), (0.0, 10.0), "N", (1, Bits8, 4, 1)),
+    mode("FRAW", IN, (0.0, 1023.0), (0.0, 1023.0), "RAW", (1, Bits16, 4, 0)),
+    mode("FPRAW", IN, (0.0, 1023.0), (0.0, 1023.0), "RAW", (1, Bits16, 4, 0)),
+    mode("CALIB", HIDDEN, (0.0, 1023.0), (0.0, 1023.0), "RAW", (8, Bits16, 4, 0)),
+];
+
+#[rustfmt::skip]
+const LIGHT_MATRIX: &[KnownMode] = &[
+    mode("LEV O", OUT, (0.0, 9.0), (0.0, 9.0), "", (1, Bits8, 1, 0)),
+    mode("COL O", OUT, (0.0, 10.0), (0.0, 10.0), "", (1, Bits8, 2, 0)),
+    mode("PIX O", OUT, (0.0, 170.0), (0.0, 170.0), "", (9, Bits8, 3, 0)),
+    mode("TRANS", OUT, (0.0, 2.0), (0.0, 2.0), "", (1, Bits8, 1, 0)),
+];
+
+#[rustfmt::skip]
+const DUPLO_MOTOR: &[KnownMode] = &[
+    mode("T MOT", OUT, (-100.0, 100.0), (-100.0, 100.0), "PCT", (1, Bits8, 4, 0)),
+];
+
+#[rustfmt::skip]
+const DUPLO_SPEAKER: &[KnownMode] = &[
+    mode("MUSIC", OUT, (0.0, 10.0), (0.0, 10.0), "", (1, Bits8, 3, 0)),
+    mode("SOUND", OUT, (0.0, 10.0), (0.0, 10.0), "", (1, Bits8, 3, 0)),
+    mode("TONE", OUT, (0.0, 10.0), (0.0, 10.0), "", (1, Bits8, 3, 0)),
+];
+
+#[rustfmt::skip]
+const DUPLO_COLOR_SENSOR: &[KnownMode] = &[
+    mode("COLOR", IN, (0.0, 10.0), (0.0, 10.0), "IDX", (1, Bits8, 2, 0)),
+    mode("C TAG", IN, (0.0, 10.0), (0.0, 10.0), "IDX", (1, Bits8, 2, 0)),
+    mode("REFLT", IN, (0.0, 100.0), (0.0, 100.0), "PCT", (1, Bits8, 3, 0)),
+    mode("RGB I", IN, (0.0, 1023.0), (0.0, 1023.0), "RAW", (3, Bits16, 4, 0)),
+];
+
+#[rustfmt::skip]
+const DUPLO_SPEEDOMETER: &[KnownMode] = &[
+    mode("SPEED", IN, (-100.0, 100.0), (-100.0, 100.0), "", (1, Bits16, 4, 0)),
+    mode("COUNT", IN, (-2147483648.0, 2147483647.0), (-2147483648.0, 2147483647.0), "", (1, Bits32, 4, 0)),
+];
+
+#[rustfmt::skip]
+const MARIO_ACCELEROMETER: &[KnownMode] = &[
+    mode("RAW", IN, (-128.0, 127.0), (-128.0, 127.0), "", (3, Bits8, 3, 0)),
+    mode("GEST", IN, (0.0, 65535.0), (0.0, 65535.0), "", (2, Bits16, 5, 0)),
+];
+
+#[rustfmt::skip]
+const MARIO_BARCODE: &[KnownMode] = &[
+    mode("TAG", IN, (0.0, 65535.0), (0.0, 65535.0), "", (2, Bits16, 5, 0)),
+    mode("RGB", IN, (0.0, 255.0), (0.0, 255.0), "", (3, Bits8, 3, 0)),
+];
+
+#[rustfmt::skip]
+const MARIO_PANTS: &[KnownMode] = &[
+    mode("PANT", IN, (0.0, 63.0), (0.0, 63.0), "IDX", (1, Bits8, 2, 0)),
+];
+
+#[rustfmt::skip]
+const INTERNAL_TILT: &[KnownMode] = &[
+    mode("ANGLE", IN, (-90.0, 90.0), (-90.0, 90.0), "DEG", (2, Bits8, 3, 0)),
+    mode("TILT", IN, (0.0, 10.0), (0.0, 10.0), "DIR", (1, Bits8, 2, 0)),
+    mode("ORINT", IN, (0.0, 5.0), (0.0, 5.0), "DIR", (1, Bits8, 1, 0)),
+    mode("IMPCT", IN_OUT, (0.0, 100.0), (0.0, 100.0), "IMP", (1, Bits32, 3, 0)),
+    mode("ACCEL", IN, (-65.0, 65.0), (-1.6, 1.6), "ACC", (3, Bits8, 3, 0)),
+    mode("OR_CF", OUT, (0.0, 6.0), (0.0, 6.0), "SID", (1, Bits8, 1, 0)),
+    mode("IM_CF", OUT, (0.0, 255.0), (0.0, 255.0), "", (2, Bits8, 3, 0)),
+    mode("CALIB", OUT, (0.0, 255.0), (0.0, 255.0), "", (3, Bits8, 3, 0)),
+];
+
+#[rustfmt::skip]
+const GEST: &[KnownMode] = &[
+    mode("GEST", IN, (0.0, 4.0), (0.0, 4.0), "", (1, Bits8, 1, 0)),
+];
+
+#[rustfmt::skip]
+const SIMPLE_MOTOR: &[KnownMode] = &[
+    mode("POWER", OUT, (-100.0, 100.0), (-100.0, 100.0), "PCT", (1, Bits8, 4, 0)),
+];
+
+#[rustfmt::skip]
+const EXTERNAL_TILT: &[KnownMode] = &[
+    mode("LPF2-ANGLE", IN, (-45.0, 45.0), (-45.0, 45.0), "DEG", (2, Bits8, 3, 0)),
+    mode("LPF2-TILT", IN, (0.0, 10.0), (0.0, 10.0), "DIR", (1, Bits8, 2, 0)),
+    mode("LPF2-CRASH", IN, (0.0, 100.0), (0.0, 100.0), "CNT", (3, Bits8, 3, 0)),
+    mode("LPF2-CAL", IN, (-45.0, 45.0), (-45.0, 45.0), "", (3, Bits8, 3, 0)),
+];
+
+#[rustfmt::skip]
+const MOTION_SENSOR: &[KnownMode] = &[
+    mode("LPF2-DETECT", IN, (0.0, 10.0), (0.0, 10.0), "", (1, Bits8, 3, 0)),
+    mode("LPF2-COUNT", IN, (0.0, 100.0), (0.0, 100.0), "", (1, Bits32, 4, 0)),
+    mode("LPF2-CAL", IN, (0.0, 1023.0), (0.0, 1023.0), "", (3, Bits16, 4, 0)),
+];
+
+#[rustfmt::skip]
+const REMOTE_BUTTONS: &[KnownMode] = &[
+    mode("RCKEY", IN, (-1.0, 1.0), (-1.0, 1.0), "btn", (1, Bits8, 4, 0)),
+    mode("KEYA", IN, (-1.0, 1.0), (-1.0, 1.0), "btn", (1, Bits8, 4, 0)),
+    mode("KEYR", IN, (-1.0, 1.0), (-1.0, 1.0), "btn", (1, Bits8, 4, 0)),
+    mode("KEYD", IN, (0.0, 7.0), (0.0, 7.0), "btn", (1, Bits8, 4, 0)),
+    mode("KEYSD", IN, (0.0, 1.0), (0.0, 1.0), "btn", (3, Bits8, 4, 0)),
+];
+
+const UNKNOWN: &[KnownMode] = &[];
+
+/// Mode table for a device of the given type. Devices without a table
+/// report no modes.
+pub fn device(kind: IoTypeId) -> KnownDevice {
+    use IoTypeId::*;
+    match kind {
+        TechnicLargeLinearMotor
+        | TechnicXLargeLinearMotor
+        | TechnicMediumAngularMotorGrey
+        | TechnicLargeAngularMotorGrey => KnownDevice {
+            capabilities: 0x0f,
+            modes: TECHNIC_MOTOR,
+            combos: &[0b1110],
+        },
+        InternalMotorTacho => KnownDevice {
+            capabilities: 0x0f,
+            modes: INTERNAL_MOTOR,
+            combos: &[0b0110],
+        },
+        HubLed => KnownDevice {
+            capabilities: 0x01,
+            modes: HUB_LED,
+            combos: &[],
+        },
+        Current => KnownDevice {
+            capabilities: 0x02,
+            modes: CURRENT,
+            combos: &[],
+        },
+        Voltage => KnownDevice {
+            capabilities: 0x02,
+            modes: VOLTAGE,
+            combos: &[],
+        },
+        TechnicHubTemperatureSensor => KnownDevice {
+            capabilities: 0x02,
+            modes: TEMPERATURE,
+            combos: &[],
+        },
+        TechnicHubAccelerometer => KnownDevice {
+            capabilities: 0x02,
+            modes: ACCELEROMETER,
+            combos: &[],
+        },
+        TechnicHubGyroSensor => KnownDevice {
+            capabilities: 0x02,
+            modes: GYRO,
+            combos: &[],
+        },
+        TechnicHubTiltSensor => KnownDevice {
+            capabilities: 0x03,
+            modes: TILT,
+            combos: &[],
+        },
+        TechnicColorSensor => KnownDevice {
+            capabilities: 0x07,
+            modes: COLOR_SENSOR,
+            combos: &[],
+        },
+        TechnicDistanceSensor => KnownDevice {
+            capabilities: 0x07,
+            modes: DISTANCE_SENSOR,
+            combos: &[],
+        },
+        TechnicForceSensor => KnownDevice {
+            capabilities: 0x06,
+            modes: FORCE_SENSOR,
+            combos: &[],
+        },
+        Technic3x3ColorLightMatrix => KnownDevice {
+            capabilities: 0x01,
+            modes: LIGHT_MATRIX,
+            combos: &[],
+        },
+        DuploTrainBaseMotor => KnownDevice {
+            capabilities: 0x01,
+            modes: DUPLO_MOTOR,
+            combos: &[],
+        },
+        DuploTrainBaseSpeaker => KnownDevice {
+            capabilities: 0x01,
+            modes: DUPLO_SPEAKER,
+            combos: &[],
+        },
+        DuploTrainBaseColorSensor => KnownDevice {
+            capabilities: 0x02,
+            modes: DUPLO_COLOR_SENSOR,
+            combos: &[],
+        },
+        DuploTrainBaseSpeedometer => KnownDevice {
+            capabilities: 0x02,
+            modes: DUPLO_SPEEDOMETER,
+            combos: &[],
+        },
+        MarioAccelerometer => KnownDevice {
+            capabilities: 0x02,
+            modes: MARIO_ACCELEROMETER,
+            combos: &[],
+        },
+        MarioBarcodeSensor => KnownDevice {
+            capabilities: 0x02,
+            modes: MARIO_BARCODE,
+            combos: &[],
+        },
+        MarioPantsSensor => KnownDevice {
+            capabilities: 0x02,
+            modes: MARIO_PANTS,
+            combos: &[],
+        },
+        InternalTilt => KnownDevice {
+            capabilities: 0x03,
+            modes: INTERNAL_TILT,
+            combos: &[],
+        },
+        TechnicHubGestSensor => KnownDevice {
+            capabilities: 0x02,
+            modes: GEST,
+            combos: &[],
+        },
+        RemoteButtons => KnownDevice {
+            capabilities: 0x02,
+            modes: REMOTE_BUTTONS,
+            combos: &[],
+        },
+        Motor | SystemTrainMotor => KnownDevice {
+            capabilities: 0x01,
+            modes: SIMPLE_MOTOR,
+            combos: &[],
+        },
+        ExternalTiltSensor => KnownDevice {
+            capabilities: 0x02,
+            modes: EXTERNAL_TILT,
+            combos: &[],
+        },
+        MotionSensor => KnownDevice {
+            capabilities: 0x02,
+            modes: MOTION_SENSOR,
+            combos: &[],
+        },
+        _ => KnownDevice {
+            capabilities: 0x00,
+            modes: UNKNOWN,
+            combos: &[],
+        },
+    }
+}
@@ -0,0 +1,161 @@
+//! Support for
+//! https://rebrickable.com/parts/45608/light-matrix-3-x-3-color-spike-essential/
+
+use async_trait::async_trait;
+use core::fmt::Debug;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use super::Basic;
+pub use crate::consts::Color;
+use crate::device_trait;
+use crate::error::{Error, Result};
+use crate::notifications::{
+    CompletionInfo, NotificationMessage, PortOutputCommandFormat,
+    PortOutputSubcommand, StartupInfo, WriteDirectModeDataPayload,
+};
+
+/// One pixel of the matrix: a color and a brightness from 0 (off) to 10
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Pixel {
+    pub color: Color,
+    pub brightness: u8,
+}
+impl Pixel {
+    pub const OFF: Pixel = Pixel {
+        color: Color::Black,
+        brightness: 0,
+    };
+
+    /// The pixel at full brightness
+    pub fn new(color: Color) -> Self {
+        Self {
+            color,
+            brightness: 10,
+        }
+    }
+
+    /// Brightness in the high nibble, color in the low one. `Color::None`
+    /// turns the pixel off.
+    pub fn to_u8(&self) -> u8 {
+        match self.color {
+            Color::None => 0,
+            color => (self.brightness.min(10) << 4) | color as u8,
+        }
+    }
+}
+impl Default for Pixel {
+    fn default() -> Self {
+        Self::OFF
+    }
+}
+
+/// The nine pixels of the matrix, row by row from the top left
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Frame(pub [Pixel; 9]);
+impl Frame {
+    /// Every pixel set to `pixel`
+    pub fn filled(pixel: Pixel) -> Self {
+        Self([pixel; 9])
+    }
+
+    /// Set the pixel at `row` and `column`, both 0 to 2
+    pub fn set(
+        &mut self,
+        row: usize,
+        column: usize,
+        pixel: Pixel,
+    ) -> Result<()> {
+        if row > 2 || column > 2 {
+            return Err(Error::HubError(format!(
+                "No pixel at row {row}, column {column}"
+            )));
+        }
+        self.0[row * 3 + column] = pixel;
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> [u8; 9] {
+        self.0.map(|pixel| pixel.to_u8())
+    }
+}
+
+fn frame_message(port_id: u8, frame: &Frame) -> NotificationMessage {
+    NotificationMessage::PortOutputCommand(PortOutputCommandFormat {
+        port_id,
+        startup_info: StartupInfo::ExecuteImmediately,
+        completion_info: CompletionInfo::NoAction,
+        subcommand: PortOutputSubcommand::WriteDirectModeData(
+            WriteDirectModeDataPayload::SetLightMatrixPixels(frame.to_bytes()),
+        ),
+    })
+}
+
+device_trait!(LightMatrix, [
+    /// Show all nine pixels in one write
+    async fn write_frame(&self, frame: &Frame) -> Result<()> {
+        self.check()?;
+        self.commit(frame_message(self.port(), frame)).await
+    },
+
+    /// Set one pixel of `frame` and show the frame. The matrix can't be
+    /// written a pixel at a time, so the caller keeps the frame that is
+    /// shown.
+    async fn set_pixel(
+        &self,
+        frame: &mut Frame,
+        row: usize,
+        column: usize,
+        pixel: Pixel,
+    ) -> Result<()> {
+        frame.set(row, column, pixel)?;
+        self.write_frame(frame).await
+    },
+
+    /// Light every pixel the same
+    async fn fill(&self, pixel: Pixel) -> Result<()> {
+        self.write_frame(&Frame::filled(pixel)).await
+    },
+
+    /// Turn every pixel off
+    async fn clear(&self) -> Result<()> {
+        self.write_frame(&Frame::default()).await
+    },
+
+    /// Set how the matrix changes from one frame to the next, as
+    /// numbered by the device. 0 switches instantly.
+    async fn set_transition(&self, transition: u8) -> Result<()> {
+        self.check()?;
+        let subcommand = PortOutputSubcommand::WriteDirectModeData(
+            WriteDirectModeDataPayload::SetLightMatrixTransition(transition),
+        );
+        self.device_command(subcommand, StartupInfo::ExecuteImmediately, CompletionInfo::NoAction).await
+    },
+
+    /// Show `frames` one after another, `interval` apart, starting
+    /// right away. With `repeat` the animation loops until the task is
+    /// aborted. The task ends with the error if a write fails.
+    fn play_animation(
+        &self,
+        frames: Vec<Frame>,
+        interval: Duration,
+        repeat: bool,
+    ) -> Result<JoinHandle<Result<()>>> {
+        self.check()?;
+        let tokens = self.tokens();
+        let port_id = self.port();
+        Ok(tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            loop {
+                for frame in &frames {
+                    ticks.tick().await;
+                    crate::hubs::send(tokens.clone(), frame_message(port_id, frame))
+                        .await?;
+                }
+                if !repeat || frames.is_empty() {
+                    return Ok(());
+                }
+            }
+        }))
+    }
+]);
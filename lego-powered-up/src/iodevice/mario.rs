@@ -0,0 +1,117 @@
+//! Support for the sensors in
+//! https://rebrickable.com/sets/71360-1/adventures-with-mario-starter-course/
+//! LEGO Mario: the pants sensor, the barcode and color scanner under his
+//! feet, and the accelerometer.
+
+use async_trait::async_trait;
+use core::fmt::Debug;
+use num_traits::FromPrimitive;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use super::definition::ScaledValue;
+use super::imu::Vector3;
+use super::modes;
+use super::sensor::readings;
+use super::Basic;
+pub use crate::consts::{MarioColor, MarioPantsType};
+use crate::device_trait;
+use crate::error::Result;
+
+/// Reported by the scanner in place of a barcode when it sees a color,
+/// and in place of a color when it sees a barcode
+const NOTHING: u16 = 0xffff;
+
+device_trait!(MarioPants, [
+    /// The pants Mario is wearing, reported when they change
+    async fn pants(
+        &self,
+    ) -> Result<(broadcast::Receiver<MarioPantsType>, JoinHandle<()>)> {
+        self.check()?;
+        readings(self, modes::MarioPantsSensor::PANT, 1, |v| {
+            let code = v.first()?.raw.to_f32() as u8;
+            let pants = MarioPantsType::from_u8(code);
+            if pants.is_none() {
+                warn!(target: crate::targets::INPUT, "Unknown Mario pants {code:#04x}");
+            }
+            pants
+        })
+        .await
+    }
+]);
+
+device_trait!(MarioScanner, [
+    /// Ground colors Mario steps on
+    async fn scanned_colors(
+        &self,
+    ) -> Result<(broadcast::Receiver<MarioColor>, JoinHandle<()>)> {
+        self.check()?;
+        readings(self, modes::MarioBarcodeSensor::TAG, 1, |v| {
+            let (barcode, code) = scanned(v)?;
+            if barcode != NOTHING || code == NOTHING {
+                return None;
+            }
+            let color = MarioColor::from_u16(code);
+            if color.is_none() {
+                warn!(target: crate::targets::INPUT, "Unknown Mario color {code:#06x}");
+            }
+            color
+        })
+        .await
+    },
+
+    /// Barcodes of the action tags Mario steps on
+    async fn barcodes(
+        &self,
+    ) -> Result<(broadcast::Receiver<u16>, JoinHandle<()>)> {
+        self.check()?;
+        readings(self, modes::MarioBarcodeSensor::TAG, 1, |v| {
+            let (barcode, color) = scanned(v)?;
+            (color == NOTHING && barcode != NOTHING).then_some(barcode)
+        })
+        .await
+    }
+]);
+
+device_trait!(MarioAccelerometer, [
+    /// Acceleration along each axis, scaled to the range Mario reports for
+    /// the mode
+    async fn mario_acceleration(
+        &self,
+        delta: u32,
+    ) -> Result<(broadcast::Receiver<Vector3>, JoinHandle<()>)> {
+        self.check()?;
+        readings(self, modes::MarioAccelerometer::RAW, delta, |v| match v {
+            [x, y, z, ..] => Some(Vector3 {
+                x: x.si,
+                y: y.si,
+                z: z.si,
+            }),
+            _ => None,
+        })
+        .await
+    },
+
+    /// Gestures as Mario reports them, a bit field whose meaning isn't
+    /// documented
+    async fn gestures(
+        &self,
+    ) -> Result<(broadcast::Receiver<u16>, JoinHandle<()>)> {
+        self.check()?;
+        readings(self, modes::MarioAccelerometer::GEST, 1, |v| {
+            Some(v.first()?.raw.to_f32() as i16 as u16)
+        })
+        .await
+    }
+]);
+
+/// The barcode and color datasets of a scan
+fn scanned(values: &[ScaledValue]) -> Option<(u16, u16)> {
+    match values {
+        [barcode, color, ..] => Some((
+            barcode.raw.to_f32() as i16 as u16,
+            color.raw.to_f32() as i16 as u16,
+        )),
+        _ => None,
+    }
+}
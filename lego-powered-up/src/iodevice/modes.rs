@@ -1,4 +1,7 @@
 #![allow(non_snake_case)]
+pub mod Motor {
+    pub const POWER: u8 = 0;
+}
 pub mod Voltage {
     pub const VLT_L: u8 = 0;
     pub const VLT_S: u8 = 1;
@@ -102,3 +105,84 @@ pub mod UnknownMovehubDevice {
     pub const CANVAS: u8 = 1;
     pub const VAR: u8 = 2;
 }
+pub mod TechnicColorSensor {
+    pub const COLOR: u8 = 0;
+    pub const REFLT: u8 = 1;
+    pub const AMBI: u8 = 2;
+    pub const LIGHT: u8 = 3;
+    pub const RREFL: u8 = 4;
+    pub const RGB_I: u8 = 5;
+    pub const HSV: u8 = 6;
+    pub const SHSV: u8 = 7;
+    pub const DEBUG: u8 = 8;
+    pub const CALIB: u8 = 9;
+}
+pub mod TechnicDistanceSensor {
+    pub const DISTL: u8 = 0;
+    pub const DISTS: u8 = 1;
+    pub const SINGL: u8 = 2;
+    pub const LISTN: u8 = 3;
+    pub const TRAW: u8 = 4;
+    pub const LIGHT: u8 = 5;
+    pub const PING: u8 = 6;
+    pub const ADRAW: u8 = 7;
+    pub const CALIB: u8 = 8;
+}
+pub mod TechnicForceSensor {
+    pub const FORCE: u8 = 0;
+    pub const TOUCH: u8 = 1;
+    pub const TAP: u8 = 2;
+    pub const FPEAK: u8 = 3;
+    pub const FRAW: u8 = 4;
+    pub const FPRAW: u8 = 5;
+    pub const CALIB: u8 = 6;
+}
+pub mod DuploTrainBaseMotor {
+    pub const T_MOT: u8 = 0;
+}
+pub mod DuploTrainBaseSpeaker {
+    pub const MUSIC: u8 = 0;
+    pub const SOUND: u8 = 1;
+    pub const TONE: u8 = 2;
+}
+pub mod DuploTrainBaseColorSensor {
+    pub const COLOR: u8 = 0;
+    pub const C_TAG: u8 = 1;
+    pub const REFLT: u8 = 2;
+    pub const RGB_I: u8 = 3;
+}
+pub mod DuploTrainBaseSpeedometer {
+    pub const SPEED: u8 = 0;
+    pub const COUNT: u8 = 1;
+}
+pub mod MarioAccelerometer {
+    pub const RAW: u8 = 0;
+    pub const GEST: u8 = 1;
+}
+pub mod MarioBarcodeSensor {
+    pub const TAG: u8 = 0;
+    pub const RGB: u8 = 1;
+}
+pub mod MarioPantsSensor {
+    pub const PANT: u8 = 0;
+}
+pub mod Technic3x3ColorLightMatrix {
+    pub const LEV_O: u8 = 0;
+    pub const COL_O: u8 = 1;
+    pub const PIX_O: u8 = 2;
+    pub const TRANS: u8 = 3;
+}
+pub mod ExternalTiltSensor {
+    pub const ANGLE: u8 = 0;
+    pub const TILT: u8 = 1;
+    pub const CRASH: u8 = 2;
+    pub const CAL: u8 = 3;
+}
+pub mod MotionSensor {
+    pub const DETECT: u8 = 0;
+    pub const COUNT: u8 = 1;
+    pub const CAL: u8 = 2;
+}
+pub mod PiezoToneSound {
+    pub const TONE: u8 = 0;
+}
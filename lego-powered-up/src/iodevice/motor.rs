@@ -4,22 +4,25 @@
 //! https://rebrickable.com/parts/22172/motor-xl-powered-up/
 //! And the internal motors in: https://rebrickable.com/parts/26910/hub-move-powered-up-6-x-16-x-4/
 //! The start_power commands should work with train motors.
+//! Two motors of the same kind can be combined into a virtual port, see
+//! ConnectedHub::create_virtual_port, and driven together with
+//! SyncedMotorPair.
 
 use async_trait::async_trait;
 use core::fmt::Debug;
+use core::time::Duration;
 use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
 use tokio::task::JoinHandle;
 
 use super::Basic;
 pub use crate::consts::MotorSensorMode;
 use crate::device_trait;
 use crate::error::{Error, Result};
+use crate::notifications::InputSetupCombinedSubcommand;
 use crate::notifications::{CompletionInfo, StartupInfo};
 pub use crate::notifications::{EndState, Power};
 use crate::notifications::{FeedbackMessage, PortOutputCommandFeedbackFormat};
-use crate::notifications::{
-    InputSetupCombinedSubcommand, PortValueCombinedFormat,
-};
 use crate::notifications::{PortOutputSubcommand, WriteDirectModeDataPayload};
 
 /// State model of a command receiver.
@@ -44,12 +47,87 @@ pub enum BufferState {
     BusyFull,  // Command in progress, buffer full (“Busy/Full”)
 }
 
+/// How a command sent with command feedback ended
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommandOutcome {
+    /// The command ran to completion
+    Completed,
+    /// The command was replaced by another one before completing
+    Discarded,
+}
+
+/// Send a command with feedback requested and wait until the hub reports
+/// it completed or discarded, for at most `timeout`. `rx` must have been
+/// subscribed before calling so that no feedback is missed.
+async fn command_and_wait<D: Basic + ?Sized>(
+    device: &D,
+    mut rx: broadcast::Receiver<PortOutputCommandFeedbackFormat>,
+    subcommand: PortOutputSubcommand,
+    timeout: Duration,
+) -> Result<CommandOutcome> {
+    let port_id = device.port();
+    device
+        .device_command(
+            subcommand,
+            StartupInfo::ExecuteImmediately,
+            CompletionInfo::CommandFeedback,
+        )
+        .await?;
+    let wait = async {
+        let mut started = false;
+        loop {
+            let feedback = match rx.recv().await {
+                Ok(feedback) => feedback,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => {
+                    return Err(Error::HubError(String::from(
+                        "Command feedback channel closed",
+                    )))
+                }
+            };
+            let messages = [Some(feedback.msg1), feedback.msg2, feedback.msg3];
+            for msg in messages.iter().flatten() {
+                if msg.port_id != port_id {
+                    continue;
+                }
+                if let Some(outcome) = command_outcome(msg, &mut started) {
+                    return Ok(outcome);
+                }
+            }
+        }
+    };
+    match tokio::time::timeout(timeout, wait).await {
+        Ok(outcome) => outcome,
+        Err(_) => Err(Error::TimeoutError(format!(
+            "Command on port {} not completed after {:?}",
+            port_id, timeout
+        ))),
+    }
+}
+
+/// Track the feedback for a command on a port. The first feedback after
+/// sending is about our command, which may complete at once. Only a discard
+/// reported along with our command in progress is about the command before
+/// it, replaced by ours.
+fn command_outcome(
+    msg: &FeedbackMessage,
+    started: &mut bool,
+) -> Option<CommandOutcome> {
+    let first = !*started;
+    *started = true;
+    if msg.empty_cmd_completed {
+        return Some(CommandOutcome::Completed);
+    }
+    if msg.discarded && !(first && msg.empty_cmd_in_progress) {
+        return Some(CommandOutcome::Discarded);
+    }
+    None
+}
+
 device_trait!(EncoderMotor, [
-    fn get_rx_combined(&self) -> Result<broadcast::Receiver<PortValueCombinedFormat>>;,
     fn get_rx_feedback(&self) -> Result<broadcast::Receiver<PortOutputCommandFeedbackFormat>>;,
 
     /// Set up handling of command feedback notifications
-    // This supports only single motors for now, synced motors is TODO
     fn cmd_feedback_handler(
         &self,
     ) -> Result<(broadcast::Receiver<CmdReceiverState>, JoinHandle<()>)> {
@@ -115,7 +193,7 @@ device_trait!(EncoderMotor, [
     },
 
     /// Commands
-    // To do: "2" variants of all commands, except done: start_power2
+    // The "2" variants are in SyncedMotorPair
     async fn start_power(&self, power: Power) -> Result<()> {
         self.check()?;
         let subcommand = PortOutputSubcommand::WriteDirectModeData(
@@ -123,11 +201,6 @@ device_trait!(EncoderMotor, [
         );
         self.device_command(subcommand, StartupInfo::ExecuteImmediately, CompletionInfo::NoAction).await
     },
-    async fn start_power2(&self, power1: Power, power2: Power) -> Result<()> {
-        self.check()?;
-        let subcommand = PortOutputSubcommand::StartPower2 { power1, power2 };
-        self.device_command(subcommand, StartupInfo::ExecuteImmediately, CompletionInfo::NoAction).await
-    },
     async fn start_speed(&self, speed: i8, max_power: u8) -> Result<()> {
         self.check()?;
         let subcommand = PortOutputSubcommand::StartSpeed {
@@ -193,6 +266,73 @@ device_trait!(EncoderMotor, [
         self.device_command(subcommand, StartupInfo::ExecuteImmediately, CompletionInfo::NoAction).await
     },
 
+    /// Commands that wait for the hub to report them completed or discarded.
+    /// Command feedback is requested automatically.
+    async fn command_and_wait(
+        &self,
+        subcommand: PortOutputSubcommand,
+        timeout: Duration,
+    ) -> Result<CommandOutcome> {
+        self.check()?;
+        // Subscribe before sending so that no feedback is missed
+        let rx = self.get_rx_feedback()?;
+        command_and_wait(self, rx, subcommand, timeout).await
+    },
+    async fn start_speed_for_degrees_wait(
+        &self,
+        degrees: i32,
+        speed: i8,
+        max_power: u8,
+        end_state: EndState,
+        timeout: Duration,
+    ) -> Result<CommandOutcome> {
+        let subcommand = PortOutputSubcommand::StartSpeedForDegrees {
+            degrees,
+            speed,
+            max_power,
+            end_state,
+            use_acc_profile: true,
+            use_dec_profile: true,
+        };
+        self.command_and_wait(subcommand, timeout).await
+    },
+    async fn start_speed_for_time_wait(
+        &self,
+        time: i16,
+        speed: i8,
+        max_power: u8,
+        end_state: EndState,
+        timeout: Duration,
+    ) -> Result<CommandOutcome> {
+        let subcommand = PortOutputSubcommand::StartSpeedForTime {
+            time,
+            speed,
+            max_power,
+            end_state,
+            use_acc_profile: true,
+            use_dec_profile: true,
+        };
+        self.command_and_wait(subcommand, timeout).await
+    },
+    async fn goto_absolute_position_wait(
+        &self,
+        abs_pos: i32,
+        speed: i8,
+        max_power: u8,
+        end_state: EndState,
+        timeout: Duration,
+    ) -> Result<CommandOutcome> {
+        let subcommand = PortOutputSubcommand::GotoAbsolutePosition {
+            abs_pos,
+            speed,
+            max_power,
+            end_state,
+            use_acc_profile: true,
+            use_dec_profile: true,
+        };
+        self.command_and_wait(subcommand, timeout).await
+    },
+
     /// Command variants with control over StartupInfo and CompletionInfo
     async fn start_power_soc(&self, power: Power, startup: StartupInfo,
         completion: CompletionInfo) -> Result<()> {
@@ -398,7 +538,7 @@ device_trait!(EncoderMotor, [
                             }
                         }
                         else {
-                            eprintln!("Combined mode unexpected length");
+                            warn!(target: crate::targets::INPUT, "Combined mode unexpected length");
                         }
 
                         // Speed primary
@@ -439,3 +579,166 @@ device_trait!(EncoderMotor, [
         }
     }
 ]);
+
+device_trait!(SyncedMotorPair, [
+    fn get_rx_pair_feedback(&self) -> Result<broadcast::Receiver<PortOutputCommandFeedbackFormat>>;,
+
+    /// The ports combined into this virtual port
+    fn motor_ports(&self) -> Result<(u8, u8)>;,
+
+    /// Motor settings
+    async fn preset_encoder2(&self, left_position: i32, right_position: i32) -> Result<()> {
+        self.check()?;
+        let subcommand = PortOutputSubcommand::PresetEncoder2 {
+            left_position,
+            right_position,
+        };
+        self.device_command(subcommand, StartupInfo::BufferIfNecessary, CompletionInfo::NoAction).await
+    },
+
+    /// Commands
+    async fn start_power2(&self, power1: Power, power2: Power) -> Result<()> {
+        self.check()?;
+        let subcommand = PortOutputSubcommand::StartPower2 { power1, power2 };
+        self.device_command(subcommand, StartupInfo::ExecuteImmediately, CompletionInfo::NoAction).await
+    },
+    async fn start_speed2(&self, speed1: i8, speed2: i8, max_power: u8) -> Result<()> {
+        self.check()?;
+        let subcommand = PortOutputSubcommand::StartSpeed2 {
+            speed1,
+            speed2,
+            max_power,
+            use_acc_profile: true,
+            use_dec_profile: true,
+        };
+        self.device_command(subcommand, StartupInfo::ExecuteImmediately, CompletionInfo::NoAction).await
+    },
+    async fn start_speed_for_time2(
+        &self,
+        time: i16,
+        speed_l: i8,
+        speed_r: i8,
+        max_power: u8,
+        end_state: EndState,
+    ) -> Result<()> {
+        self.check()?;
+        let subcommand = PortOutputSubcommand::StartSpeedForTime2 {
+            time,
+            speed_l,
+            speed_r,
+            max_power,
+            end_state,
+            use_acc_profile: true,
+            use_dec_profile: true,
+        };
+        self.device_command(subcommand, StartupInfo::ExecuteImmediately, CompletionInfo::NoAction).await
+    },
+    async fn start_speed_for_degrees2(
+        &self,
+        degrees: i32,
+        speed_l: i8,
+        speed_r: i8,
+        max_power: u8,
+        end_state: EndState,
+    ) -> Result<()> {
+        self.check()?;
+        let subcommand = PortOutputSubcommand::StartSpeedForDegrees2 {
+            degrees,
+            speed_l,
+            speed_r,
+            max_power,
+            end_state,
+            use_acc_profile: true,
+            use_dec_profile: true,
+        };
+        self.device_command(subcommand, StartupInfo::ExecuteImmediately, CompletionInfo::NoAction).await
+    },
+    async fn goto_absolute_position2(
+        &self,
+        abs_pos1: i32,
+        abs_pos2: i32,
+        speed: i8,
+        max_power: u8,
+        end_state: EndState,
+    ) -> Result<()> {
+        self.check()?;
+        let subcommand = PortOutputSubcommand::GotoAbsolutePosition2 {
+            abs_pos1,
+            abs_pos2,
+            speed,
+            max_power,
+            end_state,
+            use_acc_profile: true,
+            use_dec_profile: true,
+        };
+        self.device_command(subcommand, StartupInfo::ExecuteImmediately, CompletionInfo::NoAction).await
+    },
+
+    /// Commands that wait for the hub to report them completed or discarded
+    async fn start_speed_for_time2_wait(
+        &self,
+        time: i16,
+        speed_l: i8,
+        speed_r: i8,
+        max_power: u8,
+        end_state: EndState,
+        timeout: Duration,
+    ) -> Result<CommandOutcome> {
+        self.check()?;
+        let subcommand = PortOutputSubcommand::StartSpeedForTime2 {
+            time,
+            speed_l,
+            speed_r,
+            max_power,
+            end_state,
+            use_acc_profile: true,
+            use_dec_profile: true,
+        };
+        let rx = self.get_rx_pair_feedback()?;
+        command_and_wait(self, rx, subcommand, timeout).await
+    },
+    async fn start_speed_for_degrees2_wait(
+        &self,
+        degrees: i32,
+        speed_l: i8,
+        speed_r: i8,
+        max_power: u8,
+        end_state: EndState,
+        timeout: Duration,
+    ) -> Result<CommandOutcome> {
+        self.check()?;
+        let subcommand = PortOutputSubcommand::StartSpeedForDegrees2 {
+            degrees,
+            speed_l,
+            speed_r,
+            max_power,
+            end_state,
+            use_acc_profile: true,
+            use_dec_profile: true,
+        };
+        let rx = self.get_rx_pair_feedback()?;
+        command_and_wait(self, rx, subcommand, timeout).await
+    },
+    async fn goto_absolute_position2_wait(
+        &self,
+        abs_pos1: i32,
+        abs_pos2: i32,
+        speed: i8,
+        max_power: u8,
+        end_state: EndState,
+        timeout: Duration,
+    ) -> Result<CommandOutcome> {
+        self.check()?;
+        let subcommand = PortOutputSubcommand::GotoAbsolutePosition2 {
+            abs_pos1,
+            abs_pos2,
+            speed,
+            max_power,
+            end_state,
+            use_acc_profile: true,
+            use_dec_profile: true,
+        };
+        let rx = self.get_rx_pair_feedback()?;
+        command_and_wait(self, rx, subcommand, timeout).await
+    }
+]);
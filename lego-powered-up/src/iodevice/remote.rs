@@ -2,18 +2,26 @@
 //! https://rebrickable.com/parts/28739/control-unit-powered-up/
 //! The unit as a whole functions as a hub that connects the
 //! two button devices, hubled and voltage and rssi sensors.
+//!
+//! `RcDevice::remote_events` reports presses, releases, holds, double
+//! clicks and chords of all seven buttons; `Remotes` merges the events of
+//! several remotes.
 
-use crate::Result;
+use crate::{Error, Result};
 
 use async_trait::async_trait;
 use core::fmt::Debug;
-use tokio::sync::broadcast;
+use core::time::Duration;
+use std::collections::{BTreeMap, BTreeSet};
+use tokio::sync::broadcast::{self, error::RecvError};
 use tokio::task::JoinHandle;
+use tokio::time::Instant;
 
+use super::modes;
 use super::Basic;
 use crate::device_trait;
 use crate::notifications::{
-    ButtonState, InputSetupSingle,
+    ButtonState, InformationRequest, InformationType, InputSetupSingle,
     NetworkCommand::{self},
     NotificationMessage, PortValueSingleFormat,
 };
@@ -32,22 +40,381 @@ pub enum RcButtonState {
     GreenUp,
 }
 
+/// The buttons of the remote. The A side is on the left, the B side on
+/// the right, and the green button is the remote's hub button.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RemoteButton {
+    APlus,
+    ARed,
+    AMinus,
+    BPlus,
+    BRed,
+    BMinus,
+    Green,
+}
+
+/// Plus, red and minus button of each side, by port
+const SIDES: [[RemoteButton; 3]; 2] = [
+    [
+        RemoteButton::APlus,
+        RemoteButton::ARed,
+        RemoteButton::AMinus,
+    ],
+    [
+        RemoteButton::BPlus,
+        RemoteButton::BRed,
+        RemoteButton::BMinus,
+    ],
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemoteEvent {
+    Pressed(RemoteButton),
+    Released(RemoteButton),
+    /// The button has been down for the duration. Reported once per
+    /// `RemoteConfig::hold` it stays down.
+    Held(RemoteButton, Duration),
+    /// The button was pressed again within `RemoteConfig::double_click`
+    /// of the press before. Follows the `Pressed` event.
+    DoubleClick(RemoteButton),
+    /// A button went down while others were down, or several went down at
+    /// once: every button now down. Follows the `Pressed` events.
+    Chord(BTreeSet<RemoteButton>),
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct RemoteConfig {
+    /// Mode of the button devices, one of `modes::RemoteButtons`. Only
+    /// KEYD and KEYSD report every button down, so only they see chords
+    /// within a side. RCKEY reports the one button down, KEYA the button
+    /// last pressed and KEYR the button last released; with KEYA presses
+    /// are never followed by releases and with KEYR the reverse.
+    pub mode: u8,
+    /// Time after which a button down is reported held, and again after
+    /// each further period. `Duration::ZERO` reports no holds.
+    pub hold: Duration,
+    /// Longest time between two presses reported as a double click
+    pub double_click: Duration,
+}
+
+impl Default for RemoteConfig {
+    fn default() -> Self {
+        Self {
+            mode: modes::RemoteButtons::KEYSD,
+            hold: Duration::from_millis(500),
+            double_click: Duration::from_millis(300),
+        }
+    }
+}
+
+/// Ask the button device on `port_id` to report its value again
+fn value_request(port_id: u8) -> NotificationMessage {
+    NotificationMessage::PortInformationRequest(InformationRequest {
+        port_id,
+        information_type: InformationType::PortValue,
+    })
+}
+
+/// Apply a value reported by the button device of a side to the set of
+/// buttons down
+fn apply(
+    mode: u8,
+    side: &[RemoteButton; 3],
+    data: &[i8],
+    down: &mut BTreeSet<RemoteButton>,
+) {
+    let [plus, red, minus] = *side;
+    let named = |value: Option<&i8>| match value {
+        Some(1) => Some(plus),
+        Some(127) => Some(red),
+        Some(-1) => Some(minus),
+        _ => None,
+    };
+    let side_down: Vec<RemoteButton> = match mode {
+        modes::RemoteButtons::KEYA => {
+            down.extend(named(data.first()));
+            return;
+        }
+        modes::RemoteButtons::KEYR => {
+            if let Some(button) = named(data.first()) {
+                down.remove(&button);
+            }
+            return;
+        }
+        // One bit per button
+        modes::RemoteButtons::KEYD => {
+            let bits = data.first().copied().unwrap_or_default();
+            side.iter()
+                .enumerate()
+                .filter(|(i, _)| bits & (1 << i) != 0)
+                .map(|(_, button)| *button)
+                .collect()
+        }
+        // One dataset per button
+        modes::RemoteButtons::KEYSD => data
+            .iter()
+            .zip(side)
+            .filter(|(value, _)| **value != 0)
+            .map(|(_, button)| *button)
+            .collect(),
+        _ => named(data.first()).into_iter().collect(),
+    };
+    for button in side {
+        down.remove(button);
+    }
+    down.extend(side_down);
+}
+
+/// Turns the set of buttons down into events
+#[derive(Debug)]
+struct Tracker {
+    config: RemoteConfig,
+    /// When each button down went down, and how often it was reported held
+    down: BTreeMap<RemoteButton, (Instant, u32)>,
+    /// Last press of each button that may become a double click
+    clicks: BTreeMap<RemoteButton, Instant>,
+}
+
+impl Tracker {
+    fn new(config: RemoteConfig) -> Self {
+        Self {
+            config,
+            down: Default::default(),
+            clicks: Default::default(),
+        }
+    }
+
+    fn update(
+        &mut self,
+        buttons: &BTreeSet<RemoteButton>,
+        now: Instant,
+    ) -> Vec<RemoteEvent> {
+        let mut events = Vec::new();
+        let released: Vec<RemoteButton> = self
+            .down
+            .keys()
+            .filter(|button| !buttons.contains(button))
+            .copied()
+            .collect();
+        for button in released {
+            self.down.remove(&button);
+            events.push(RemoteEvent::Released(button));
+        }
+        let mut pressed = false;
+        for &button in buttons {
+            if self.down.contains_key(&button) {
+                continue;
+            }
+            pressed = true;
+            self.down.insert(button, (now, 0));
+            events.push(RemoteEvent::Pressed(button));
+            match self.clicks.remove(&button) {
+                Some(at) if now - at <= self.config.double_click => {
+                    events.push(RemoteEvent::DoubleClick(button))
+                }
+                _ => {
+                    self.clicks.insert(button, now);
+                }
+            }
+        }
+        if pressed && buttons.len() > 1 {
+            events.push(RemoteEvent::Chord(buttons.clone()));
+        }
+        events
+    }
+
+    /// When a button down is next to be reported held
+    fn next_hold(&self) -> Option<Instant> {
+        if self.config.hold.is_zero() {
+            return None;
+        }
+        self.down
+            .values()
+            .map(|(since, holds)| *since + self.config.hold * (holds + 1))
+            .min()
+    }
+
+    fn hold(&mut self, now: Instant) -> Vec<RemoteEvent> {
+        let hold = self.config.hold.as_nanos();
+        let mut events = Vec::new();
+        for (button, (since, holds)) in self.down.iter_mut() {
+            let held = now - *since;
+            let periods = (held.as_nanos() / hold) as u32;
+            if periods > *holds {
+                *holds = periods;
+                events.push(RemoteEvent::Held(*button, held));
+            }
+        }
+        events
+    }
+}
+
+/// An event of one of the remotes in `Remotes`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaggedRemoteEvent {
+    /// The name the remote was added with
+    pub remote: String,
+    pub event: RemoteEvent,
+}
+
+/// The events of several remotes in one stream, tagged with the remote
+/// they came from. Stops reporting when dropped.
+#[derive(Debug)]
+pub struct Remotes {
+    config: RemoteConfig,
+    sender: broadcast::Sender<TaggedRemoteEvent>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Remotes {
+    pub fn new(config: RemoteConfig) -> Self {
+        Self {
+            config,
+            sender: broadcast::channel(64).0,
+            tasks: Vec::new(),
+        }
+    }
+
+    /// Report the events of the remote `device` is on, tagged with `name`
+    pub async fn add(
+        &mut self,
+        name: impl Into<String>,
+        device: &impl RcDevice,
+    ) -> Result<()> {
+        let (mut events, task) = device.remote_events(self.config).await?;
+        let remote = name.into();
+        let sender = self.sender.clone();
+        self.tasks.push(task);
+        self.tasks.push(tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => {
+                        let _ = sender.send(TaggedRemoteEvent {
+                            remote: remote.clone(),
+                            event,
+                        });
+                    }
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        }));
+        Ok(())
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<TaggedRemoteEvent> {
+        self.sender.subscribe()
+    }
+}
+
+impl Drop for Remotes {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
 device_trait!(RcDevice, [
     fn get_rx_pvs(&self) -> Result<broadcast::Receiver<PortValueSingleFormat>>;,
     fn get_rx_nwc(&self) -> Result<broadcast::Receiver<NetworkCommand>>;,
 
     async fn remote_buttons_enable_by_port(&self, port_id: u8) -> Result<()> {
+        self.remote_buttons_enable_mode(port_id, modes::RemoteButtons::RCKEY)
+            .await
+    },
+
+    async fn remote_buttons_enable_mode(&self, port_id: u8, mode: u8) -> Result<()> {
         self.check()?;
         let msg =
             NotificationMessage::PortInputFormatSetupSingle(InputSetupSingle {
                 port_id,
-                mode: 0,
+                mode,
                 delta: 1,
                 notification_enabled: true,
             });
         self.commit(msg).await
     },
 
+    /// Events of all seven buttons of the remote this device is on
+    async fn remote_events(
+        &self,
+        config: RemoteConfig,
+    ) -> Result<(broadcast::Receiver<RemoteEvent>, JoinHandle<()>)> {
+        if config.mode > modes::RemoteButtons::KEYSD {
+            return Err(Error::HubError(format!(
+                "No remote button mode {}",
+                config.mode
+            )));
+        }
+        // Subscribe first so the values reported on enabling aren't missed
+        let mut values = self.get_rx_pvs()?;
+        let mut network = self.get_rx_nwc()?;
+        for port_id in 0..SIDES.len() as u8 {
+            self.remote_buttons_enable_mode(port_id, config.mode).await?;
+        }
+
+        let (tx, rx) = broadcast::channel::<RemoteEvent>(64);
+        let tokens = self.tokens();
+        let task = tokio::spawn(async move {
+            let mut tracker = Tracker::new(config);
+            let mut down = BTreeSet::new();
+            loop {
+                let hold = tracker.next_hold();
+                let events = tokio::select! {
+                    msg = values.recv() => match msg {
+                        Ok(msg) => {
+                            let Some(side) = SIDES.get(msg.port_id as usize) else {
+                                continue;
+                            };
+                            apply(config.mode, side, &msg.data, &mut down);
+                            tracker.update(&down, Instant::now())
+                        }
+                        // Values were dropped, releases among them maybe:
+                        // release the buttons of both sides and read them
+                        // again
+                        Err(RecvError::Lagged(_)) => {
+                            down.retain(|b| *b == RemoteButton::Green);
+                            for port_id in 0..SIDES.len() as u8 {
+                                let msg = value_request(port_id);
+                                let _ = crate::hubs::send(tokens.clone(), msg)
+                                    .await;
+                            }
+                            tracker.update(&down, Instant::now())
+                        }
+                        Err(RecvError::Closed) => break,
+                    },
+                    msg = network.recv() => match msg {
+                        Ok(NetworkCommand::ConnectionRequest(ButtonState::Up)) => {
+                            down.insert(RemoteButton::Green);
+                            tracker.update(&down, Instant::now())
+                        }
+                        Ok(NetworkCommand::ConnectionRequest(ButtonState::Released)) => {
+                            down.remove(&RemoteButton::Green);
+                            tracker.update(&down, Instant::now())
+                        }
+                        // The green button can't be read again, so it
+                        // stays released until next pressed
+                        Err(RecvError::Lagged(_)) => {
+                            down.remove(&RemoteButton::Green);
+                            tracker.update(&down, Instant::now())
+                        }
+                        Ok(_) => continue,
+                        Err(RecvError::Closed) => break,
+                    },
+                    _ = tokio::time::sleep_until(hold.unwrap_or_else(Instant::now)), if hold.is_some() => {
+                        tracker.hold(Instant::now())
+                    }
+                };
+                for event in events {
+                    let _ = tx.send(event);
+                }
+            }
+        });
+
+        Ok((rx, task))
+    },
+
     async fn remote_connect(
         &self,
     ) -> Result<(broadcast::Receiver<RcButtonState>, JoinHandle<()>)> {
@@ -4,14 +4,41 @@
 
 use async_trait::async_trait;
 use core::fmt::Debug;
+use std::fmt;
 use tokio::sync::broadcast;
 use tokio::task::JoinHandle;
 
+use super::definition::ScaledValue;
 use super::Basic;
 use crate::device_trait;
 use crate::error::{Error, Result};
 use crate::notifications::DatasetType;
 
+/// The datasets of a value reported in a mode, scaled by the mode's ranges.
+/// Displays as the SI values with the mode's decimals and unit symbol.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModeValue {
+    pub mode: u8,
+    pub values: Vec<ScaledValue>,
+    pub symbol: String,
+    pub decimals: u8,
+}
+impl fmt::Display for ModeValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let decimals = self.decimals as usize;
+        let values: Vec<String> = self
+            .values
+            .iter()
+            .map(|v| format!("{:.*}", decimals, v.si))
+            .collect();
+        write!(f, "{}", values.join(", "))?;
+        if !self.symbol.is_empty() {
+            write!(f, " {}", self.symbol)?;
+        }
+        Ok(())
+    }
+}
+
 device_trait!(GenericSensor, [
     fn check_dataset(&self, mode: u8, datasettype: DatasetType) -> Result<()>;,
 
@@ -154,6 +181,45 @@ device_trait!(GenericSensor, [
         }
     },
 
+    /// Values of any mode, decoded by its value format and scaled by its
+    /// ranges
+    async fn enable_scaled_sensor(
+        &self,
+        mode: u8,
+        delta: u32,
+    ) -> Result<(broadcast::Receiver<ModeValue>, JoinHandle<()>)> {
+        let Some(port_mode) = self.def().modes().get(&mode).cloned() else {
+            return Err(Error::NoneError(String::from("Mode not found")));
+        };
+        let mut rx_from_main = self.get_rx()?;
+        self.device_mode(mode, delta, true).await?;
+
+        // Set up channel
+        let port_id = self.port();
+        let (tx, rx) = broadcast::channel::<ModeValue>(64);
+        let task = tokio::spawn(async move {
+            while let Ok(msg) = rx_from_main.recv().await {
+                if msg.port_id != port_id {
+                    continue;
+                }
+                let values = match port_mode.decode(&msg) {
+                    Ok(values) => values,
+                    Err(e) => {
+                        warn!(target: crate::targets::INPUT, "Sensor value: {e}");
+                        continue;
+                    }
+                };
+                let _ = tx.send(ModeValue {
+                    mode,
+                    values,
+                    symbol: port_mode.symbol.clone(),
+                    decimals: port_mode.value_format.decimals,
+                });
+            }
+        });
+        Ok((rx, task))
+    },
+
     fn raw_channel(
         &self,
     ) -> Result<(broadcast::Receiver<Vec<i8>>, JoinHandle<()>)> {
@@ -179,3 +245,45 @@ device_trait!(GenericSensor, [
     }
 
 ]);
+
+/// Enable a mode and map its scaled datasets to readings, skipping values
+/// that don't map to one
+pub(super) async fn readings<D, T, F>(
+    device: &D,
+    mode: u8,
+    delta: u32,
+    mut reading: F,
+) -> Result<(broadcast::Receiver<T>, JoinHandle<()>)>
+where
+    D: Basic + ?Sized,
+    T: Clone + Debug + Send + 'static,
+    F: FnMut(&[ScaledValue]) -> Option<T> + Send + 'static,
+{
+    let Some(port_mode) = device.def().modes().get(&mode).cloned() else {
+        return Err(Error::NoneError(String::from("Mode not found")));
+    };
+    let mut rx_from_main = device.get_rx()?;
+    device.device_mode(mode, delta, true).await?;
+
+    // Set up channel
+    let port_id = device.port();
+    let (tx, rx) = broadcast::channel::<T>(64);
+    let task = tokio::spawn(async move {
+        while let Ok(msg) = rx_from_main.recv().await {
+            if msg.port_id != port_id {
+                continue;
+            }
+            let values = match port_mode.decode(&msg) {
+                Ok(values) => values,
+                Err(e) => {
+                    warn!(target: crate::targets::INPUT, "Sensor value: {e}");
+                    continue;
+                }
+            };
+            if let Some(reading) = reading(&values) {
+                let _ = tx.send(reading);
+            }
+        }
+    });
+    Ok((rx, task))
+}
@@ -0,0 +1,115 @@
+//! Support for the speaker in the
+//! https://rebrickable.com/sets/10874-1/steam-train/ Duplo train base,
+//! and a sequencer playing notes by frequency and duration on whatever
+//! device can play them.
+
+use async_trait::async_trait;
+use core::fmt::Debug;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use super::modes;
+use super::Basic;
+pub use crate::consts::DuploTrainBaseSound;
+use crate::device_trait;
+use crate::error::{Error, Result};
+use crate::hubs::Tokens;
+use crate::notifications::{
+    CompletionInfo, NotificationMessage, PortOutputSubcommand, StartupInfo,
+    WriteDirectModeDataPayload,
+};
+
+/// A tone of `frequency` Hz, or silence when the frequency is 0
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Note {
+    pub frequency: u16,
+    pub duration: Duration,
+}
+impl Note {
+    pub fn new(frequency: u16, duration: Duration) -> Self {
+        Self {
+            frequency,
+            duration,
+        }
+    }
+
+    pub fn rest(duration: Duration) -> Self {
+        Self::new(0, duration)
+    }
+}
+
+/// Plays queued notes one after another in a background task, so queueing
+/// never waits for the notes before it
+#[derive(Debug)]
+pub struct Sequencer {
+    notes: mpsc::UnboundedSender<Note>,
+    task: JoinHandle<Result<()>>,
+}
+impl Sequencer {
+    /// Start playing the queued notes as the messages `play` makes of
+    /// them, sent to the hub behind `tokens`. A rest sends nothing.
+    pub fn new(
+        tokens: Tokens,
+        play: impl Fn(&Note) -> NotificationMessage + Send + 'static,
+    ) -> Self {
+        let (notes, mut rx) = mpsc::unbounded_channel::<Note>();
+        let task = tokio::spawn(async move {
+            while let Some(note) = rx.recv().await {
+                if note.frequency != 0 {
+                    crate::hubs::send(tokens.clone(), play(&note)).await?;
+                }
+                tokio::time::sleep(note.duration).await;
+            }
+            Ok(())
+        });
+        Self { notes, task }
+    }
+
+    /// Play `note` after the ones already queued
+    pub fn queue(&self, note: Note) -> Result<()> {
+        self.notes
+            .send(note)
+            .map_err(|_| Error::HubError(String::from("Sequencer stopped")))
+    }
+
+    pub fn queue_all(
+        &self,
+        notes: impl IntoIterator<Item = Note>,
+    ) -> Result<()> {
+        notes.into_iter().try_for_each(|note| self.queue(note))
+    }
+
+    /// Stop taking notes. The task ends once the queued notes are played,
+    /// or with the error if sending one fails.
+    pub fn finish(self) -> JoinHandle<Result<()>> {
+        self.task
+    }
+
+    /// Drop the queued notes. The note playing now is not cut short.
+    pub fn stop(self) {
+        self.task.abort();
+    }
+}
+
+device_trait!(Speaker, [
+    /// Play one of the sounds built into the train base
+    async fn play_sound(&self, sound: DuploTrainBaseSound) -> Result<()> {
+        self.check()?;
+        self.device_mode(modes::DuploTrainBaseSpeaker::SOUND, 1, true).await?;
+        let subcommand = PortOutputSubcommand::WriteDirectModeData(
+            WriteDirectModeDataPayload::PlayDuploSound(sound),
+        );
+        self.device_command(subcommand, StartupInfo::ExecuteImmediately, CompletionInfo::NoAction).await
+    },
+
+    /// Play one of the tones built into the train base, numbered from 0
+    async fn play_tone(&self, tone: u8) -> Result<()> {
+        self.check()?;
+        self.device_mode(modes::DuploTrainBaseSpeaker::TONE, 1, true).await?;
+        let subcommand = PortOutputSubcommand::WriteDirectModeData(
+            WriteDirectModeDataPayload::PlayDuploTone(tone),
+        );
+        self.device_command(subcommand, StartupInfo::ExecuteImmediately, CompletionInfo::NoAction).await
+    }
+]);
@@ -0,0 +1,136 @@
+//! Support for the tilt sensor built into the
+//! https://rebrickable.com/sets/88006-1/move-hub/
+
+use async_trait::async_trait;
+use core::fmt::Debug;
+use num_traits::FromPrimitive;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use super::imu::Vector3;
+use super::modes;
+use super::sensor::readings;
+use super::Basic;
+use crate::device_trait;
+use crate::error::Result;
+use crate::notifications::{
+    CalibrationOrientation, CompletionInfo, Orientation, PortOutputSubcommand,
+    StartupInfo, WriteDirectModeDataPayload,
+};
+
+/// Tilt angles about the hub's x and y axes
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct TiltAngle {
+    pub x: f32,
+    pub y: f32,
+}
+
+device_trait!(InternalTilt, [
+    /// Tilt angles in degrees
+    async fn tilt_angle(
+        &self,
+        delta: u32,
+    ) -> Result<(broadcast::Receiver<TiltAngle>, JoinHandle<()>)> {
+        self.check()?;
+        readings(self, modes::InternalTilt::ANGLE, delta, |v| match v {
+            [x, y, ..] => Some(TiltAngle { x: x.si, y: y.si }),
+            _ => None,
+        })
+        .await
+    },
+
+    /// The side of the hub facing down
+    async fn tilt_orientation(
+        &self,
+    ) -> Result<(broadcast::Receiver<Orientation>, JoinHandle<()>)> {
+        self.check()?;
+        readings(self, modes::InternalTilt::ORINT, 1, |v| {
+            Orientation::from_i8(v.first()?.raw.to_f32() as i8)
+        })
+        .await
+    },
+
+    /// Number of impacts detected
+    async fn impact_count(
+        &self,
+        delta: u32,
+    ) -> Result<(broadcast::Receiver<u32>, JoinHandle<()>)> {
+        self.check()?;
+        readings(self, modes::InternalTilt::IMPCT, delta, |v| {
+            v.first().map(|count| count.raw.to_f32() as u32)
+        })
+        .await
+    },
+
+    /// Acceleration along each axis, scaled to the range the hub reports
+    /// for the mode
+    async fn tilt_acceleration(
+        &self,
+        delta: u32,
+    ) -> Result<(broadcast::Receiver<Vector3>, JoinHandle<()>)> {
+        self.check()?;
+        readings(self, modes::InternalTilt::ACCEL, delta, |v| match v {
+            [x, y, z, ..] => Some(Vector3 {
+                x: x.si,
+                y: y.si,
+                z: z.si,
+            }),
+            _ => None,
+        })
+        .await
+    },
+
+    /// Set the impact count
+    async fn preset_impact_count(&self, count: i32) -> Result<()> {
+        self.check()?;
+        self.tilt_config(WriteDirectModeDataPayload::TiltImpactPreset(count))
+            .await
+    },
+
+    /// Set which side of the hub counts as the bottom
+    async fn set_orientation_reference(
+        &self,
+        orientation: Orientation,
+    ) -> Result<()> {
+        self.check()?;
+        self.tilt_config(WriteDirectModeDataPayload::TiltConfigOrientation(
+            orientation,
+        ))
+        .await
+    },
+
+    /// Set how hard a knock must be to count as an impact, and how long
+    /// to wait before counting another one
+    async fn set_impact_threshold(
+        &self,
+        impact_threshold: i8,
+        bump_holdoff: i8,
+    ) -> Result<()> {
+        self.check()?;
+        self.tilt_config(WriteDirectModeDataPayload::TiltConfigImpact {
+            impact_threshold,
+            bump_holdoff,
+        })
+        .await
+    },
+
+    /// Calibrate the sensor with the hub in the given position
+    async fn factory_calibrate(
+        &self,
+        orientation: CalibrationOrientation,
+    ) -> Result<()> {
+        self.check()?;
+        self.tilt_config(WriteDirectModeDataPayload::TiltFactoryCalibration(
+            orientation as i8,
+        ))
+        .await
+    },
+
+    async fn tilt_config(
+        &self,
+        payload: WriteDirectModeDataPayload,
+    ) -> Result<()> {
+        let subcommand = PortOutputSubcommand::WriteDirectModeData(payload);
+        self.device_command(subcommand, StartupInfo::ExecuteImmediately, CompletionInfo::NoAction).await
+    }
+]);
@@ -0,0 +1,136 @@
+//! Support for the devices of the WeDo 2.0 kit:
+//! https://rebrickable.com/parts/45303/simple-medium-linear-motor/
+//! https://rebrickable.com/parts/45305/tilt-sensor/
+//! https://rebrickable.com/parts/45304/motion-sensor/
+//! and the piezo buzzer built into the hub, which plays tones by frequency.
+//! The motor is also the one in Powered Up trains, and works the same on
+//! the other hubs.
+
+use async_trait::async_trait;
+use core::fmt::Debug;
+use num_traits::FromPrimitive;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+
+use super::modes;
+use super::sensor::readings;
+use super::speaker::{Note, Sequencer};
+use super::tilt::TiltAngle;
+use super::Basic;
+pub use crate::consts::TiltDirection;
+use crate::device_trait;
+use crate::error::Result;
+use crate::notifications::{
+    CompletionInfo, NotificationMessage, PortOutputCommandFormat,
+    PortOutputSubcommand, Power, StartupInfo, WriteDirectModeDataPayload,
+};
+
+/// The tone write, which `Wedo2Transport` turns into the hub's own tone
+/// command: frequency in Hz and duration in ms
+fn tone_write(port_id: u8, note: &Note) -> NotificationMessage {
+    let duration = note.duration.as_millis().min(u16::MAX as u128) as u16;
+    let mut data = note.frequency.to_le_bytes().to_vec();
+    data.extend(duration.to_le_bytes());
+    NotificationMessage::PortOutputCommand(PortOutputCommandFormat {
+        port_id,
+        startup_info: StartupInfo::ExecuteImmediately,
+        completion_info: CompletionInfo::NoAction,
+        subcommand: PortOutputSubcommand::WriteDirectModeData(
+            WriteDirectModeDataPayload::Raw {
+                mode: modes::PiezoToneSound::TONE,
+                data,
+            },
+        ),
+    })
+}
+
+device_trait!(SimpleMotor, [
+    /// Run the motor at `power`, -100 to 100. 0 lets it run down.
+    async fn set_power(&self, power: i8) -> Result<()> {
+        self.check()?;
+        self.simple_power(Power::from_i8(power.clamp(-100, 100))?).await
+    },
+
+    async fn brake(&self) -> Result<()> {
+        self.check()?;
+        self.simple_power(Power::Brake).await
+    },
+
+    async fn simple_power(&self, power: Power) -> Result<()> {
+        let subcommand = PortOutputSubcommand::WriteDirectModeData(
+            WriteDirectModeDataPayload::StartPower(power),
+        );
+        self.device_command(subcommand, StartupInfo::ExecuteImmediately, CompletionInfo::NoAction).await
+    }
+]);
+
+device_trait!(ExternalTilt, [
+    /// Tilt angles in degrees, up to 45 each way
+    async fn external_tilt(
+        &self,
+        delta: u32,
+    ) -> Result<(broadcast::Receiver<TiltAngle>, JoinHandle<()>)> {
+        self.check()?;
+        readings(self, modes::ExternalTiltSensor::ANGLE, delta, |v| match v {
+            [x, y, ..] => Some(TiltAngle { x: x.si, y: y.si }),
+            _ => None,
+        })
+        .await
+    },
+
+    /// The direction the sensor leans, reported when it changes
+    async fn tilt_direction(
+        &self,
+    ) -> Result<(broadcast::Receiver<TiltDirection>, JoinHandle<()>)> {
+        self.check()?;
+        readings(self, modes::ExternalTiltSensor::TILT, 1, |v| {
+            TiltDirection::from_u8(v.first()?.raw.to_f32() as u8)
+        })
+        .await
+    }
+]);
+
+device_trait!(MotionSensor, [
+    /// Distance to the nearest object, 0 (touching) to 10 (nothing seen)
+    async fn proximity(
+        &self,
+        delta: u32,
+    ) -> Result<(broadcast::Receiver<u8>, JoinHandle<()>)> {
+        self.check()?;
+        readings(self, modes::MotionSensor::DETECT, delta, |v| {
+            v.first().map(|distance| distance.raw.to_f32() as u8)
+        })
+        .await
+    },
+
+    /// Number of times something passed in front of the sensor
+    async fn motion_count(
+        &self,
+        delta: u32,
+    ) -> Result<(broadcast::Receiver<u32>, JoinHandle<()>)> {
+        self.check()?;
+        readings(self, modes::MotionSensor::COUNT, delta, |v| {
+            v.first().map(|count| count.raw.to_f32() as u32)
+        })
+        .await
+    }
+]);
+
+device_trait!(Buzzer, [
+    /// Start playing `note`. Returns once the command is sent, a rest
+    /// sends nothing.
+    async fn play_note(&self, note: Note) -> Result<()> {
+        self.check()?;
+        if note.frequency == 0 {
+            return Ok(());
+        }
+        self.commit(tone_write(self.port(), &note)).await
+    },
+
+    /// Start a sequencer playing on the buzzer
+    fn sequencer(&self) -> Result<Sequencer> {
+        self.check()?;
+        let port_id = self.port();
+        Ok(Sequencer::new(self.tokens(), move |note| tone_write(port_id, note)))
+    }
+]);
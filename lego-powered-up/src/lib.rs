@@ -1,7 +1,7 @@
 pub use btleplug;
 use btleplug::api::{
     Central, CentralEvent, Manager as _, Peripheral as _, PeripheralProperties,
-    ScanFilter, ValueNotification,
+    ScanFilter,
 };
 use btleplug::platform::{Adapter, Manager, PeripheralId};
 use tokio_util::sync::CancellationToken;
@@ -10,12 +10,14 @@ use tokio_util::sync::CancellationToken;
 use core::time::Duration;
 pub use futures;
 use futures::{stream::StreamExt, Stream};
-use hubs::HubNotification;
+use hubs::HubEvent;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use tokio::sync::broadcast;
 use tokio::sync::Mutex;
+use tracing::Instrument;
 #[macro_use]
-extern crate log;
+extern crate tracing;
 
 // nostd
 use core::fmt::Debug;
@@ -24,26 +26,36 @@ use num_traits::FromPrimitive;
 
 // Crate
 pub mod consts;
+pub mod discovery;
 pub mod error;
 pub mod hubs;
 pub mod iodevice;
 pub mod notifications;
+pub mod pool;
 pub mod setup;
+#[cfg(any(test, feature = "sim"))]
+pub mod sim;
+pub mod targets;
+pub mod transport;
 
 pub use crate::consts::IoTypeId;
 pub use crate::iodevice::IoDevice;
 pub use hubs::Hub;
 
-use consts::{BLEManufacturerData, HubType};
+use consts::{BLEManufacturerData, HubPropertyOperation, HubType};
+use discovery::{Discovery, DiscoveryEvent};
 pub use error::{Error, OptionContext, Result};
+use hubs::properties::{self, HubPropertyChannels};
+use hubs::readiness::{Readiness, FENCE_PROPERTY};
 use notifications::{
     NetworkCommand, PortOutputCommandFeedbackFormat, PortValueCombinedFormat,
     PortValueSingleFormat,
 };
+use transport::{BtleTransport, FrameStream, Wedo2Transport};
 
 pub type HubMutex = Arc<Mutex<Box<dyn Hub>>>;
-type NotificationStream = Pin<Box<dyn Stream<Item = ValueNotification> + Send>>;
 
+#[derive(Clone)]
 pub struct PoweredUp {
     adapter: Adapter,
 }
@@ -81,8 +93,7 @@ impl PoweredUp {
     }
 
     pub async fn run(&mut self) -> Result<()> {
-        self.adapter.start_scan(ScanFilter::default()).await?;
-        Ok(())
+        start_scan(&self.adapter, ScanFilter::default()).await
     }
 
     pub async fn find_hub(&mut self) -> Result<Option<DiscoveredHub>> {
@@ -114,88 +125,132 @@ impl PoweredUp {
         self.wait_for_hub_filter(HubFilter::Null).await
     }
 
+    /// The first hub found that matches `filter`. Scans until one is
+    /// found; fails if the adapter stops reporting events before that.
     pub async fn wait_for_hub_filter(
         &mut self,
         filter: HubFilter,
     ) -> Result<DiscoveredHub> {
-        let mut events = self.adapter.events().await?;
-        // self.adapter.start_scan(ScanFilter::default()).await?;
-        self.adapter.start_scan(scanfilter()).await?;
-        while let Some(event) = events.next().await {
-            let CentralEvent::DeviceDiscovered(id) = event else {
+        let mut discovery = self.discover().await?;
+        while let Some(event) = discovery.next().await {
+            let DiscoveryEvent::Found(hub) = event else {
                 continue;
             };
-            // get peripheral info
-            let peripheral = self.adapter.peripheral(&id).await?;
-            // println!("{:?}", peripheral.properties().await?);
-            let Some(props) = peripheral.properties().await? else {
-                continue;
-            };
-            if let Some(hub_type) = identify_hub(&props).await? {
-                let hub = DiscoveredHub {
-                    hub_type,
-                    addr: id,
-                    name: props
-                        .local_name
-                        .unwrap_or_else(|| "unknown".to_string()),
-                };
-                if filter.matches(&hub) {
-                    self.adapter.stop_scan().await?;
-                    return Ok(hub);
-                }
+            if filter.matches(&hub) {
+                discovery.stop().await?;
+                return Ok(hub);
             }
         }
-        panic!()
+        Err(Error::NoneError(String::from(
+            "Scan ended before a hub was found",
+        )))
     }
 
+    /// Like `wait_for_hub_filter`, failing with a `TimeoutError` if no
+    /// matching hub is found within `timeout`
+    pub async fn wait_for_hub_filter_timeout(
+        &mut self,
+        filter: HubFilter,
+        timeout: Duration,
+    ) -> Result<DiscoveredHub> {
+        tokio::time::timeout(timeout, self.wait_for_hub_filter(filter))
+            .await
+            .map_err(|_| {
+                Error::TimeoutError(format!("No hub found in {:?}", timeout))
+            })?
+    }
+
+    /// The first `count` hubs found that match `filter`. Scans until they
+    /// are found; fails if the adapter stops reporting events before that.
     pub async fn wait_for_hubs_filter(
         &mut self,
         filter: HubFilter,
         count: &u8,
     ) -> Result<Vec<DiscoveredHub>> {
-        let mut events = self.adapter.events().await?;
+        let mut discovery = self.discover().await?;
         let mut hubs = Vec::new();
-        self.adapter.start_scan(scanfilter()).await?;
-        while let Some(event) = events.next().await {
-            let CentralEvent::DeviceDiscovered(id) = event else {
-                continue;
-            };
-            // get peripheral info
-            let peripheral = self.adapter.peripheral(&id).await?;
-            // println!("{:?}", peripheral.properties().await?);
-            let Some(props) = peripheral.properties().await? else {
-                continue;
-            };
-            if let Some(hub_type) = identify_hub(&props).await? {
-                let hub = DiscoveredHub {
-                    hub_type,
-                    addr: id,
-                    name: props
-                        .local_name
-                        .unwrap_or_else(|| "unknown".to_string()),
-                };
-                if filter.matches(&hub) {
-                    hubs.push(hub);
+        while hubs.len() < *count as usize {
+            match discovery.next().await {
+                Some(DiscoveryEvent::Found(hub)) if filter.matches(&hub) => {
+                    hubs.push(hub)
                 }
-                if hubs.len() == *count as usize {
-                    self.adapter.stop_scan().await?;
-                    return Ok(hubs);
+                Some(_) => continue,
+                None => {
+                    return Err(Error::NoneError(format!(
+                        "Scan ended after finding {} of {} hubs",
+                        hubs.len(),
+                        count
+                    )))
                 }
             }
         }
-        panic!()
+        discovery.stop().await?;
+        Ok(hubs)
+    }
+
+    /// Hubs matching `filter` found within `timeout`, at most `count`.
+    /// Returns as soon as `count` are found, or with those found so far
+    /// when time is up or the adapter stops reporting events.
+    pub async fn discover_hubs(
+        &mut self,
+        filter: HubFilter,
+        count: usize,
+        timeout: Duration,
+    ) -> Result<Vec<DiscoveredHub>> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut discovery = self.discover().await?;
+        let mut hubs = Vec::new();
+        while hubs.len() < count {
+            match tokio::time::timeout_at(deadline, discovery.next()).await {
+                Ok(Some(DiscoveryEvent::Found(hub)))
+                    if filter.matches(&hub) =>
+                {
+                    hubs.push(hub)
+                }
+                Ok(Some(_)) => continue,
+                Ok(None) | Err(_) => break,
+            }
+        }
+        discovery.stop().await?;
+        Ok(hubs)
+    }
+
+    /// Start scanning, reporting each hub found once, its signal strength
+    /// as it changes, and hubs not heard from for `DEFAULT_LOST_AFTER`.
+    /// Scanning stops when the returned `Discovery` is dropped.
+    pub async fn discover(&self) -> Result<Discovery> {
+        self.discover_lost_after(discovery::DEFAULT_LOST_AFTER)
+            .await
+    }
+
+    /// Like `discover`, reporting hubs as lost once they haven't been heard
+    /// from for `lost_after`
+    pub async fn discover_lost_after(
+        &self,
+        lost_after: Duration,
+    ) -> Result<Discovery> {
+        Discovery::start(self.adapter.clone(), lost_after).await
     }
 
     pub async fn create_hub(
         &mut self,
         hub: &DiscoveredHub,
     ) -> Result<Box<dyn Hub>> {
-        info!("Connecting to hub {}...", hub.addr,);
+        info!(target: targets::CONNECTION, "Connecting to hub {}...", hub.addr);
 
         let peripheral = self.adapter.peripheral(&hub.addr).await?;
         peripheral.connect().await?;
         peripheral.discover_services().await?;
         // tokio::time::sleep(Duration::from_secs(2)).await;
+        let cancel = CancellationToken::new();
+        if hub.hub_type == HubType::Wedo2SmartHub {
+            let transport = Wedo2Transport::new(peripheral)?
+                .with_adapter(self.adapter.clone());
+            return Ok(Box::new(
+                hubs::wedo2_hub::Wedo2Hub::init(Arc::new(transport), cancel)
+                    .await?,
+            ));
+        }
         let chars = peripheral.characteristics();
 
         // dbg!(&chars);
@@ -205,15 +260,17 @@ impl PoweredUp {
             .find(|c| c.uuid == *consts::blecharacteristic::LPF2_ALL)
             .context("Device does not advertise LPF2_ALL characteristic")?
             .clone();
-        let cancel = CancellationToken::new();
+        let transport: hubs::Tokens = Arc::new(
+            BtleTransport::new(peripheral, lpf_char)
+                .with_adapter(self.adapter.clone()),
+        );
         match hub.hub_type {
             // These have had some real life-testing.
             HubType::TechnicMediumHub
             | HubType::MoveHub
             | HubType::RemoteControl => Ok(Box::new(
                 hubs::generic_hub::GenericHub::init(
-                    peripheral,
-                    lpf_char,
+                    transport,
                     hub.hub_type,
                     cancel,
                 )
@@ -221,24 +278,21 @@ impl PoweredUp {
             )),
             // These are untested, but if they support the same "Lego Wireless protocol 3.0"
             // then they should probably work?
-            HubType::Wedo2SmartHub
-            | HubType::Hub
-            | HubType::DuploTrainBase
-            | HubType::Mario => Ok(Box::new(
-                hubs::generic_hub::GenericHub::init(
-                    peripheral,
-                    lpf_char,
-                    hub.hub_type,
-                    cancel,
-                )
-                .await?,
-            )),
+            HubType::Hub | HubType::DuploTrainBase | HubType::Mario => {
+                Ok(Box::new(
+                    hubs::generic_hub::GenericHub::init(
+                        transport,
+                        hub.hub_type,
+                        cancel,
+                    )
+                    .await?,
+                ))
+            }
             // Here is some hub that advertises LPF2_ALL but is not in the known list.
             // Set kind to Unknown and give it a try, why not?
             _ => Ok(Box::new(
                 hubs::generic_hub::GenericHub::init(
-                    peripheral,
-                    lpf_char,
+                    transport,
                     HubType::Unknown,
                     cancel,
                 )
@@ -252,17 +306,17 @@ impl PoweredUp {
     ) -> Result<impl Stream<Item = DiscoveredHub> + '_> {
         let events = self.adapter.events().await?;
         // self.adapter.start_scan(ScanFilter::default()).await?;
-        self.adapter.start_scan(scanfilter()).await?;
+        start_scan(&self.adapter, scanfilter()).await?;
         Ok(events.filter_map(|event| async {
             let CentralEvent::DeviceDiscovered(id) = event else {
                 None?
             };
             // get peripheral info
             let peripheral = self.adapter.peripheral(&id).await.ok()?;
-            println!("{:?}", peripheral.properties().await.unwrap());
             let Some(props) = peripheral.properties().await.ok()? else {
                 None?
             };
+            debug!(target: targets::SCAN, "{:?}", props);
             if let Some(hub_type) = identify_hub(&props).await.ok()? {
                 let hub = DiscoveredHub {
                     hub_type,
@@ -282,17 +336,17 @@ impl PoweredUp {
         &mut self,
     ) -> Result<Pin<Box<dyn Stream<Item = DiscoveredHub> + Send + '_>>> {
         let events = self.adapter.events().await?;
-        self.adapter.start_scan(scanfilter()).await?;
+        start_scan(&self.adapter, scanfilter()).await?;
         Ok(Box::pin(events.filter_map(|event| async {
             let CentralEvent::DeviceDiscovered(id) = event else {
                 None?
             };
             // get peripheral info
             let peripheral = self.adapter.peripheral(&id).await.ok()?;
-            println!("{:?}", peripheral.properties().await.unwrap());
             let Some(props) = peripheral.properties().await.ok()? else {
                 None?
             };
+            debug!(target: targets::SCAN, "{:?}", props);
             if let Some(hub_type) = identify_hub(&props).await.ok()? {
                 let hub = DiscoveredHub {
                     hub_type,
@@ -309,7 +363,7 @@ impl PoweredUp {
     }
 }
 
-fn scanfilter() -> ScanFilter {
+pub(crate) fn scanfilter() -> ScanFilter {
     ScanFilter {
         services: vec![
             *consts::bleservice::LPF2_HUB,
@@ -318,6 +372,33 @@ fn scanfilter() -> ScanFilter {
     }
 }
 
+/// Scans started and not stopped yet. The adapter has one scan however
+/// many are started, so it only stops once the last one is.
+static SCANS: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) async fn start_scan(
+    adapter: &Adapter,
+    filter: ScanFilter,
+) -> Result<()> {
+    adapter.start_scan(filter).await?;
+    SCANS.fetch_add(1, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Stop a scan started with `start_scan`, and the adapter's scan with it
+/// if it was the last one
+pub(crate) async fn stop_scan(adapter: &Adapter) -> Result<()> {
+    if scan_stopped() {
+        adapter.stop_scan().await?;
+    }
+    Ok(())
+}
+
+/// Count a scan as stopped; true if it was the last one
+pub(crate) fn scan_stopped() -> bool {
+    SCANS.fetch_sub(1, Ordering::SeqCst) == 1
+}
+
 /// Properties by which to filter discovered hubs
 #[derive(Debug, PartialEq, Eq)]
 pub enum HubFilter {
@@ -385,20 +466,63 @@ async fn identify_hub(props: &PeripheralProperties) -> Result<Option<HubType>> {
     Ok(None)
 }
 
+/// Changes of the link to a connected hub
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ConnectionEvent {
+    /// The link was lost; devices stop receiving values until reconnected
+    Disconnected,
+    /// The link is back and the port setups that were active are restored
+    Reconnected,
+}
+
+/// Interval at which the link is checked, for platforms where the
+/// notification stream doesn't end when the hub goes away
+const LINK_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// First delay between reconnect attempts, doubled up to the maximum
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const RECONNECT_DELAY_MAX: Duration = Duration::from_secs(30);
+/// Time setup and `wait_for_device` wait for the hub to describe its devices
+pub const DEFAULT_READY_TIMEOUT: Duration = Duration::from_secs(10);
+/// Information requests still unanswered after this long are sent again
+const INFO_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
 pub struct ConnectedHub {
     pub name: String,
     pub mutex: HubMutex,
     pub kind: HubType,
     pub cancel: CancellationToken,
     // pub dropguard: DropGuard,
+    connection_sender: broadcast::Sender<ConnectionEvent>,
+    event_sender: broadcast::Sender<HubEvent>,
+    readiness: Readiness,
+    ready_timeout: Duration,
+    properties: HubPropertyChannels,
 }
 impl ConnectedHub {
+    /// Set up the hub and wait until all attached devices are described,
+    /// for at most `DEFAULT_READY_TIMEOUT`.
     pub async fn setup_hub(created_hub: Box<dyn Hub>) -> Result<ConnectedHub> {
+        Self::setup_hub_with_timeout(created_hub, DEFAULT_READY_TIMEOUT).await
+    }
+
+    /// Set up the hub, waiting at most `ready_timeout` for the attached
+    /// devices to be described. The timeout is also used by
+    /// `wait_for_device`. If the hub isn't ready in time it is
+    /// disconnected again.
+    pub async fn setup_hub_with_timeout(
+        created_hub: Box<dyn Hub>,
+        ready_timeout: Duration,
+    ) -> Result<ConnectedHub> {
         let connected_hub = ConnectedHub {
             kind: created_hub.kind(),
             name: created_hub.name().await?,
             cancel: created_hub.cancel_token(),
             mutex: Arc::new(Mutex::new(created_hub)),
+            connection_sender: broadcast::channel::<ConnectionEvent>(8).0,
+            event_sender: broadcast::channel::<HubEvent>(16).0,
+            readiness: Readiness::new(),
+            ready_timeout,
+            properties: HubPropertyChannels::new(ready_timeout),
         };
         // Create forwarding channels and store in hub so we can create receivers on demand
         {
@@ -409,8 +533,8 @@ impl ConnectedHub {
                 Some(broadcast::channel::<PortValueCombinedFormat>(32).0);
             lock.channels().networkcmd_sender =
                 Some(broadcast::channel::<NetworkCommand>(16).0);
-            lock.channels().hubnotification_sender =
-                Some(broadcast::channel::<HubNotification>(16).0);
+            lock.channels().hubevent_sender =
+                Some(connected_hub.event_sender.clone());
             lock.channels().commandfeedback_sender = Some(
                 broadcast::channel::<PortOutputCommandFeedbackFormat>(16).0,
             );
@@ -419,61 +543,311 @@ impl ConnectedHub {
         let hub_mutex = connected_hub.mutex.clone();
         {
             let lock = &mut connected_hub.mutex.lock().await;
-            let stream: NotificationStream =
-                lock.peripheral().notifications().await?;
+            let stream: FrameStream = lock.tokens().notifications().await?;
             let senders = lock.channels().clone();
-            // let senders = (
-            //     lock.channels().singlevalue_sender.as_ref().unwrap().clone(),
-            //     lock.channels()
-            //         .combinedvalue_sender
-            //         .as_ref()
-            //         .unwrap()
-            //         .clone(),
-            //     lock.channels().networkcmd_sender.as_ref().unwrap().clone(),
-            //     lock.channels()
-            //         .hubnotification_sender
-            //         .as_ref()
-            //         .unwrap()
-            //         .clone(),
-            //     lock.channels()
-            //         .commandfeedback_sender
-            //         .as_ref()
-            //         .unwrap()
-            //         .clone(),
-            // );
             let io_handler_cancel = connected_hub.cancel.clone();
-            let _io_handler_task = tokio::spawn(async move {
-                crate::hubs::io_event::io_event_handler(
+            let connection_sender = connected_hub.connection_sender.clone();
+            let span = info_span!("hub", name = %connected_hub.name);
+            let _io_handler_task = tokio::spawn(
+                supervise_connection(
                     stream,
                     hub_mutex,
                     senders,
+                    connected_hub.readiness.clone(),
+                    connected_hub.properties.clone(),
                     io_handler_cancel,
+                    connection_sender,
                 )
-                .await
-                .expect("Error setting up main notification handler");
-            });
+                .instrument(span),
+            );
+            // The hub answers this after announcing its attached devices
+            lock.hub_props(
+                FENCE_PROPERTY,
+                HubPropertyOperation::RequestUpdateDownstream,
+            )
+            .await?;
+            properties::request_properties(&***lock).await?;
+        }
+
+        if let Err(e) = connected_hub.wait_until_ready(ready_timeout).await {
+            // The hub is never handed out, so don't leave it connected with
+            // its notification handler running
+            connected_hub.cancel.cancel();
+            let _ = connected_hub.mutex.lock().await.disconnect().await;
+            return Err(e);
+        }
+
+        Ok(connected_hub)
+    }
+
+    /// Wait until the hub has announced its attached devices and answered
+    /// every information request about them. Requests that go unanswered
+    /// are repeated, as replies can get lost on a poor link.
+    pub async fn wait_until_ready(&self, timeout: Duration) -> Result<()> {
+        let mut state = self.readiness.subscribe();
+        let wait = async {
+            loop {
+                if state.borrow_and_update().is_ready() {
+                    return Ok(());
+                }
+                match tokio::time::timeout(INFO_RETRY_INTERVAL, state.changed())
+                    .await
+                {
+                    Ok(Ok(())) => (),
+                    Ok(Err(_)) => {
+                        return Err(Error::HubError(String::from(
+                            "Hub readiness no longer tracked",
+                        )))
+                    }
+                    Err(_) => self.repeat_info_requests().await?,
+                }
+            }
+        };
+        match tokio::time::timeout(timeout, wait).await {
+            Ok(result) => result,
+            Err(_) => {
+                let mut ports: Vec<u8> = self
+                    .readiness
+                    .pending()
+                    .iter()
+                    .map(|r| r.port_id())
+                    .collect();
+                ports.sort_unstable();
+                ports.dedup();
+                Err(Error::TimeoutError(format!(
+                    "Hub not ready after {:?}, ports not described: {:?}",
+                    timeout, ports
+                )))
+            }
         }
+    }
 
-        // Subscribe to btleplug peripheral
+    /// Wait for a device of the given kind to be attached to the port and
+    /// described, for at most the hub's ready timeout.
+    pub async fn wait_for_device(
+        &self,
+        kind: IoTypeId,
+        port_id: u8,
+    ) -> Result<IoDevice> {
+        let mut state = self.readiness.subscribe();
+        let wait = async {
+            loop {
+                {
+                    // The IO event handler updates the readiness state
+                    // while holding the hub lock
+                    let hub = self.mutex.lock().await;
+                    let described =
+                        state.borrow_and_update().is_port_ready(port_id);
+                    if described {
+                        if let Ok(device) = hub.io_from_port(port_id) {
+                            if *device.kind() == kind {
+                                return Ok(device);
+                            }
+                        }
+                    }
+                }
+                if state.changed().await.is_err() {
+                    return Err(Error::HubError(String::from(
+                        "Hub readiness no longer tracked",
+                    )));
+                }
+            }
+        };
+        match tokio::time::timeout(self.ready_timeout, wait).await {
+            Ok(result) => result,
+            Err(_) => Err(Error::TimeoutError(format!(
+                "No {:?} on port {} after {:?}",
+                kind, port_id, self.ready_timeout
+            ))),
+        }
+    }
+
+    /// Combine two motors of the same kind into a virtual port, and return
+    /// the device on it once the hub has attached and described it. Drive
+    /// it with `SyncedMotorPair`.
+    pub async fn create_virtual_port(
+        &self,
+        port_a: u8,
+        port_b: u8,
+    ) -> Result<IoDevice> {
+        let same_ports = |a: u8, b: u8| {
+            (a, b) == (port_a, port_b) || (b, a) == (port_a, port_b)
+        };
+        let mut events = self.events();
         {
-            let lock = connected_hub.mutex.lock().await;
-            match lock.peripheral().subscribe(&lock.characteristic()).await {
-                Ok(()) => (),
-                // We got a peri connection but can't subscribe. Can happen if the hub has almost timed out
-                // waiting for a connection; it seemingly connects but then turns off. On Windows the error
-                // returned was a HRESULT: Operation aborted
-                Err(e) => {
-                    eprintln!(
-                        "Error subscribing to peripheral notifications: {:#?}",
-                        e
-                    )
+            let hub = self.mutex.lock().await;
+            let existing = hub.connected_io().values().find(|device| {
+                device
+                    .virtual_ports()
+                    .is_some_and(|(a, b)| same_ports(a, b))
+            });
+            if let Some(device) = existing {
+                return hub.io_from_port(device.port());
+            }
+            hub.connect_virtual_port(port_a, port_b).await?;
+        }
+        let attached = async {
+            loop {
+                match events.recv().await {
+                    Ok(HubEvent::AttachedVirtual {
+                        port_id,
+                        io_type_id,
+                        port_a,
+                        port_b,
+                    }) if same_ports(port_a, port_b) => {
+                        return Ok((io_type_id, port_id));
+                    }
+                    Ok(HubEvent::Error(e))
+                        if e.command_type
+                            == consts::MessageType::VirtualPortSetup as u8 =>
+                    {
+                        return Err(Error::HubError(format!(
+                            "Hub refused virtual port for ports {} and {}: {:?}",
+                            port_a, port_b, e.error_code
+                        )));
+                    }
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => (),
+                    Err(broadcast::error::RecvError::Closed) => {
+                        return Err(Error::HubError(String::from(
+                            "Hub event channel closed",
+                        )))
+                    }
+                }
+            }
+        };
+        let (kind, port_id) =
+            match tokio::time::timeout(self.ready_timeout, attached).await {
+                Ok(result) => result?,
+                Err(_) => {
+                    return Err(Error::TimeoutError(format!(
+                        "No virtual port for ports {} and {} after {:?}",
+                        port_a, port_b, self.ready_timeout
+                    )))
+                }
+            };
+        self.wait_for_device(kind, port_id).await
+    }
+
+    async fn repeat_info_requests(&self) -> Result<()> {
+        let hub = self.mutex.lock().await;
+        if !self.readiness.subscribe().borrow().is_announced() {
+            hub.hub_props(
+                FENCE_PROPERTY,
+                HubPropertyOperation::RequestUpdateDownstream,
+            )
+            .await?;
+        }
+        self.readiness.repeated();
+        for request in self.readiness.pending() {
+            request.send(&**hub).await?;
+        }
+        Ok(())
+    }
+
+    /// Receive an event whenever the link to the hub is lost or restored.
+    /// Devices obtained from the hub keep working after a reconnect.
+    pub fn connection_events(&self) -> broadcast::Receiver<ConnectionEvent> {
+        self.connection_sender.subscribe()
+    }
+
+    /// Receive hub events: property updates, button presses, actions,
+    /// alerts, errors, and devices being attached and detached.
+    pub fn events(&self) -> broadcast::Receiver<HubEvent> {
+        self.event_sender.subscribe()
+    }
+
+    /// Latest hub properties as reported by the hub. Battery level, signal
+    /// strength and button state are kept up to date.
+    pub fn properties(&self) -> &HubPropertyChannels {
+        &self.properties
+    }
+}
+
+/// Run the IO event handler for as long as the hub is in use, reconnecting
+/// whenever the link is lost. Only cancelling the hub stops it.
+async fn supervise_connection(
+    mut stream: FrameStream,
+    mutex: HubMutex,
+    senders: hubs::Channels,
+    readiness: Readiness,
+    properties: HubPropertyChannels,
+    cancel: CancellationToken,
+    connection_sender: broadcast::Sender<ConnectionEvent>,
+) {
+    let tokens = mutex.lock().await.tokens();
+    loop {
+        // Ends the handler when the link is lost but the stream stays open
+        let session = cancel.child_token();
+        let watchdog =
+            tokio::spawn(watch_link(tokens.clone(), session.clone()));
+        let outcome = crate::hubs::io_event::io_event_handler(
+            stream,
+            mutex.clone(),
+            senders.clone(),
+            readiness.clone(),
+            properties.clone(),
+            session.clone(),
+        )
+        .await;
+        session.cancel();
+        let _ = watchdog.await;
+        if cancel.is_cancelled() {
+            break;
+        }
+        if let Err(e) = outcome {
+            warn!(target: targets::CONNECTION, "IO event handler stopped: {}", e);
+        }
+
+        info!(target: targets::CONNECTION, "Lost connection to hub, reconnecting...");
+        let _ = connection_sender.send(ConnectionEvent::Disconnected);
+        stream = match reconnect(&tokens, &cancel).await {
+            Some(stream) => stream,
+            None => break,
+        };
+        info!(target: targets::CONNECTION, "Reconnected to hub");
+        let _ = connection_sender.send(ConnectionEvent::Reconnected);
+    }
+}
+
+async fn watch_link(tokens: hubs::Tokens, session: CancellationToken) {
+    loop {
+        tokio::select! {
+            _ = session.cancelled() => break,
+            _ = tokio::time::sleep(LINK_CHECK_INTERVAL) => {
+                if !tokens.is_connected().await.unwrap_or(false) {
+                    session.cancel();
+                    break;
                 }
             }
         }
-        // Wait for devices to be collected. This is set to a very long time because notifications
-        // from the hub sometimes lag, and we don't know how many devices to expect.
-        tokio::time::sleep(Duration::from_millis(3000)).await;
+    }
+}
 
-        Ok(connected_hub)
+/// Retry until the hub is connected and notifications are resubscribed,
+/// which also restores the port setups. None if cancelled meanwhile.
+async fn reconnect(
+    tokens: &hubs::Tokens,
+    cancel: &CancellationToken,
+) -> Option<FrameStream> {
+    let mut delay = RECONNECT_DELAY;
+    loop {
+        let attempt = async {
+            tokens.reconnect().await?;
+            tokens.notifications().await
+        };
+        tokio::select! {
+            _ = cancel.cancelled() => return None,
+            result = attempt => match result {
+                Ok(stream) => return Some(stream),
+                Err(e) => {
+                    warn!(target: targets::CONNECTION, "Reconnect failed: {}", e)
+                }
+            }
+        }
+        tokio::select! {
+            _ = cancel.cancelled() => return None,
+            _ = tokio::time::sleep(delay) => (),
+        }
+        delay = (delay * 2).min(RECONNECT_DELAY_MAX);
     }
 }
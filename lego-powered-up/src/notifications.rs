@@ -6,12 +6,11 @@
 
 use crate::consts::*;
 use crate::error::{Error, OptionContext, Result};
-use log::{debug, trace};
 use lpu_macros::Parse;
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
-use std::collections::HashMap;
 use std::fmt::{self, Debug, Display};
+use tracing::{debug, trace};
 
 pub use self::message::NotificationMessage;
 pub mod message;
@@ -19,6 +18,11 @@ pub mod message;
 #[macro_use]
 pub mod macros;
 
+#[cfg(test)]
+mod strategy;
+#[cfg(test)]
+mod test;
+
 pub const MAX_NAME_SIZE: usize = 14;
 
 /// The two modes by which Hub LED colours may be set
@@ -33,7 +37,8 @@ pub enum HubLedMode {
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct HubProperty {
-    pub property: HubPropertyValue,
+    /// The value set or updated. Requests carry none.
+    pub property: Option<HubPropertyValue>,
     pub operation: HubPropertyOperation,
     pub reference: HubPropertyRef,
 }
@@ -42,39 +47,13 @@ impl HubProperty {
     pub fn parse<'a>(mut msg: impl Iterator<Item = &'a u8>) -> Result<Self> {
         let property_int = next!(msg);
         let operation = ok!(HubPropertyOperation::from_u8(next!(msg)));
-        let property = HubPropertyValue::parse(property_int, &mut msg)?;
-        let reference = match property {
-            HubPropertyValue::AdvertisingName(_) => {
-                HubPropertyRef::AdvertisingName
-            }
-            HubPropertyValue::Button(_) => HubPropertyRef::Button,
-            HubPropertyValue::FwVersion(_) => HubPropertyRef::FwVersion,
-            HubPropertyValue::HwVersion(_) => HubPropertyRef::HwVersion,
-            HubPropertyValue::Rssi(_) => HubPropertyRef::Rssi,
-            HubPropertyValue::BatteryVoltage(_) => {
-                HubPropertyRef::BatteryVoltage
-            }
-            HubPropertyValue::BatteryType(_) => HubPropertyRef::BatteryType,
-            HubPropertyValue::ManufacturerName(_) => {
-                HubPropertyRef::ManufacturerName
-            }
-            HubPropertyValue::RadioFirmwareVersion(_) => {
-                HubPropertyRef::RadioFirmwareVersion
-            }
-            HubPropertyValue::LegoWirelessProtocolVersion(_) => {
-                HubPropertyRef::LegoWirelessProtocolVersion
-            }
-            HubPropertyValue::SystemTypeId(_) => HubPropertyRef::SystemTypeId,
-            HubPropertyValue::HwNetworkId(_) => HubPropertyRef::HwNetworkId,
-            HubPropertyValue::PrimaryMacAddress(_) => {
-                HubPropertyRef::PrimaryMacAddress
-            }
-            HubPropertyValue::SecondaryMacAddress => {
-                HubPropertyRef::SecondaryMacAddress
-            }
-            HubPropertyValue::HardwareNetworkFamily(_) => {
-                HubPropertyRef::HardwareNetworkFamily
+        let reference = ok!(HubPropertyRef::from_u8(property_int));
+        let property = match operation {
+            HubPropertyOperation::SetDownstream
+            | HubPropertyOperation::UpdateUpstream => {
+                Some(HubPropertyValue::parse(property_int, &mut msg)?)
             }
+            _ => None,
         };
 
         Ok(Self {
@@ -93,6 +72,9 @@ impl HubProperty {
             self.reference as u8,
             self.operation as u8,
         ]);
+        if let Some(property) = &self.property {
+            msg.extend(property.serialise());
+        }
 
         msg
     }
@@ -187,6 +169,52 @@ impl HubPropertyValue {
             }
         })
     }
+
+    /// Payload bytes only, the property reference is part of HubProperty
+    pub fn serialise(&self) -> Vec<u8> {
+        use HubPropertyValue::*;
+        match self {
+            AdvertisingName(name) => name.clone(),
+            Button(state) => vec![*state],
+            FwVersion(vers) => vers.to_le_bytes().to_vec(),
+            HwVersion(vers) => vers.to_le_bytes().to_vec(),
+            Rssi(rssi) => rssi.to_le_bytes().to_vec(),
+            BatteryVoltage(pct) => vec![*pct],
+            BatteryType(t) => vec![*t as u8],
+            ManufacturerName(name) => name.clone(),
+            RadioFirmwareVersion(vers) => vers.clone(),
+            LegoWirelessProtocolVersion(vers) => vers.to_le_bytes().to_vec(),
+            SystemTypeId(id) => vec![*id],
+            HwNetworkId(id) => vec![*id],
+            PrimaryMacAddress(mac) => mac.to_vec(),
+            SecondaryMacAddress => Vec::new(),
+            HardwareNetworkFamily(fam) => vec![*fam],
+        }
+    }
+
+    /// The property this is a value of
+    pub fn reference(&self) -> HubPropertyRef {
+        use HubPropertyValue::*;
+        match self {
+            AdvertisingName(_) => HubPropertyRef::AdvertisingName,
+            Button(_) => HubPropertyRef::Button,
+            FwVersion(_) => HubPropertyRef::FwVersion,
+            HwVersion(_) => HubPropertyRef::HwVersion,
+            Rssi(_) => HubPropertyRef::Rssi,
+            BatteryVoltage(_) => HubPropertyRef::BatteryVoltage,
+            BatteryType(_) => HubPropertyRef::BatteryType,
+            ManufacturerName(_) => HubPropertyRef::ManufacturerName,
+            RadioFirmwareVersion(_) => HubPropertyRef::RadioFirmwareVersion,
+            LegoWirelessProtocolVersion(_) => {
+                HubPropertyRef::LegoWirelessProtocolVersion
+            }
+            SystemTypeId(_) => HubPropertyRef::SystemTypeId,
+            HwNetworkId(_) => HubPropertyRef::HwNetworkId,
+            PrimaryMacAddress(_) => HubPropertyRef::PrimaryMacAddress,
+            SecondaryMacAddress => HubPropertyRef::SecondaryMacAddress,
+            HardwareNetworkFamily(_) => HubPropertyRef::HardwareNetworkFamily,
+        }
+    }
 }
 
 #[repr(u8)]
@@ -279,7 +307,11 @@ impl HubAlert {
     pub fn parse<'a>(mut msg: impl Iterator<Item = &'a u8>) -> Result<Self> {
         let alert_type = AlertType::parse(&mut msg)?;
         let operation = AlertOperation::parse(&mut msg)?;
-        let payload = AlertPayload::parse(&mut msg)?;
+        // Only updates from the hub carry a payload
+        let payload = match operation {
+            AlertOperation::Update => AlertPayload::parse(&mut msg)?,
+            _ => AlertPayload::StatusOk,
+        };
         Ok(HubAlert {
             alert_type,
             operation,
@@ -295,6 +327,9 @@ impl HubAlert {
             self.alert_type as u8,
             self.operation as u8,
         ]);
+        if self.operation == AlertOperation::Update {
+            msg.push(self.payload as u8);
+        }
         msg
     }
 }
@@ -311,6 +346,17 @@ impl AttachedIo {
         let event = IoAttachEvent::parse(&mut msg)?;
         Ok(Self { port, event })
     }
+    pub fn serialise(&self) -> Vec<u8> {
+        let mut msg = Vec::with_capacity(15);
+        msg.extend_from_slice(&[
+            0,
+            0,
+            MessageType::HubAttachedIo as u8,
+            self.port,
+        ]);
+        msg.extend(self.event.serialise());
+        msg
+    }
 }
 
 #[repr(u8)]
@@ -365,6 +411,33 @@ impl IoAttachEvent {
             }
         })
     }
+    pub fn serialise(&self) -> Vec<u8> {
+        match self {
+            IoAttachEvent::DetachedIo {} => vec![Event::DetachedIo as u8],
+            IoAttachEvent::AttachedIo {
+                io_type_id,
+                hw_rev,
+                fw_rev,
+            } => {
+                let mut bytes = vec![Event::AttachedIo as u8];
+                bytes.extend_from_slice(&(*io_type_id as u16).to_le_bytes());
+                bytes.extend(hw_rev.serialise());
+                bytes.extend(fw_rev.serialise());
+                bytes
+            }
+            IoAttachEvent::AttachedVirtualIo {
+                io_type_id,
+                port_a,
+                port_b,
+            } => {
+                let mut bytes = vec![Event::AttachedVirtualIo as u8];
+                bytes.extend_from_slice(&(*io_type_id as u16).to_le_bytes());
+                bytes.push(*port_a);
+                bytes.push(*port_b);
+                bytes
+            }
+        }
+    }
 }
 
 /// One observed version number (for a large motor) is 0x1000002f,
@@ -431,6 +504,7 @@ impl VersionNumber {
     }
 }
 
+/// As LEGO writes them, e.g. 1.1.00.0004
 impl Display for VersionNumber {
     fn fmt(
         &self,
@@ -438,7 +512,7 @@ impl Display for VersionNumber {
     ) -> std::result::Result<(), fmt::Error> {
         write!(
             fmt,
-            "{}.{}.{}.{:x}",
+            "{}.{}.{:02}.{:04x}",
             self.major, self.minor, self.bugfix, self.build
         )
     }
@@ -455,8 +529,8 @@ impl Debug for VersionNumber {
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct ErrorMessageFormat {
-    command_type: u8,
-    error_code: ErrorCode,
+    pub command_type: u8,
+    pub error_code: ErrorCode,
 }
 
 impl ErrorMessageFormat {
@@ -469,6 +543,15 @@ impl ErrorMessageFormat {
             error_code,
         })
     }
+    pub fn serialise(&self) -> Vec<u8> {
+        vec![
+            0,
+            0,
+            MessageType::GenericErrorMessages as u8,
+            self.command_type,
+            self.error_code as u8,
+        ]
+    }
 }
 
 #[repr(u8)]
@@ -538,19 +621,13 @@ impl NetworkCommand {
                 let fam = NetworkFamily::parse(&mut msg)?;
                 FamilySet(fam)
             }
-            HwNetworkCommandType::JoinDenied => {
-                todo!()
-            }
-            HwNetworkCommandType::GetFamily => {
-                todo!()
-            }
+            HwNetworkCommandType::JoinDenied => JoinDenied(),
+            HwNetworkCommandType::GetFamily => GetFamily(),
             HwNetworkCommandType::Family => {
                 let fam = NetworkFamily::parse(&mut msg)?;
                 Family(fam)
             }
-            HwNetworkCommandType::GetSubfamily => {
-                todo!()
-            }
+            HwNetworkCommandType::GetSubfamily => GetSubfamily(),
             HwNetworkCommandType::Subfamily => {
                 let fam = NetworkSubFamily::parse(&mut msg)?;
                 Subfamily(fam)
@@ -559,9 +636,7 @@ impl NetworkCommand {
                 let fam = NetworkSubFamily::parse(&mut msg)?;
                 SubfamilySet(fam)
             }
-            HwNetworkCommandType::GetExtendedFamily => {
-                todo!()
-            }
+            HwNetworkCommandType::GetExtendedFamily => GetExtendedFamily(),
             HwNetworkCommandType::ExtendedFamily => {
                 // Bit 7 | sss | ffff
                 let byte = next!(msg);
@@ -581,10 +656,62 @@ impl NetworkCommand {
                 ExtendedFamilySet { family, subfamily }
             }
             HwNetworkCommandType::ResetLongPressTiming => {
-                todo!()
+                ResetLongPressTiming()
             }
         })
     }
+
+    pub fn serialise(&self) -> Vec<u8> {
+        use NetworkCommand::*;
+        let mut msg = vec![0, 0, MessageType::HwNetworkCommands as u8];
+        match self {
+            ConnectionRequest(button) => msg.extend_from_slice(&[
+                HwNetworkCommandType::ConnectionRequest as u8,
+                *button as u8,
+            ]),
+            FamilyRequest => {
+                msg.push(HwNetworkCommandType::FamilyRequest as u8)
+            }
+            FamilySet(fam) => msg.extend_from_slice(&[
+                HwNetworkCommandType::FamilySet as u8,
+                *fam as u8,
+            ]),
+            JoinDenied() => msg.push(HwNetworkCommandType::JoinDenied as u8),
+            GetFamily() => msg.push(HwNetworkCommandType::GetFamily as u8),
+            Family(fam) => msg.extend_from_slice(&[
+                HwNetworkCommandType::Family as u8,
+                *fam as u8,
+            ]),
+            GetSubfamily() => {
+                msg.push(HwNetworkCommandType::GetSubfamily as u8)
+            }
+            Subfamily(sub) => msg.extend_from_slice(&[
+                HwNetworkCommandType::Subfamily as u8,
+                *sub as u8,
+            ]),
+            SubfamilySet(sub) => msg.extend_from_slice(&[
+                HwNetworkCommandType::SubfamilySet as u8,
+                *sub as u8,
+            ]),
+            GetExtendedFamily() => {
+                msg.push(HwNetworkCommandType::GetExtendedFamily as u8)
+            }
+            ExtendedFamily { family, subfamily } => msg.extend_from_slice(&[
+                HwNetworkCommandType::ExtendedFamily as u8,
+                ((*subfamily as u8) << 4) | *family as u8,
+            ]),
+            ExtendedFamilySet { family, subfamily } => {
+                msg.extend_from_slice(&[
+                    HwNetworkCommandType::ExtendedFamilySet as u8,
+                    ((*subfamily as u8) << 4) | *family as u8,
+                ])
+            }
+            ResetLongPressTiming() => {
+                msg.push(HwNetworkCommandType::ResetLongPressTiming as u8)
+            }
+        }
+        msg
+    }
 }
 
 #[repr(u8)]
@@ -659,7 +786,7 @@ impl InformationRequest {
 }
 
 #[repr(u8)]
-#[derive(Copy, Clone, Debug, PartialEq, Eq, FromPrimitive, Parse)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, FromPrimitive, Parse)]
 pub enum InformationType {
     PortValue = 0x00,
     ModeInfo = 0x01,
@@ -699,7 +826,7 @@ impl ModeInformationRequest {
 }
 
 #[repr(u8)]
-#[derive(Copy, Clone, Debug, PartialEq, Eq, FromPrimitive, Parse)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, FromPrimitive, Parse)]
 pub enum ModeInformationType {
     Name = 0x00,
     Raw = 0x01,
@@ -807,7 +934,7 @@ impl InputSetupCombined {
                 // bytes.extend_from_slice(mode_dataset.as_slice());
                 bytes
             }
-            LockLpf2DeviceForSetup {} => {
+            LockLpf2DeviceForSetup => {
                 vec![
                     // Header
                     0, // len
@@ -819,7 +946,7 @@ impl InputSetupCombined {
                         as u8,
                 ]
             }
-            UnlockAndStartMultiEnabled {} => {
+            UnlockAndStartMultiEnabled => {
                 vec![
                     // Header
                     0, // len
@@ -830,7 +957,7 @@ impl InputSetupCombined {
                     InputSetupCombinedSubcommandValue::UnlockAndStartMultiEnabled as u8,
                 ]
             }
-            UnlockAndStartMultiDisabled {} => {
+            UnlockAndStartMultiDisabled => {
                 vec![
                     // Header
                     0, // len
@@ -841,7 +968,7 @@ impl InputSetupCombined {
                     InputSetupCombinedSubcommandValue::UnlockAndStartMultiDisabled as u8,
                 ]
             }
-            NotUsed {} => {
+            NotUsed => {
                 vec![
                     // Header
                     0, // len
@@ -852,7 +979,7 @@ impl InputSetupCombined {
                     InputSetupCombinedSubcommandValue::NotUsed as u8,
                 ]
             }
-            ResetSensor {} => {
+            ResetSensor => {
                 vec![
                     // Header
                     0, // len
@@ -888,9 +1015,10 @@ impl InputSetupCombinedSubcommand {
         Ok(match comm {
             PortInputFormatSetupSubCommand::SetModeanddatasetCombinations => {
                 let combination_index = next!(msg);
-                let mut mode_dataset = [0_u8; 8];
-                for ele in mode_dataset.iter_mut() {
-                    *ele = next!(msg);
+                // Unused entries are either 255 or left out altogether
+                let mut mode_dataset = [255_u8; 8];
+                for (ele, val) in mode_dataset.iter_mut().zip(&mut msg) {
+                    *ele = *val;
                 }
                 SetModeanddatasetCombinations {
                     combination_index,
@@ -928,6 +1056,17 @@ impl PortInformationValue {
             information_type,
         })
     }
+    pub fn serialise(&self) -> Vec<u8> {
+        let mut msg = Vec::with_capacity(11);
+        msg.extend_from_slice(&[
+            0,
+            0,
+            MessageType::PortInformation as u8,
+            self.port_id,
+        ]);
+        msg.extend(self.information_type.serialise());
+        msg
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -971,6 +1110,41 @@ impl PortInformationType {
             ))),
         }
     }
+    /// The request this is a reply to
+    pub fn information_type(&self) -> InformationType {
+        match self {
+            PortInformationType::ModeInfo { .. } => InformationType::ModeInfo,
+            PortInformationType::PossibleModeCombinations(_) => {
+                InformationType::PossibleModeCombinations
+            }
+        }
+    }
+    pub fn serialise(&self) -> Vec<u8> {
+        use PortInformationType::*;
+        match self {
+            ModeInfo {
+                capabilities,
+                mode_count,
+                input_modes,
+                output_modes,
+            } => {
+                let mut bytes = vec![
+                    InformationType::ModeInfo as u8,
+                    capabilities.0,
+                    *mode_count,
+                ];
+                bytes.extend_from_slice(&input_modes.to_le_bytes());
+                bytes.extend_from_slice(&output_modes.to_le_bytes());
+                bytes
+            }
+            PossibleModeCombinations(combinations) => {
+                let mut bytes =
+                    vec![InformationType::PossibleModeCombinations as u8];
+                bytes.extend_from_slice(combinations);
+                bytes
+            }
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -1000,6 +1174,18 @@ impl PortModeInformationValue {
             information_type,
         })
     }
+    pub fn serialise(&self) -> Vec<u8> {
+        let mut msg = Vec::with_capacity(16);
+        msg.extend_from_slice(&[
+            0,
+            0,
+            MessageType::PortModeInformation as u8,
+            self.port_id,
+            self.mode,
+        ]);
+        msg.extend(self.information_type.serialise());
+        msg
+    }
 }
 
 #[repr(u8)]
@@ -1104,6 +1290,63 @@ impl PortModeInformationType {
             }
         })
     }
+    /// The request this is a reply to
+    pub fn information_type(&self) -> ModeInformationType {
+        use PortModeInformationType::*;
+        match self {
+            Name(_) => ModeInformationType::Name,
+            RawRange { .. } => ModeInformationType::Raw,
+            PctRange { .. } => ModeInformationType::Pct,
+            SiRange { .. } => ModeInformationType::Si,
+            Symbol(_) => ModeInformationType::Symbol,
+            Mapping { .. } => ModeInformationType::Mapping,
+            MotorBias(_) => ModeInformationType::MotorBias,
+            CapabilityBits(_) => ModeInformationType::CapabilityBits,
+            ValueFormat(_) => ModeInformationType::ValueFormat,
+        }
+    }
+    pub fn serialise(&self) -> Vec<u8> {
+        use PortModeInformationType::*;
+        let range = |info_type: ModeInformationType, min: &f32, max: &f32| {
+            let mut bytes = vec![info_type as u8];
+            bytes.extend_from_slice(&min.to_le_bytes());
+            bytes.extend_from_slice(&max.to_le_bytes());
+            bytes
+        };
+        match self {
+            Name(name) => {
+                let mut bytes = vec![ModeInformationType::Name as u8];
+                bytes.extend_from_slice(name);
+                bytes
+            }
+            RawRange { min, max } => range(ModeInformationType::Raw, min, max),
+            PctRange { min, max } => range(ModeInformationType::Pct, min, max),
+            SiRange { min, max } => range(ModeInformationType::Si, min, max),
+            Symbol(symbol) => {
+                let mut bytes = vec![ModeInformationType::Symbol as u8];
+                bytes.extend_from_slice(symbol);
+                bytes
+            }
+            Mapping { input, output } => {
+                vec![ModeInformationType::Mapping as u8, input.0, output.0]
+            }
+            MotorBias(bias) => {
+                vec![ModeInformationType::MotorBias as u8, *bias]
+            }
+            CapabilityBits(bits) => {
+                let mut bytes = vec![ModeInformationType::CapabilityBits as u8];
+                bytes.extend_from_slice(bits);
+                bytes
+            }
+            ValueFormat(format) => vec![
+                ModeInformationType::ValueFormat as u8,
+                format.number_of_datasets,
+                format.dataset_type as u8,
+                format.total_figures,
+                format.decimals,
+            ],
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
@@ -1166,17 +1409,26 @@ impl fmt::Display for DatasetType {
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum TypedValue {
-    Bits8(u8),
-    Bits16(u16),
-    Bits32(u32),
+    Bits8(i8),
+    Bits16(i16),
+    Bits32(i32),
     Float(f32),
 }
+impl TypedValue {
+    pub fn to_f32(self) -> f32 {
+        match self {
+            TypedValue::Bits8(v) => v as f32,
+            TypedValue::Bits16(v) => v as f32,
+            TypedValue::Bits32(v) => v as f32,
+            TypedValue::Float(v) => v,
+        }
+    }
+}
 
 /// The PortValueSingleFormat is a list of port id & value pairs, except
 /// that the values may be different lengths (u8, u16, u32, f32) depending
-/// on the port configuration. For now we just save the payload and then
-/// later on will provide a method to split it out into port-value pairs
-/// based on a separate port type mapping.
+/// on the port configuration. We save the raw payload; `process` splits it
+/// into values given the value format of the port's mode.
 ///
 /// Notes on Value Format
 /// 1) The valuetypes are signed, i.e. the variants are i8, i16, i32, f32. This:
@@ -1185,16 +1437,16 @@ pub enum TypedValue {
 ///    report negative values, as can be seen by requesting Port Mode Information::Raw range.
 /// 2) The values are not a single value but an array, the length of which is given
 ///    by the "number_of_datasets"-member of Value Format.
-///    ("Single" in PortValueSingle refers to single sensor mode, but single sensors can)  
-///     and do provide provide array data, ex. color RGB or accelerometer XYZ-data.)
+///    ("Single" in PortValueSingle refers to single sensor mode, but single sensors can)
+///    and do provide provide array data, ex. color RGB or accelerometer XYZ-data.)
 /// 3) There are some inconsistencies looking at port mode information:
-///         HubLeds in RBG reports taking 8 bit values in the range 0-255, though this
-///         doesn't concern the parser of incoming values. As regards sensors;
-///         TechnicHubTiltSensor mode CFG, as well as MoveHubInternalTilt modes IM_CF
-///         and CALIB: These all report that they will provide 8 bit values in
-///         range 0-255.
-///     But these are the only ones I've been able to find. On the whole it seems better
-///     to correctly support the multitude of sensors and modes.
+///    HubLeds in RBG reports taking 8 bit values in the range 0-255, though this
+///    doesn't concern the parser of incoming values. As regards sensors;
+///    TechnicHubTiltSensor mode CFG, as well as MoveHubInternalTilt modes IM_CF
+///    and CALIB: These all report that they will provide 8 bit values in
+///    range 0-255.
+///    But these are the only ones I've been able to find. On the whole it seems better
+///    to correctly support the multitude of sensors and modes.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct PortValueSingleFormat {
     pub port_id: u8,
@@ -1206,15 +1458,42 @@ impl PortValueSingleFormat {
         let data = msg.cloned().map(|x| x as i8).collect();
         Ok(Self { port_id, data })
     }
+    pub fn serialise(&self) -> Vec<u8> {
+        let mut msg = Vec::with_capacity(self.data.len() + 4);
+        msg.extend_from_slice(&[
+            0,
+            0,
+            MessageType::PortValueSingle as u8,
+            self.port_id,
+        ]);
+        msg.extend(self.data.iter().map(|x| *x as u8));
+        msg
+    }
 
-    pub fn process(&self, _type_mapping: ()) -> HashMap<u8, TypedValue> {
-        unimplemented!()
+    /// Split the payload into the datasets described by the value format
+    /// of the port's current mode
+    pub fn process(&self, format: &ValueFormatType) -> Result<Vec<TypedValue>> {
+        let bytes: Vec<u8> = self.data.iter().map(|x| *x as u8).collect();
+        let mut msg = bytes.iter();
+        let mut values = Vec::with_capacity(format.number_of_datasets as usize);
+        for _ in 0..format.number_of_datasets {
+            let value = match format.dataset_type {
+                DatasetType::Bits8 => TypedValue::Bits8(next_i8!(msg)),
+                DatasetType::Bits16 => TypedValue::Bits16(next_i16!(msg)),
+                DatasetType::Bits32 => TypedValue::Bits32(next_i32!(msg)),
+                DatasetType::Float => TypedValue::Float(next_f32!(msg)),
+            };
+            values.push(value);
+        }
+        Ok(values)
     }
 }
 
-/// The PortValueCombinedFormat is some horrific set of pointers to
-/// values we should already have cached elsewhere. For now we save the
-/// raw data and leave parsing it for later.
+/// The PortValueCombinedFormat starts with a 16 bit pointer, a bit field
+/// of the mode/dataset entries of the combination that are reported,
+/// followed by their values in entry order. Entries that are left out
+/// haven't changed since they were last reported. We save the raw data;
+/// `process` splits it given the dataset types of the entries.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct PortValueCombinedFormat {
     pub port_id: u8,
@@ -1227,6 +1506,42 @@ impl PortValueCombinedFormat {
         let data = msg.cloned().collect();
         Ok(Self { port_id, data })
     }
+    pub fn serialise(&self) -> Vec<u8> {
+        let mut msg = Vec::with_capacity(self.data.len() + 4);
+        msg.extend_from_slice(&[
+            0,
+            0,
+            MessageType::PortValueCombined as u8,
+            self.port_id,
+        ]);
+        msg.extend_from_slice(&self.data);
+        msg
+    }
+
+    /// Split the payload into the values of the reported entries, given
+    /// the dataset type of each entry of the combination. Returns the
+    /// index of each reported entry along with its value.
+    pub fn process(
+        &self,
+        entries: &[DatasetType],
+    ) -> Result<Vec<(usize, TypedValue)>> {
+        let mut msg = self.data.iter();
+        let pointer = next_u16!(msg);
+        let mut values = Vec::new();
+        for (index, dataset_type) in entries.iter().enumerate() {
+            if pointer & (1 << index) == 0 {
+                continue;
+            }
+            let value = match dataset_type {
+                DatasetType::Bits8 => TypedValue::Bits8(next_i8!(msg)),
+                DatasetType::Bits16 => TypedValue::Bits16(next_i16!(msg)),
+                DatasetType::Bits32 => TypedValue::Bits32(next_i32!(msg)),
+                DatasetType::Float => TypedValue::Float(next_f32!(msg)),
+            };
+            values.push((index, value));
+        }
+        Ok(values)
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -1257,15 +1572,28 @@ impl PortInputFormatSingleFormat {
             notification_enabled,
         })
     }
+    pub fn serialise(&self) -> Vec<u8> {
+        let mut msg = Vec::with_capacity(10);
+        msg.extend_from_slice(&[
+            0,
+            0,
+            MessageType::PortInputFormatSingle as u8,
+            self.port_id,
+            self.mode,
+        ]);
+        msg.extend_from_slice(&self.delta.to_le_bytes());
+        msg.push(self.notification_enabled as u8);
+        msg
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct PortInputFormatCombinedFormat {
-    port_id: u8,
-    control: u8,
-    combination_index: u8,
-    multi_update: bool,
-    mode_dataset_combination_pointer: u16,
+    pub port_id: u8,
+    pub control: u8,
+    pub combination_index: u8,
+    pub multi_update: bool,
+    pub mode_dataset_combination_pointer: u16,
 }
 
 impl PortInputFormatCombinedFormat {
@@ -1273,10 +1601,9 @@ impl PortInputFormatCombinedFormat {
         let port_id = next!(msg);
         let control = next!(msg);
 
-        // let combination_index = next!(msg);  // combination index is part of control byte, not separate byte.
-        // This caused function to fail with "NoneError: Insufficient length"
-        let combination_index: u8 = 0; // Set to 0 for now, figure out how to get from control byte later
-
+        // Combination index is the lower bits of the control byte, not a
+        // separate byte.
+        let combination_index = control & 0x7f;
         let multi_update = (control >> 7) != 0;
         let mode_dataset_combination_pointer = next_u16!(msg);
 
@@ -1288,6 +1615,20 @@ impl PortInputFormatCombinedFormat {
             mode_dataset_combination_pointer,
         })
     }
+    pub fn serialise(&self) -> Vec<u8> {
+        let mut msg = Vec::with_capacity(7);
+        msg.extend_from_slice(&[
+            0,
+            0,
+            MessageType::PortInputFormatCombined as u8,
+            self.port_id,
+            self.control,
+        ]);
+        msg.extend_from_slice(
+            &self.mode_dataset_combination_pointer.to_le_bytes(),
+        );
+        msg
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -1317,6 +1658,17 @@ impl VirtualPortSetupFormat {
             ))),
         }
     }
+    pub fn serialise(&self) -> Vec<u8> {
+        use VirtualPortSetupFormat::*;
+        let mut msg = vec![0, 0, MessageType::VirtualPortSetup as u8];
+        match self {
+            Disconnect { port_id } => msg.extend_from_slice(&[0x00, *port_id]),
+            Connect { port_a, port_b } => {
+                msg.extend_from_slice(&[0x01, *port_a, *port_b])
+            }
+        }
+        msg
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -1359,7 +1711,7 @@ impl PortOutputCommandFormat {
                 use_dec_profile,
             } => {
                 let profile =
-                    ((*use_acc_profile as u8) << 1) | (*use_dec_profile as u8);
+                    (*use_acc_profile as u8) | ((*use_dec_profile as u8) << 1);
                 let speed = speed.to_le_bytes()[0];
                 let max_power = max_power.to_le_bytes()[0];
                 vec![
@@ -1387,7 +1739,7 @@ impl PortOutputCommandFormat {
                 use_dec_profile,
             } => {
                 let profile =
-                    ((*use_acc_profile as u8) << 1) | (*use_dec_profile as u8);
+                    (*use_acc_profile as u8) | ((*use_dec_profile as u8) << 1);
                 let speed = speed.to_le_bytes()[0];
                 let max_power = max_power.to_le_bytes()[0];
                 let degrees = degrees.to_le_bytes();
@@ -1419,7 +1771,7 @@ impl PortOutputCommandFormat {
                 use_dec_profile,
             } => {
                 let profile =
-                    ((*use_acc_profile as u8) << 1) | (*use_dec_profile as u8);
+                    (*use_acc_profile as u8) | ((*use_dec_profile as u8) << 1);
                 let speed = speed.to_le_bytes()[0];
                 let max_power = max_power.to_le_bytes()[0];
                 let abs_pos = abs_pos.to_le_bytes();
@@ -1451,7 +1803,7 @@ impl PortOutputCommandFormat {
                 use_dec_profile,
             } => {
                 let profile =
-                    ((*use_acc_profile as u8) << 1) | (*use_dec_profile as u8);
+                    (*use_acc_profile as u8) | ((*use_dec_profile as u8) << 1);
                 let speed = speed.to_le_bytes()[0];
                 let max_power = max_power.to_le_bytes()[0];
                 //dbg!(time);
@@ -1520,7 +1872,199 @@ impl PortOutputCommandFormat {
 
                 bytes
             }
-            _ => todo!(),
+            StartPower2 { power1, power2 } => {
+                vec![
+                    // Header
+                    0, // len
+                    0, // hub id - always set to 0
+                    MessageType::PortOutputCommand as u8,
+                    // Command
+                    self.port_id,
+                    startup_and_completion_byte,
+                    PortOutputSubCommandValue::StartPower2 as u8,
+                    // Subcommand payload
+                    power1.to_u8(),
+                    power2.to_u8(),
+                ]
+            }
+            // There is no separate subcommand for this, it goes out as a
+            // regular StartSpeed
+            StartSpeedNoPower {
+                speed,
+                max_power,
+                use_acc_profile,
+                use_dec_profile,
+            } => {
+                let profile =
+                    (*use_acc_profile as u8) | ((*use_dec_profile as u8) << 1);
+                vec![
+                    // Header
+                    0, // len
+                    0, // hub id - always set to 0
+                    MessageType::PortOutputCommand as u8,
+                    // Command
+                    self.port_id,
+                    startup_and_completion_byte,
+                    PortOutputSubCommandValue::StartSpeed as u8,
+                    // Subcommand payload
+                    speed.to_le_bytes()[0],
+                    *max_power,
+                    profile,
+                ]
+            }
+            StartSpeed2 {
+                speed1,
+                speed2,
+                max_power,
+                use_acc_profile,
+                use_dec_profile,
+            } => {
+                let profile =
+                    (*use_acc_profile as u8) | ((*use_dec_profile as u8) << 1);
+                vec![
+                    // Header
+                    0, // len
+                    0, // hub id - always set to 0
+                    MessageType::PortOutputCommand as u8,
+                    // Command
+                    self.port_id,
+                    startup_and_completion_byte,
+                    PortOutputSubCommandValue::StartSpeed2 as u8,
+                    // Subcommand payload
+                    speed1.to_le_bytes()[0],
+                    speed2.to_le_bytes()[0],
+                    *max_power,
+                    profile,
+                ]
+            }
+            StartSpeedForTime2 {
+                time,
+                speed_l,
+                speed_r,
+                max_power,
+                end_state,
+                use_acc_profile,
+                use_dec_profile,
+            } => {
+                let profile =
+                    (*use_acc_profile as u8) | ((*use_dec_profile as u8) << 1);
+                let mut bytes = vec![
+                    // Header
+                    0, // len
+                    0, // hub id - always set to 0
+                    MessageType::PortOutputCommand as u8,
+                    // Command
+                    self.port_id,
+                    startup_and_completion_byte,
+                    PortOutputSubCommandValue::StartSpeedForTime2 as u8,
+                ];
+                // Subcommand payload
+                bytes.extend_from_slice(&time.to_le_bytes());
+                bytes.push(speed_l.to_le_bytes()[0]);
+                bytes.push(speed_r.to_le_bytes()[0]);
+                bytes.push(*max_power);
+                bytes.push(end_state.to_u8());
+                bytes.push(profile);
+
+                bytes
+            }
+            StartSpeedForDegrees2 {
+                degrees,
+                speed_l,
+                speed_r,
+                max_power,
+                end_state,
+                use_acc_profile,
+                use_dec_profile,
+            } => {
+                let profile =
+                    (*use_acc_profile as u8) | ((*use_dec_profile as u8) << 1);
+                let mut bytes = vec![
+                    // Header
+                    0, // len
+                    0, // hub id - always set to 0
+                    MessageType::PortOutputCommand as u8,
+                    // Command
+                    self.port_id,
+                    startup_and_completion_byte,
+                    PortOutputSubCommandValue::StartSpeedForDegrees2 as u8,
+                ];
+                // Subcommand payload
+                bytes.extend_from_slice(&degrees.to_le_bytes());
+                bytes.push(speed_l.to_le_bytes()[0]);
+                bytes.push(speed_r.to_le_bytes()[0]);
+                bytes.push(*max_power);
+                bytes.push(end_state.to_u8());
+                bytes.push(profile);
+
+                bytes
+            }
+            GotoAbsolutePosition2 {
+                abs_pos1,
+                abs_pos2,
+                speed,
+                max_power,
+                end_state,
+                use_acc_profile,
+                use_dec_profile,
+            } => {
+                let profile =
+                    (*use_acc_profile as u8) | ((*use_dec_profile as u8) << 1);
+                let mut bytes = vec![
+                    // Header
+                    0, // len
+                    0, // hub id - always set to 0
+                    MessageType::PortOutputCommand as u8,
+                    // Command
+                    self.port_id,
+                    startup_and_completion_byte,
+                    PortOutputSubCommandValue::GotoAbsolutePosition2 as u8,
+                ];
+                // Subcommand payload
+                bytes.extend_from_slice(&abs_pos1.to_le_bytes());
+                bytes.extend_from_slice(&abs_pos2.to_le_bytes());
+                bytes.push(speed.to_le_bytes()[0]);
+                bytes.push(*max_power);
+                bytes.push(end_state.to_u8());
+                bytes.push(profile);
+
+                bytes
+            }
+            PresetEncoder2 {
+                left_position,
+                right_position,
+            } => {
+                let mut bytes = vec![
+                    // Header
+                    0, // len
+                    0, // hub id - always set to 0
+                    MessageType::PortOutputCommand as u8,
+                    // Command
+                    self.port_id,
+                    startup_and_completion_byte,
+                    PortOutputSubCommandValue::PresetEncoder2 as u8,
+                ];
+                // Subcommand payload
+                bytes.extend_from_slice(&left_position.to_le_bytes());
+                bytes.extend_from_slice(&right_position.to_le_bytes());
+
+                bytes
+            }
+            WriteDirect(data) => {
+                let mut bytes = vec![
+                    // Header
+                    0, // len
+                    0, // hub id - always set to 0
+                    MessageType::PortOutputCommand as u8,
+                    // Command
+                    self.port_id,
+                    startup_and_completion_byte,
+                    0x50, // WriteDirect
+                ];
+                bytes.extend(data.serialise());
+
+                bytes
+            }
         }
     }
 }
@@ -1941,8 +2485,43 @@ pub enum WriteDirectPayload {
 }
 
 impl WriteDirectPayload {
-    pub fn parse<'a>(_msg: impl Iterator<Item = &'a u8>) -> Result<Self> {
-        todo!()
+    pub fn parse<'a>(mut msg: impl Iterator<Item = &'a u8>) -> Result<Self> {
+        use WriteDirectPayload::*;
+        let first = next!(msg);
+        if first == 0xd4 && next!(msg) == 0x11 {
+            return Ok(HardwareReset);
+        }
+        let orientation = match CalibrationOrientation::from_u8(first) {
+            Some(orientation) => orientation,
+            None => {
+                return Err(Error::ParseError(format!(
+                    "Unsupported write direct payload starting {:#x}",
+                    first
+                )))
+            }
+        };
+        let pass_code = String::from_utf8(msg.copied().collect())
+            .map_err(|e| Error::ParseError(e.to_string()))?;
+        Ok(TiltFactoryCalibration {
+            orientation,
+            pass_code,
+        })
+    }
+
+    /// Payload bytes following the WriteDirect subcommand
+    pub fn serialise(&self) -> Vec<u8> {
+        use WriteDirectPayload::*;
+        match self {
+            TiltFactoryCalibration {
+                orientation,
+                pass_code,
+            } => {
+                let mut bytes = vec![*orientation as u8];
+                bytes.extend_from_slice(pass_code.as_bytes());
+                bytes
+            }
+            HardwareReset => vec![0xd4, 0x11],
+        }
     }
 }
 
@@ -1996,45 +2575,91 @@ pub enum WriteDirectModeDataPayload {
         bump_holdoff: i8,
     },
     TiltFactoryCalibration(i8),
+    // The Technic hub's tilt sensor takes the impact count preset in mode 1
+    // and the impact setup in mode 2, where these parse as Raw
+    TechnicTiltImpactPreset(i32),
+    TechnicTiltConfigImpact {
+        impact_threshold: i8,
+        bump_holdoff: i8,
+    },
     SetHubColor(i8),
+    // These share modes 3 and 5 with TiltImpactPreset and
+    // TiltConfigOrientation, and parse as Raw
+    /// Brightness of the color sensor's three lights in percent
+    SetColorSensorLights([u8; 3]),
+    /// Brightness of the distance sensor's four eye lights in percent
+    SetDistanceSensorLights([u8; 4]),
+    // These share modes 2 and 3 with PresetEncoder and TiltImpactPreset,
+    // and parse as Raw
+    /// Brightness and color of each pixel of the 3x3 light matrix
+    SetLightMatrixPixels([u8; 9]),
+    SetLightMatrixTransition(u8),
+    // The Duplo train base speaker takes these in modes 1 and 2, where
+    // they parse as Raw
+    PlayDuploSound(DuploTrainBaseSound),
+    PlayDuploTone(u8),
     SetHubRgb {
         red: u8,
         green: u8,
         blue: u8,
     },
     SetVisionSensorColor(i8),
+    /// Data for a mode no other variant parses as, or of another length
+    Raw {
+        mode: u8,
+        data: Vec<u8>,
+    },
 }
 
 impl WriteDirectModeDataPayload {
     pub fn parse<'a>(mut msg: impl Iterator<Item = &'a u8>) -> Result<Self> {
+        let mode = next!(msg);
+        let data: Vec<u8> = msg.copied().collect();
+        Ok(match Self::parse_mode(mode, &data) {
+            Ok(payload) => payload,
+            Err(_) => WriteDirectModeDataPayload::Raw { mode, data },
+        })
+    }
+
+    /// The mode alone doesn't tell which device the command is for, so
+    /// SetHubColor (mode 0) parses as StartPower and SetVisionSensorColor
+    /// (mode 5) as TiltConfigOrientation. Data of another length than the
+    /// command's doesn't parse.
+    fn parse_mode(mode: u8, data: &[u8]) -> Result<Self> {
         use WriteDirectModeDataPayload::*;
 
-        let mode = next!(msg);
-        Ok(match mode {
-            0x01 => {
-                //Should be 0x00 according to docs? Seems to work, I may be misreading.
+        let mut msg = data.iter();
+        Ok(match (mode, data.len()) {
+            (0x00, 1) => {
                 // StartPower(Power)
                 let power = Power::parse(&mut msg)?;
                 StartPower(power)
             }
-            0x02 => {
+            (0x01, 3) => {
+                // SetHubRgb(RedColor, GreenColor, BlueColor)
+                let red = next!(msg);
+                let green = next!(msg);
+                let blue = next!(msg);
+                SetHubRgb { red, green, blue }
+            }
+            (0x02, 4) => {
                 // PresetEncoder(Position)
                 let position = next_i32!(msg);
                 PresetEncoder(position)
             }
-            0x03 => {
+            (0x03, 4) => {
                 // TiltImpactPreset(PresetValue)
                 // "Mode 3 impact counts"
                 let preset_value = next_i32!(msg);
                 TiltImpactPreset(preset_value)
             }
-            0x05 => {
+            (0x05, 1) => {
                 // TiltConfigOrientation(Orientation)
                 // "Mode 5"
                 let orientation = Orientation::parse(&mut msg)?;
                 TiltConfigOrientation(orientation)
             }
-            0x06 => {
+            (0x06, 2) => {
                 // TiltConfigImpact(ImpactThreshold, BumpHoldoff)
                 // "Mode 6"
                 let impact_threshold = next_i8!(msg);
@@ -2044,29 +2669,17 @@ impl WriteDirectModeDataPayload {
                     bump_holdoff,
                 }
             }
-            0x07 => {
+            (0x07, 13) => {
                 // TiltFactoryCalibration(Orientation, CalibrationPassCode)  Passcode is 12 chars: Calib-Sensor
                 // "Mode 7"
                 let orientation = next_i8!(msg);
                 // let passcode = next_i8!(msg);
                 TiltFactoryCalibration(orientation)
             }
-            0x08 => {
-                // SetHubColor(ColorNo)
-                let col = next_i8!(msg);
-                SetHubColor(col)
-            }
-            0x09 => {
-                // SetHubRgb(RedColor, GreenColor, BlueColor)
-                let red = next!(msg);
-                let green = next!(msg);
-                let blue = next!(msg);
-                SetHubRgb { red, green, blue }
-            }
-            m => {
+            (m, len) => {
                 return Err(Error::ParseError(format!(
-                    "Invalid write direct mode {}",
-                    m
+                    "No write direct mode {} command with {} bytes",
+                    m, len
                 )))
             }
         })
@@ -2219,6 +2832,129 @@ impl WriteDirectModeDataPayload {
                     b'r',
                 ]
             }
+            TechnicTiltImpactPreset(preset_value) => {
+                let startup_and_completion =
+                    meta.startup_info.serialise(&meta.completion_info);
+                let val_bytes: [u8; 4] = preset_value.to_le_bytes(); // i32 sent as 4 bytes
+                vec![
+                    0,
+                    0, // hub id
+                    MessageType::PortOutputCommand as u8,
+                    meta.port_id,
+                    startup_and_completion,
+                    0x51, // WriteDirect
+                    crate::iodevice::modes::TechnicHubTiltSensor::IMP,
+                    val_bytes[0],
+                    val_bytes[1],
+                    val_bytes[2],
+                    val_bytes[3],
+                ]
+            }
+            TechnicTiltConfigImpact {
+                impact_threshold,
+                bump_holdoff,
+            } => {
+                let startup_and_completion =
+                    meta.startup_info.serialise(&meta.completion_info);
+                vec![
+                    0,
+                    0, // hub id
+                    MessageType::PortOutputCommand as u8,
+                    meta.port_id,
+                    startup_and_completion,
+                    0x51, // WriteDirect
+                    crate::iodevice::modes::TechnicHubTiltSensor::CFG,
+                    *impact_threshold as u8,
+                    *bump_holdoff as u8,
+                ]
+            }
+            SetColorSensorLights(brightness) => {
+                let startup_and_completion =
+                    meta.startup_info.serialise(&meta.completion_info);
+                let mut bytes = vec![
+                    0,
+                    0, // hub id
+                    MessageType::PortOutputCommand as u8,
+                    meta.port_id,
+                    startup_and_completion,
+                    0x51, // WriteDirect
+                    crate::iodevice::modes::TechnicColorSensor::LIGHT,
+                ];
+                bytes.extend_from_slice(brightness);
+                bytes
+            }
+            SetDistanceSensorLights(brightness) => {
+                let startup_and_completion =
+                    meta.startup_info.serialise(&meta.completion_info);
+                let mut bytes = vec![
+                    0,
+                    0, // hub id
+                    MessageType::PortOutputCommand as u8,
+                    meta.port_id,
+                    startup_and_completion,
+                    0x51, // WriteDirect
+                    crate::iodevice::modes::TechnicDistanceSensor::LIGHT,
+                ];
+                bytes.extend_from_slice(brightness);
+                bytes
+            }
+            SetLightMatrixPixels(pixels) => {
+                let startup_and_completion =
+                    meta.startup_info.serialise(&meta.completion_info);
+                let mut bytes = vec![
+                    0,
+                    0, // hub id
+                    MessageType::PortOutputCommand as u8,
+                    meta.port_id,
+                    startup_and_completion,
+                    0x51, // WriteDirect
+                    crate::iodevice::modes::Technic3x3ColorLightMatrix::PIX_O,
+                ];
+                bytes.extend_from_slice(pixels);
+                bytes
+            }
+            SetLightMatrixTransition(transition) => {
+                let startup_and_completion =
+                    meta.startup_info.serialise(&meta.completion_info);
+                vec![
+                    0,
+                    0, // hub id
+                    MessageType::PortOutputCommand as u8,
+                    meta.port_id,
+                    startup_and_completion,
+                    0x51, // WriteDirect
+                    crate::iodevice::modes::Technic3x3ColorLightMatrix::TRANS,
+                    *transition,
+                ]
+            }
+            PlayDuploSound(sound) => {
+                let startup_and_completion =
+                    meta.startup_info.serialise(&meta.completion_info);
+                vec![
+                    0,
+                    0, // hub id
+                    MessageType::PortOutputCommand as u8,
+                    meta.port_id,
+                    startup_and_completion,
+                    0x51, // WriteDirect
+                    crate::iodevice::modes::DuploTrainBaseSpeaker::SOUND,
+                    *sound as u8,
+                ]
+            }
+            PlayDuploTone(tone) => {
+                let startup_and_completion =
+                    meta.startup_info.serialise(&meta.completion_info);
+                vec![
+                    0,
+                    0, // hub id
+                    MessageType::PortOutputCommand as u8,
+                    meta.port_id,
+                    startup_and_completion,
+                    0x51, // WriteDirect
+                    crate::iodevice::modes::DuploTrainBaseSpeaker::TONE,
+                    *tone,
+                ]
+            }
             SetVisionSensorColor(c) => {
                 let startup_and_completion =
                     meta.startup_info.serialise(&meta.completion_info);
@@ -2233,6 +2969,21 @@ impl WriteDirectModeDataPayload {
                     *c as u8,
                 ]
             }
+            Raw { mode, data } => {
+                let startup_and_completion =
+                    meta.startup_info.serialise(&meta.completion_info);
+                let mut bytes = vec![
+                    0,
+                    0, // hub id
+                    MessageType::PortOutputCommand as u8,
+                    meta.port_id,
+                    startup_and_completion,
+                    0x51, // WriteDirect
+                    *mode,
+                ];
+                bytes.extend_from_slice(data);
+                bytes
+            }
         }
     }
 }
@@ -2269,6 +3020,19 @@ impl PortOutputCommandFeedbackFormat {
         let msg3 = FeedbackMessage::parse(&mut msg).ok();
         Ok(PortOutputCommandFeedbackFormat { msg1, msg2, msg3 })
     }
+    pub fn serialise(&self) -> Vec<u8> {
+        let mut msg = Vec::with_capacity(9);
+        msg.extend_from_slice(&[
+            0,
+            0,
+            MessageType::PortOutputCommandFeedback as u8,
+        ]);
+        for feedback in [Some(self.msg1), self.msg2, self.msg3].iter().flatten()
+        {
+            msg.extend(feedback.serialise());
+        }
+        msg
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -2299,4 +3063,12 @@ impl FeedbackMessage {
             busy_full,
         })
     }
+    pub fn serialise(&self) -> Vec<u8> {
+        let bitfields = (self.empty_cmd_in_progress as u8)
+            | (self.empty_cmd_completed as u8) << 1
+            | (self.discarded as u8) << 2
+            | (self.idle as u8) << 3
+            | (self.busy_full as u8) << 4;
+        vec![self.port_id, bitfields]
+    }
 }
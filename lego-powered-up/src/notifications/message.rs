@@ -221,7 +221,6 @@ impl NotificationMessage {
         } else {
             // high bit set - length is both bytes with a bit missing
             let second = next!(msg); // only advance if needed
-            eprintln!("second: {:x}", second);
             ((second as usize) << 7) | ((first & 0x7f) as usize)
         };
 
@@ -233,7 +232,8 @@ impl NotificationMessage {
         buf.iter().fold(0xff, |acc, x| acc ^ x)
     }
 
-    /// Serialise a notification message into a Vec<u8>
+    /// Serialise a notification message into a Vec<u8>. Covers messages
+    /// in both directions, so parse(serialise(msg)) gives back msg.
     /// TODO no alloc
     pub fn serialise(&self) -> Vec<u8> {
         use NotificationMessage::*;
@@ -242,33 +242,49 @@ impl NotificationMessage {
             HubProperties(msg) => msg.serialise(),
             HubActions(msg) => msg.serialise(),
             HubAlerts(msg) => msg.serialise(),
-            HwNetworkCommands(_) => todo!(),
-            FwUpdateGoIntoBootMode(_) => todo!(),
-            FwUpdateLockMemory(_) => todo!(),
-            FwUpdateLockStatusRequest => todo!(),
+            HubAttachedIo(msg) => msg.serialise(),
+            GenericErrorMessages(msg) => msg.serialise(),
+            HwNetworkCommands(msg) => msg.serialise(),
+            FwUpdateGoIntoBootMode(safety) => {
+                let mut ser =
+                    vec![0, 0, MessageType::FwUpdateGoIntoBootMode as u8];
+                ser.extend_from_slice(safety);
+                ser
+            }
+            FwUpdateLockMemory(safety) => {
+                let mut ser = vec![0, 0, MessageType::FwUpdateLockMemory as u8];
+                ser.extend_from_slice(safety);
+                ser
+            }
+            FwUpdateLockStatusRequest => {
+                vec![0, 0, MessageType::FwUpdateLockStatusRequest as u8]
+            }
+            FwLockStatus(status) => {
+                vec![0, 0, MessageType::FwLockStatus as u8, *status as u8]
+            }
             PortInformationRequest(msg) => msg.serialise(),
             PortModeInformationRequest(msg) => msg.serialise(),
             PortInputFormatSetupSingle(msg) => msg.serialise(),
             PortInputFormatSetupCombinedmode(msg) => msg.serialise(),
-            VirtualPortSetup(_) => todo!(),
+            PortInformation(msg) => msg.serialise(),
+            PortModeInformation(msg) => msg.serialise(),
+            PortValueSingle(msg) => msg.serialise(),
+            PortValueCombined(msg) => msg.serialise(),
+            PortInputFormatSingle(msg) => msg.serialise(),
+            PortInputFormatCombinedmode(msg) => msg.serialise(),
+            VirtualPortSetup(msg) => msg.serialise(),
             PortOutputCommand(cmd) => cmd.serialise(),
-
-            // Documentation unclear; marked as upstream only but desc says
-            // it can be used to request data.
-            HubAttachedIo(_) => todo!(),
-
-            // These are upstream only and shouldn't need serialisation
-            GenericErrorMessages(_) => todo!(),
-            FwLockStatus(_) => todo!(),
-            PortInformation(_) => todo!(),
-            PortModeInformation(_) => todo!(),
-            PortValueSingle(_) => todo!(),
-            PortValueCombined(_) => todo!(),
-            PortInputFormatSingle(_) => todo!(),
-            PortInputFormatCombinedmode(_) => todo!(),
-            PortOutputCommandFeedback(_) => todo!(),
+            PortOutputCommandFeedback(msg) => msg.serialise(),
         };
-        ser[0] = ser.len() as u8;
+        let len = ser.len();
+        if len > 0x7f {
+            // Two byte length, which itself counts towards the total
+            let len = len + 1;
+            ser[0] = (len as u8 & 0x7f) | 0x80;
+            ser.insert(1, (len >> 7) as u8);
+        } else {
+            ser[0] = len as u8;
+        }
         debug!("Serialised to: {:02x?}", ser);
         ser
     }
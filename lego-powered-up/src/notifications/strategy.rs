@@ -0,0 +1,591 @@
+//! Proptest generators for the notification types.
+//!
+//! Only messages that parse back unchanged are generated, so commands that
+//! are encoded the same as another command are left out.
+
+use super::*;
+use proptest::prelude::*;
+use proptest::sample::select;
+
+/// Strategy picking one of the listed unit variants
+macro_rules! variants {
+    ($enum:ident: $($variant:ident),+ $(,)?) => {
+        select(vec![$($enum::$variant),+])
+    };
+}
+
+fn bytes(max: usize) -> impl Strategy<Value = Vec<u8>> {
+    prop::collection::vec(any::<u8>(), 0..max)
+}
+
+fn float() -> impl Strategy<Value = f32> {
+    -1.0e6_f32..1.0e6_f32
+}
+
+fn io_type_id() -> impl Strategy<Value = IoTypeId> {
+    (0_u16..=0x50).prop_filter_map("not an IoTypeId", IoTypeId::from_u16)
+}
+
+fn version_number() -> impl Strategy<Value = VersionNumber> {
+    (0_u8..8, 0_u8..16, 0_u8..100, any::<u16>()).prop_map(
+        |(major, minor, bugfix, build)| VersionNumber {
+            major,
+            minor,
+            bugfix,
+            build,
+        },
+    )
+}
+
+pub fn power() -> impl Strategy<Value = Power> {
+    prop_oneof![
+        (1_u8..=100).prop_map(Power::Cw),
+        (1_u8..=100).prop_map(Power::Ccw),
+        Just(Power::Float),
+        Just(Power::Brake),
+    ]
+}
+
+fn end_state() -> impl Strategy<Value = EndState> {
+    variants!(EndState: Float, Hold, Brake)
+}
+
+fn network_family() -> impl Strategy<Value = NetworkFamily> {
+    variants!(NetworkFamily:
+        Green, Yellow, Red, Blue, Purple, LightBlue, Teal, Pink, White)
+}
+
+fn network_subfamily() -> impl Strategy<Value = NetworkSubFamily> {
+    variants!(NetworkSubFamily:
+        OneFlash, TwoFlashes, ThreeFlashes, FourFlashes, FiveFlashes,
+        SixFlashes, SevenFlashes)
+}
+
+fn hub_property() -> impl Strategy<Value = HubProperty> {
+    use HubPropertyValue::*;
+    let update = prop_oneof![
+        bytes(20).prop_map(AdvertisingName),
+        any::<u8>().prop_map(Button),
+        any::<i32>().prop_map(FwVersion),
+        any::<i32>().prop_map(HwVersion),
+        any::<i8>().prop_map(Rssi),
+        any::<u8>().prop_map(BatteryVoltage),
+        variants!(HubBatteryType: Normal, Rechargeable).prop_map(BatteryType),
+        bytes(20).prop_map(ManufacturerName),
+        bytes(20).prop_map(RadioFirmwareVersion),
+        any::<u16>().prop_map(LegoWirelessProtocolVersion),
+        any::<u8>().prop_map(SystemTypeId),
+        any::<u8>().prop_map(HwNetworkId),
+        any::<[u8; 6]>().prop_map(PrimaryMacAddress),
+        Just(SecondaryMacAddress),
+        any::<u8>().prop_map(HardwareNetworkFamily),
+    ];
+    let with_payload = (
+        update,
+        variants!(HubPropertyOperation: SetDownstream, UpdateUpstream),
+    )
+        .prop_map(|(property, operation)| {
+            let reference = property.reference();
+            HubProperty {
+                property: Some(property),
+                operation,
+                reference,
+            }
+        });
+    let request = (
+        (0x01_u8..=0x0f).prop_filter_map("", HubPropertyRef::from_u8),
+        variants!(HubPropertyOperation:
+            EnableUpdatesDownstream, DisableUpdatesDownstream,
+            ResetDownstream, RequestUpdateDownstream),
+    )
+        .prop_map(|(reference, operation)| HubProperty {
+            property: None,
+            operation,
+            reference,
+        });
+    prop_oneof![with_payload, request]
+}
+
+fn hub_alert() -> impl Strategy<Value = HubAlert> {
+    let alert_type = variants!(AlertType:
+        LowVoltage, HighCurrent, LowSignalStrength, OverPowerCondition);
+    let operation = variants!(AlertOperation:
+        EnableUpdates, DisableUpdates, RequestUpdate, Update);
+    let payload = variants!(AlertPayload: StatusOk, Alert);
+    (alert_type, operation, payload).prop_map(
+        |(alert_type, operation, payload)| HubAlert {
+            alert_type,
+            operation,
+            // Only updates carry a payload
+            payload: match operation {
+                AlertOperation::Update => payload,
+                _ => AlertPayload::StatusOk,
+            },
+        },
+    )
+}
+
+fn attached_io() -> impl Strategy<Value = AttachedIo> {
+    let event = prop_oneof![
+        Just(IoAttachEvent::DetachedIo {}),
+        (io_type_id(), version_number(), version_number()).prop_map(
+            |(io_type_id, hw_rev, fw_rev)| IoAttachEvent::AttachedIo {
+                io_type_id,
+                hw_rev,
+                fw_rev,
+            }
+        ),
+        (io_type_id(), any::<u8>(), any::<u8>()).prop_map(
+            |(io_type_id, port_a, port_b)| IoAttachEvent::AttachedVirtualIo {
+                io_type_id,
+                port_a,
+                port_b,
+            }
+        ),
+    ];
+    (any::<u8>(), event).prop_map(|(port, event)| AttachedIo { port, event })
+}
+
+fn network_command() -> impl Strategy<Value = NetworkCommand> {
+    use NetworkCommand::*;
+    prop_oneof![
+        variants!(ButtonState: Pressed, Released, Up, Down, Stop)
+            .prop_map(ConnectionRequest),
+        Just(FamilyRequest),
+        Just(JoinDenied()),
+        Just(GetFamily()),
+        Just(GetSubfamily()),
+        Just(GetExtendedFamily()),
+        Just(ResetLongPressTiming()),
+        network_family().prop_map(FamilySet),
+        network_family().prop_map(Family),
+        network_subfamily().prop_map(Subfamily),
+        network_subfamily().prop_map(SubfamilySet),
+        (network_family(), network_subfamily()).prop_map(
+            |(family, subfamily)| ExtendedFamily { family, subfamily }
+        ),
+        (network_family(), network_subfamily()).prop_map(
+            |(family, subfamily)| ExtendedFamilySet { family, subfamily }
+        ),
+    ]
+}
+
+fn input_setup_combined() -> impl Strategy<Value = InputSetupCombined> {
+    use InputSetupCombinedSubcommand::*;
+    let set = (any::<u8>(), prop::collection::vec(0_u8..255, 0..=8)).prop_map(
+        |(combination_index, entries)| {
+            // Unused entries are marked with 255
+            let mut mode_dataset = [255; 8];
+            mode_dataset[..entries.len()].copy_from_slice(&entries);
+            SetModeanddatasetCombinations {
+                combination_index,
+                mode_dataset,
+            }
+        },
+    );
+    let subcommand = prop_oneof![
+        set,
+        Just(LockLpf2DeviceForSetup),
+        Just(UnlockAndStartMultiEnabled),
+        Just(UnlockAndStartMultiDisabled),
+        Just(NotUsed),
+        Just(ResetSensor),
+    ];
+    (any::<u8>(), subcommand).prop_map(|(port_id, subcommand)| {
+        InputSetupCombined {
+            port_id,
+            subcommand,
+        }
+    })
+}
+
+fn port_information() -> impl Strategy<Value = PortInformationValue> {
+    let information_type = prop_oneof![
+        (any::<u8>(), any::<u8>(), any::<u16>(), any::<u16>()).prop_map(
+            |(caps, mode_count, input_modes, output_modes)| {
+                PortInformationType::ModeInfo {
+                    capabilities: PortCapabilities(caps),
+                    mode_count,
+                    input_modes,
+                    output_modes,
+                }
+            }
+        ),
+        bytes(16).prop_map(PortInformationType::PossibleModeCombinations),
+    ];
+    (any::<u8>(), information_type).prop_map(|(port_id, information_type)| {
+        PortInformationValue {
+            port_id,
+            information_type,
+        }
+    })
+}
+
+fn port_mode_information() -> impl Strategy<Value = PortModeInformationValue> {
+    use PortModeInformationType::*;
+    let information_type = prop_oneof![
+        bytes(11).prop_map(Name),
+        (float(), float()).prop_map(|(min, max)| RawRange { min, max }),
+        (float(), float()).prop_map(|(min, max)| PctRange { min, max }),
+        (float(), float()).prop_map(|(min, max)| SiRange { min, max }),
+        bytes(5).prop_map(Symbol),
+        (any::<u8>(), any::<u8>()).prop_map(|(i, o)| Mapping {
+            input: MappingValue(i),
+            output: MappingValue(o),
+        }),
+        any::<u8>().prop_map(MotorBias),
+        any::<[u8; 6]>().prop_map(CapabilityBits),
+        (
+            any::<u8>(),
+            variants!(DatasetType: Bits8, Bits16, Bits32, Float),
+            any::<u8>(),
+            any::<u8>()
+        )
+            .prop_map(
+                |(
+                    number_of_datasets,
+                    dataset_type,
+                    total_figures,
+                    decimals,
+                )| {
+                    ValueFormat(ValueFormatType {
+                        number_of_datasets,
+                        dataset_type,
+                        total_figures,
+                        decimals,
+                    })
+                }
+            ),
+    ];
+    (any::<u8>(), any::<u8>(), information_type).prop_map(
+        |(port_id, mode, information_type)| PortModeInformationValue {
+            port_id,
+            mode,
+            information_type,
+        },
+    )
+}
+
+fn feedback_message() -> impl Strategy<Value = FeedbackMessage> {
+    (any::<u8>(), any::<[bool; 5]>()).prop_map(|(port_id, bits)| {
+        FeedbackMessage {
+            port_id,
+            empty_cmd_in_progress: bits[0],
+            empty_cmd_completed: bits[1],
+            discarded: bits[2],
+            idle: bits[3],
+            busy_full: bits[4],
+        }
+    })
+}
+
+fn command_feedback() -> impl Strategy<Value = PortOutputCommandFeedbackFormat>
+{
+    let rest = prop_oneof![
+        Just((None, None)),
+        feedback_message().prop_map(|m| (Some(m), None)),
+        (feedback_message(), feedback_message())
+            .prop_map(|(m2, m3)| (Some(m2), Some(m3))),
+    ];
+    (feedback_message(), rest).prop_map(|(msg1, (msg2, msg3))| {
+        PortOutputCommandFeedbackFormat { msg1, msg2, msg3 }
+    })
+}
+
+fn write_direct() -> impl Strategy<Value = WriteDirectPayload> {
+    prop_oneof![
+        Just(WriteDirectPayload::HardwareReset),
+        (
+            variants!(CalibrationOrientation: LayingFlat, Standing),
+            "[ -~]{0,12}"
+        )
+            .prop_map(|(orientation, pass_code)| {
+                WriteDirectPayload::TiltFactoryCalibration {
+                    orientation,
+                    pass_code,
+                }
+            }),
+    ]
+}
+
+pub fn write_direct_mode_data(
+) -> impl Strategy<Value = WriteDirectModeDataPayload> {
+    use WriteDirectModeDataPayload::*;
+    let orientation = variants!(Orientation:
+        Bottom, Front, Back, Left, Right, Top, UseActualAsBottomReference);
+    prop_oneof![
+        power().prop_map(StartPower),
+        any::<i32>().prop_map(PresetEncoder),
+        any::<i32>().prop_map(TiltImpactPreset),
+        orientation.prop_map(TiltConfigOrientation),
+        (any::<i8>(), any::<i8>()).prop_map(
+            |(impact_threshold, bump_holdoff)| TiltConfigImpact {
+                impact_threshold,
+                bump_holdoff,
+            }
+        ),
+        any::<i8>().prop_map(TiltFactoryCalibration),
+        (any::<u8>(), any::<u8>(), any::<u8>())
+            .prop_map(|(red, green, blue)| SetHubRgb { red, green, blue }),
+        // Modes no other variant parses
+        (prop_oneof![Just(0x04_u8), 0x08_u8..], bytes(16))
+            .prop_map(|(mode, data)| Raw { mode, data }),
+    ]
+}
+
+pub fn port_output_subcommand() -> impl Strategy<Value = PortOutputSubcommand> {
+    use PortOutputSubcommand::*;
+    // speed, max power, end state, acc profile, dec profile
+    let motion = || {
+        (
+            any::<i8>(),
+            any::<u8>(),
+            end_state(),
+            any::<bool>(),
+            any::<bool>(),
+        )
+    };
+    prop_oneof![
+        (power(), power())
+            .prop_map(|(power1, power2)| StartPower2 { power1, power2 }),
+        (any::<i16>(), any::<i8>()).prop_map(|(time, profile_number)| {
+            SetAccTime {
+                time,
+                profile_number,
+            }
+        }),
+        (any::<i16>(), any::<i8>()).prop_map(|(time, profile_number)| {
+            SetDecTime {
+                time,
+                profile_number,
+            }
+        }),
+        motion().prop_map(|(speed, max_power, _, acc, dec)| StartSpeed {
+            speed,
+            max_power,
+            use_acc_profile: acc,
+            use_dec_profile: dec,
+        }),
+        (any::<i8>(), motion()).prop_map(
+            |(speed2, (speed1, max_power, _, acc, dec))| StartSpeed2 {
+                speed1,
+                speed2,
+                max_power,
+                use_acc_profile: acc,
+                use_dec_profile: dec,
+            }
+        ),
+        (any::<i16>(), motion()).prop_map(
+            |(time, (speed, max_power, end_state, acc, dec))| {
+                StartSpeedForTime {
+                    time,
+                    speed,
+                    max_power,
+                    end_state,
+                    use_acc_profile: acc,
+                    use_dec_profile: dec,
+                }
+            }
+        ),
+        (any::<i16>(), any::<i8>(), motion()).prop_map(
+            |(time, speed_r, (speed_l, max_power, end_state, acc, dec))| {
+                StartSpeedForTime2 {
+                    time,
+                    speed_l,
+                    speed_r,
+                    max_power,
+                    end_state,
+                    use_acc_profile: acc,
+                    use_dec_profile: dec,
+                }
+            }
+        ),
+        (any::<i32>(), motion()).prop_map(
+            |(degrees, (speed, max_power, end_state, acc, dec))| {
+                StartSpeedForDegrees {
+                    degrees,
+                    speed,
+                    max_power,
+                    end_state,
+                    use_acc_profile: acc,
+                    use_dec_profile: dec,
+                }
+            }
+        ),
+        (any::<i32>(), any::<i8>(), motion()).prop_map(
+            |(degrees, speed_r, (speed_l, max_power, end_state, acc, dec))| {
+                StartSpeedForDegrees2 {
+                    degrees,
+                    speed_l,
+                    speed_r,
+                    max_power,
+                    end_state,
+                    use_acc_profile: acc,
+                    use_dec_profile: dec,
+                }
+            }
+        ),
+        (any::<i32>(), motion()).prop_map(
+            |(abs_pos, (speed, max_power, end_state, acc, dec))| {
+                GotoAbsolutePosition {
+                    abs_pos,
+                    speed,
+                    max_power,
+                    end_state,
+                    use_acc_profile: acc,
+                    use_dec_profile: dec,
+                }
+            }
+        ),
+        (any::<i32>(), any::<i32>(), motion()).prop_map(
+            |(abs_pos1, abs_pos2, (speed, max_power, end_state, acc, dec))| {
+                GotoAbsolutePosition2 {
+                    abs_pos1,
+                    abs_pos2,
+                    speed,
+                    max_power,
+                    end_state,
+                    use_acc_profile: acc,
+                    use_dec_profile: dec,
+                }
+            }
+        ),
+        (any::<i32>(), any::<i32>()).prop_map(
+            |(left_position, right_position)| PresetEncoder2 {
+                left_position,
+                right_position,
+            }
+        ),
+        write_direct().prop_map(WriteDirect),
+        write_direct_mode_data().prop_map(WriteDirectModeData),
+    ]
+}
+
+fn port_output_command() -> impl Strategy<Value = PortOutputCommandFormat> {
+    (
+        any::<u8>(),
+        variants!(StartupInfo: BufferIfNecessary, ExecuteImmediately),
+        variants!(CompletionInfo: NoAction, CommandFeedback),
+        port_output_subcommand(),
+    )
+        .prop_map(
+            |(port_id, startup_info, completion_info, subcommand)| {
+                PortOutputCommandFormat {
+                    port_id,
+                    startup_info,
+                    completion_info,
+                    subcommand,
+                }
+            },
+        )
+}
+
+pub fn notification_message() -> impl Strategy<Value = NotificationMessage> {
+    use NotificationMessage::*;
+    prop_oneof![
+        hub_property().prop_map(HubProperties),
+        variants!(HubAction:
+            SwitchOffHub, Disconnect, VccPortControlOn, VccPortControlOff,
+            ActivateBusyIndication, ResetBusyIndication, Shutdown,
+            HubWillSwitchOff, HubWillDisconnect, HubWillGoIntoBootMode)
+        .prop_map(|action_type| HubActions(HubActionRequest { action_type })),
+        hub_alert().prop_map(HubAlerts),
+        attached_io().prop_map(HubAttachedIo),
+        (
+            any::<u8>(),
+            variants!(ErrorCode:
+                Ack, Mack, BufferOverflow, Timeout, CommandNotRecognized,
+                InvalidUse, Overcurrent, InternalError)
+        )
+            .prop_map(|(command_type, error_code)| {
+                GenericErrorMessages(ErrorMessageFormat {
+                    command_type,
+                    error_code,
+                })
+            }),
+        network_command().prop_map(HwNetworkCommands),
+        any::<[u8; 9]>().prop_map(FwUpdateGoIntoBootMode),
+        any::<[u8; 8]>().prop_map(FwUpdateLockMemory),
+        Just(FwUpdateLockStatusRequest),
+        variants!(LockStatus: Ok, NotLocked).prop_map(FwLockStatus),
+        (
+            any::<u8>(),
+            variants!(InformationType:
+                PortValue, ModeInfo, PossibleModeCombinations)
+        )
+            .prop_map(|(port_id, information_type)| {
+                PortInformationRequest(InformationRequest {
+                    port_id,
+                    information_type,
+                })
+            }),
+        (
+            any::<u8>(),
+            any::<u8>(),
+            variants!(ModeInformationType:
+                Name, Raw, Pct, Si, Symbol, Mapping, UsedInternally,
+                MotorBias, CapabilityBits, ValueFormat)
+        )
+            .prop_map(|(port_id, mode, information_type)| {
+                PortModeInformationRequest(ModeInformationRequest {
+                    port_id,
+                    mode,
+                    information_type,
+                })
+            }),
+        (any::<u8>(), any::<u8>(), any::<u32>(), any::<bool>()).prop_map(
+            |(port_id, mode, delta, notification_enabled)| {
+                PortInputFormatSetupSingle(InputSetupSingle {
+                    port_id,
+                    mode,
+                    delta,
+                    notification_enabled,
+                })
+            }
+        ),
+        input_setup_combined().prop_map(PortInputFormatSetupCombinedmode),
+        port_information().prop_map(PortInformation),
+        port_mode_information().prop_map(PortModeInformation),
+        (any::<u8>(), prop::collection::vec(any::<i8>(), 0..200)).prop_map(
+            |(port_id, data)| PortValueSingle(PortValueSingleFormat {
+                port_id,
+                data
+            })
+        ),
+        (any::<u8>(), bytes(200)).prop_map(|(port_id, data)| {
+            PortValueCombined(PortValueCombinedFormat { port_id, data })
+        }),
+        (any::<u8>(), any::<u8>(), any::<u32>(), any::<bool>()).prop_map(
+            |(port_id, mode, delta, notification_enabled)| {
+                PortInputFormatSingle(PortInputFormatSingleFormat {
+                    port_id,
+                    mode,
+                    delta,
+                    notification_enabled,
+                })
+            }
+        ),
+        (any::<u8>(), any::<u8>(), any::<u16>()).prop_map(
+            |(port_id, control, pointer)| {
+                PortInputFormatCombinedmode(PortInputFormatCombinedFormat {
+                    port_id,
+                    control,
+                    combination_index: control & 0x7f,
+                    multi_update: control & 0x80 != 0,
+                    mode_dataset_combination_pointer: pointer,
+                })
+            }
+        ),
+        prop_oneof![
+            any::<u8>().prop_map(|port_id| {
+                VirtualPortSetupFormat::Disconnect { port_id }
+            }),
+            (any::<u8>(), any::<u8>()).prop_map(|(port_a, port_b)| {
+                VirtualPortSetupFormat::Connect { port_a, port_b }
+            }),
+        ]
+        .prop_map(VirtualPortSetup),
+        port_output_command().prop_map(PortOutputCommand),
+        command_feedback().prop_map(PortOutputCommandFeedback),
+    ]
+}
@@ -0,0 +1,336 @@
+//! Several hubs used together, e.g. an installation of a few Technic hubs
+//! and the remotes driving them.
+//!
+//! `HubPool::connect` discovers one hub for each role it is given and
+//! connects them concurrently, each as soon as it is found. The pool looks
+//! hubs up by role, name, address or type, merges their events into one
+//! stream tagged with the hub they came from, and disconnects or shuts
+//! down all of them at once.
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use core::time::Duration;
+use futures::stream::StreamExt;
+use std::collections::HashSet;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::task::{JoinHandle, JoinSet};
+
+use crate::consts::HubType;
+use crate::discovery::DiscoveryEvent;
+use crate::error::{Error, OptionContext, Result};
+use crate::hubs::HubEvent;
+use crate::targets;
+use crate::{
+    ConnectedHub, ConnectionEvent, DiscoveredHub, HubFilter, PoweredUp,
+};
+
+/// How a hub in the pool is known
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HubId {
+    /// The role the hub was given when it joined the pool, unique in it
+    pub role: String,
+    /// Name of the hub, as set in the PoweredUp/Control+ apps
+    pub name: String,
+    /// Bluetooth address the hub reports
+    pub address: String,
+    pub kind: HubType,
+}
+
+/// Something that happened on one of the hubs in a pool
+#[derive(Debug, Clone, PartialEq)]
+pub enum PoolEvent {
+    Hub(HubEvent),
+    Connection(ConnectionEvent),
+}
+
+/// An event tagged with the hub it came from
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaggedHubEvent {
+    pub hub: HubId,
+    pub event: PoolEvent,
+}
+
+struct Member {
+    id: HubId,
+    hub: ConnectedHub,
+    task: JoinHandle<()>,
+}
+
+/// Connected hubs indexed by role, with their events merged. Stops
+/// reporting events when dropped; the hubs stay connected until
+/// `disconnect` or `shutdown`.
+pub struct HubPool {
+    members: Vec<Member>,
+    sender: broadcast::Sender<TaggedHubEvent>,
+}
+
+impl Default for HubPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HubPool {
+    /// Empty pool; add connected hubs with `insert`.
+    pub fn new() -> Self {
+        Self {
+            members: Vec::new(),
+            sender: broadcast::channel(256).0,
+        }
+    }
+
+    /// Discover a hub for each of `roles` and connect them concurrently.
+    /// A discovered hub takes the first free role whose filter it matches,
+    /// roles with `HubFilter::Null` are filled last. Role names must be
+    /// unique; nothing is discovered if one is given twice.
+    ///
+    /// Fails with a `TimeoutError` if not every role is filled within
+    /// `timeout`; each hub's setup is bounded by `DEFAULT_READY_TIMEOUT`
+    /// on top of that. A hub whose setup fails is disconnected, and on
+    /// failure so are the hubs already connected.
+    pub async fn connect<S: Into<String>>(
+        pu: &PoweredUp,
+        roles: impl IntoIterator<Item = (S, HubFilter)>,
+        timeout: Duration,
+    ) -> Result<Self> {
+        let mut wanted: Vec<(String, HubFilter)> = roles
+            .into_iter()
+            .map(|(role, filter)| (role.into(), filter))
+            .collect();
+        let mut roles = HashSet::new();
+        if let Some((role, _)) =
+            wanted.iter().find(|(role, _)| !roles.insert(role))
+        {
+            return Err(Error::HubError(format!(
+                "Role `{role}` is given twice"
+            )));
+        }
+        let count = wanted.len();
+        let mut connecting = JoinSet::new();
+        let mut discovery = pu.discover().await?;
+
+        let found = tokio::time::timeout(timeout, async {
+            // A hub being connected may be lost and found again
+            let mut seen = HashSet::new();
+            while !wanted.is_empty() {
+                let event = discovery.next().await.context("Scan ended")?;
+                let DiscoveryEvent::Found(hub) = event else {
+                    continue;
+                };
+                if !seen.insert(hub.addr.clone()) {
+                    continue;
+                }
+                let Some(index) = role_for(&wanted, &hub) else {
+                    continue;
+                };
+                let (role, _) = wanted.remove(index);
+                info!(target: targets::CONNECTION, "Found hub `{}` for role `{}`", hub.name, role);
+                let mut connector = pu.clone();
+                connecting.spawn(async move {
+                    let created = connector.create_hub(&hub).await?;
+                    // Setup takes the hub; keep hold of its link so it
+                    // isn't left connected if setup fails
+                    let tokens = created.tokens();
+                    let cancel = created.cancel_token();
+                    match ConnectedHub::setup_hub(created).await {
+                        Ok(connected) => Ok((role, connected)),
+                        Err(e) => {
+                            cancel.cancel();
+                            let _ = tokens.disconnect().await;
+                            Err(e)
+                        }
+                    }
+                });
+            }
+            Ok::<_, Error>(())
+        })
+        .await;
+        let stopped = discovery.stop().await;
+        let found = found.unwrap_or_else(|_| {
+            Err(Error::TimeoutError(format!(
+                "Found {} of {} hubs",
+                count - wanted.len(),
+                count
+            )))
+        });
+
+        let mut failure = found.and(stopped).err();
+        let mut pool = Self::new();
+        while let Some(joined) = connecting.join_next().await {
+            let connected = joined
+                .map_err(|e| Error::HubError(e.to_string()))
+                .and_then(|connected| connected);
+            let inserted = match connected {
+                Ok((role, hub)) => pool.insert(role, hub).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = inserted {
+                failure.get_or_insert(e);
+            }
+        }
+        if let Some(e) = failure {
+            let _ = pool.disconnect().await;
+            return Err(e);
+        }
+        Ok(pool)
+    }
+
+    /// Add a connected hub under `role`, which must not be taken yet.
+    pub async fn insert(
+        &mut self,
+        role: impl Into<String>,
+        hub: ConnectedHub,
+    ) -> Result<()> {
+        let role = role.into();
+        if self.get(&role).is_some() {
+            return Err(Error::HubError(format!("Role `{role}` is taken")));
+        }
+        let address = hub.mutex.lock().await.properties().mac_address.clone();
+        let id = HubId {
+            role,
+            name: hub.name.clone(),
+            address,
+            kind: hub.kind,
+        };
+        let task = forward(
+            id.clone(),
+            hub.events(),
+            hub.connection_events(),
+            self.sender.clone(),
+        );
+        self.members.push(Member { id, hub, task });
+        Ok(())
+    }
+
+    /// Take the hub with `role` out of the pool. Its events are no longer
+    /// reported by the pool.
+    pub fn remove(&mut self, role: &str) -> Option<ConnectedHub> {
+        let index = self.members.iter().position(|m| m.id.role == role)?;
+        let member = self.members.remove(index);
+        member.task.abort();
+        Some(member.hub)
+    }
+
+    /// The hub with `role`
+    pub fn get(&self, role: &str) -> Option<&ConnectedHub> {
+        self.find(|id| id.role == role)
+    }
+
+    /// The first hub named `name`
+    pub fn by_name(&self, name: &str) -> Option<&ConnectedHub> {
+        self.find(|id| id.name == name)
+    }
+
+    /// The hub with Bluetooth address `address`, in any case
+    pub fn by_address(&self, address: &str) -> Option<&ConnectedHub> {
+        self.find(|id| id.address.eq_ignore_ascii_case(address))
+    }
+
+    /// All hubs of type `kind`, in the order they joined the pool
+    pub fn by_kind(
+        &self,
+        kind: HubType,
+    ) -> impl Iterator<Item = &ConnectedHub> + '_ {
+        self.members
+            .iter()
+            .filter(move |m| m.id.kind == kind)
+            .map(|m| &m.hub)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&HubId, &ConnectedHub)> {
+        self.members.iter().map(|m| (&m.id, &m.hub))
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    /// Receive the hub and connection events of all hubs in the pool
+    pub fn events(&self) -> broadcast::Receiver<TaggedHubEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Disconnect every hub in the pool. All are tried; the first error
+    /// is returned.
+    pub async fn disconnect(&self) -> Result<()> {
+        let mut result = Ok(());
+        for Member { id, hub, .. } in &self.members {
+            if let Err(e) = hub.mutex.lock().await.disconnect().await {
+                warn!(target: targets::CONNECTION, "Failed to disconnect hub `{}`: {}", id.role, e);
+                result = result.and(Err(e));
+            }
+        }
+        result
+    }
+
+    /// Switch off every hub in the pool. All are tried; the first error
+    /// is returned.
+    pub async fn shutdown(&self) -> Result<()> {
+        let mut result = Ok(());
+        for Member { id, hub, .. } in &self.members {
+            if let Err(e) = hub.mutex.lock().await.shutdown().await {
+                warn!(target: targets::CONNECTION, "Failed to shut down hub `{}`: {}", id.role, e);
+                result = result.and(Err(e));
+            }
+        }
+        result
+    }
+
+    fn find(&self, pred: impl Fn(&HubId) -> bool) -> Option<&ConnectedHub> {
+        self.members.iter().find(|m| pred(&m.id)).map(|m| &m.hub)
+    }
+}
+
+impl Drop for HubPool {
+    fn drop(&mut self) {
+        for member in &self.members {
+            member.task.abort();
+        }
+    }
+}
+
+/// Index of the free role `hub` should take: the first whose filter it
+/// matches, trying the catch-all roles last
+fn role_for(
+    wanted: &[(String, HubFilter)],
+    hub: &DiscoveredHub,
+) -> Option<usize> {
+    let matching = |catch_all: bool| {
+        wanted.iter().position(|(_, filter)| {
+            (*filter == HubFilter::Null) == catch_all && filter.matches(hub)
+        })
+    };
+    matching(false).or_else(|| matching(true))
+}
+
+fn forward(
+    id: HubId,
+    mut events: broadcast::Receiver<HubEvent>,
+    mut connection: broadcast::Receiver<ConnectionEvent>,
+    sender: broadcast::Sender<TaggedHubEvent>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            let event = tokio::select! {
+                event = events.recv() => event.map(PoolEvent::Hub),
+                event = connection.recv() => event.map(PoolEvent::Connection),
+            };
+            match event {
+                Ok(event) => {
+                    let _ = sender.send(TaggedHubEvent {
+                        hub: id.clone(),
+                        event,
+                    });
+                }
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            }
+        }
+    })
+}
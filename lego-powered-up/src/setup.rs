@@ -1,44 +1,37 @@
 //! Convenience functions
 
 use crate::error::Result;
+use crate::targets;
 use crate::HubFilter;
 use crate::{ConnectedHub, PoweredUp};
 
 /// Setup single hub
 pub async fn single_hub() -> Result<ConnectedHub> {
-    println!("Discovering BT adapter and initializing PoweredUp");
+    info!(target: targets::CONNECTION, "Discovering BT adapter and initializing PoweredUp");
     let mut pu = PoweredUp::init().await?;
-    println!("Waiting for hub...");
+    info!(target: targets::CONNECTION, "Waiting for hub...");
     let hub = pu.wait_for_hub().await?;
-    println!("Connecting to hub...");
+    info!(target: targets::CONNECTION, "Connecting to hub...");
     //dbg!(&hub);
 
-    Ok(ConnectedHub::setup_hub(
-        pu.create_hub(&hub).await.expect("Error creating hub"),
-    )
-    .await
-    .expect("Error setting up hub"))
+    ConnectedHub::setup_hub(pu.create_hub(&hub).await?).await
 }
 
 /// Setup main hub + remote control
 pub async fn main_and_rc() -> Result<(ConnectedHub, ConnectedHub)> {
-    println!("Discovering BT adapter and initializing PoweredUp");
+    info!(target: targets::CONNECTION, "Discovering BT adapter and initializing PoweredUp");
     let mut pu = PoweredUp::init().await?;
     let hub_count = 2;
-    println!("Waiting for hubs...");
+    info!(target: targets::CONNECTION, "Waiting for hubs...");
     let discovered_hubs =
         pu.wait_for_hubs_filter(HubFilter::Null, &hub_count).await?;
-    println!("Discovered {} hubs, trying to connect...", &hub_count);
+    info!(target: targets::CONNECTION, "Discovered {} hubs, trying to connect...", &hub_count);
 
     let mut connected_hubs: Vec<ConnectedHub> = Vec::new();
     for dh in discovered_hubs {
-        println!("Connecting to hub `{}`", dh.name);
+        info!(target: targets::CONNECTION, "Connecting to hub `{}`", dh.name);
         let created_hub = pu.create_hub(&dh).await?;
-        connected_hubs.push(
-            ConnectedHub::setup_hub(created_hub)
-                .await
-                .expect("Error setting up hub `{}`, dh.name"),
-        )
+        connected_hubs.push(ConnectedHub::setup_hub(created_hub).await?);
     }
 
     let rc_hub: ConnectedHub;
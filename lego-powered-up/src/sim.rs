@@ -0,0 +1,1344 @@
+//! In-process simulated hub for hardware-free testing.
+//!
+//! `SimHub` implements `Transport` and answers the messages sent by the
+//! crate the way a Technic Medium Hub would: it announces its devices with
+//! HubAttachedIo when notifications are enabled, replies to port and mode
+//! information requests from the tables in `iodevice::known_devices`,
+//! integrates motor commands into encoder values and reports port values
+//! and command feedback.
+//!
+//! Only built for the crate's tests, or with the `sim` feature.
+//!
+//! ```no_run
+//! # async fn example() -> lego_powered_up::Result<()> {
+//! use lego_powered_up::sim::{self, SimHub};
+//! use lego_powered_up::IoTypeId;
+//! use std::sync::Arc;
+//!
+//! let sim = Arc::new(
+//!     SimHub::technic_hub()
+//!         .with_device(0, IoTypeId::TechnicLargeLinearMotor),
+//! );
+//! let hub = sim::connect(sim.clone()).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use async_trait::async_trait;
+use core::time::Duration;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use crate::consts::{HubPropertyOperation, HubPropertyRef, HubType};
+use crate::error::{Error, Result};
+use crate::hubs::generic_hub::GenericHub;
+use crate::hubs::HubProperties;
+use crate::iodevice::known_devices::{self, KnownDevice};
+use crate::iodevice::modes;
+use crate::notifications::*;
+use crate::transport::{FrameStream, Transport};
+use crate::{ConnectedHub, IoTypeId};
+
+pub mod devices;
+
+#[cfg(test)]
+mod test;
+
+/// Interval at which motors are integrated and values reported
+const TICK: Duration = Duration::from_millis(20);
+/// Simulated motor speed at speed 100, roughly a Technic L motor
+const DEGREES_PER_SECOND: f64 = 1000.0;
+
+// Feedback bits, cf. PortOutputCommandFeedbackFormat
+const IN_PROGRESS: u8 = 0x01;
+const COMPLETED: u8 = 0x02;
+const DISCARDED: u8 = 0x04;
+const IDLE: u8 = 0x08;
+
+/// First port id given to virtual ports
+const VIRTUAL_PORT_BASE: u8 = 0x10;
+
+/// Connect a simulated hub and set it up like `PoweredUp::create_hub`
+/// followed by `ConnectedHub::setup_hub` would for a real one.
+pub async fn connect(sim: Arc<SimHub>) -> Result<ConnectedHub> {
+    sim.connect().await?;
+    let hub =
+        GenericHub::init(sim.clone(), sim.kind(), CancellationToken::new())
+            .await?;
+    ConnectedHub::setup_hub(Box::new(hub)).await
+}
+
+#[derive(Debug)]
+pub struct SimHub {
+    kind: HubType,
+    name: String,
+    address: [u8; 6],
+    state: Arc<Mutex<SimState>>,
+}
+
+impl SimHub {
+    /// Empty hub of the given type; add devices with `with_device`.
+    pub fn new(kind: HubType, name: &str) -> Self {
+        use HubPropertyValue::*;
+        let sim = Self {
+            kind,
+            name: name.to_string(),
+            address: [0x00, 0x16, 0x53, 0x00, 0x00, 0x00],
+            state: Default::default(),
+        };
+        for property in [
+            AdvertisingName(name.as_bytes().to_vec()),
+            Button(0),
+            FwVersion(0x1100_0000),
+            HwVersion(0x0100_0000),
+            Rssi(-50),
+            BatteryVoltage(100),
+            BatteryType(HubBatteryType::Normal),
+            ManufacturerName(b"LEGO System A/S".to_vec()),
+            RadioFirmwareVersion(b"2_02_01".to_vec()),
+            LegoWirelessProtocolVersion(0x0300),
+            SystemTypeId(0x80),
+            HwNetworkId(0),
+            PrimaryMacAddress(sim.address),
+        ] {
+            sim.state().set_property(property);
+        }
+        sim
+    }
+
+    /// Duplo train base with its internal devices on their usual ports.
+    pub fn duplo_train_base() -> Self {
+        Self::new(HubType::DuploTrainBase, "Train Base")
+            .with_device(0x00, IoTypeId::DuploTrainBaseMotor)
+            .with_device(0x01, IoTypeId::DuploTrainBaseSpeaker)
+            .with_device(0x11, IoTypeId::HubLed)
+            .with_device(0x12, IoTypeId::DuploTrainBaseColorSensor)
+            .with_device(0x13, IoTypeId::DuploTrainBaseSpeedometer)
+            .with_device(0x14, IoTypeId::Voltage)
+    }
+
+    /// LEGO Mario with his sensors on their usual ports.
+    pub fn mario() -> Self {
+        Self::new(HubType::Mario, "Mario")
+            .with_device(0x00, IoTypeId::MarioAccelerometer)
+            .with_device(0x01, IoTypeId::MarioBarcodeSensor)
+            .with_device(0x02, IoTypeId::MarioPantsSensor)
+    }
+
+    /// Remote control with its button devices and internal devices on
+    /// their usual ports.
+    pub fn remote() -> Self {
+        Self::new(HubType::RemoteControl, "Handset")
+            .with_device(0x00, IoTypeId::RemoteButtons)
+            .with_device(0x01, IoTypeId::RemoteButtons)
+            .with_device(0x34, IoTypeId::HubLed)
+            .with_device(0x3b, IoTypeId::Voltage)
+            .with_device(0x3c, IoTypeId::Rssi)
+    }
+
+    /// Technic Medium Hub with its internal devices on their usual ports.
+    pub fn technic_hub() -> Self {
+        Self::new(HubType::TechnicMediumHub, "Technic Hub")
+            .with_device(0x32, IoTypeId::HubLed)
+            .with_device(0x3b, IoTypeId::Current)
+            .with_device(0x3c, IoTypeId::Voltage)
+            .with_device(0x3d, IoTypeId::TechnicHubTemperatureSensor)
+            .with_device(0x60, IoTypeId::TechnicHubTemperatureSensor)
+            .with_device(0x61, IoTypeId::TechnicHubAccelerometer)
+            .with_device(0x62, IoTypeId::TechnicHubGyroSensor)
+            .with_device(0x63, IoTypeId::TechnicHubTiltSensor)
+            .with_device(0x64, IoTypeId::TechnicHubGestSensor)
+    }
+
+    /// Move Hub with its internal motors and devices on their usual ports.
+    pub fn move_hub() -> Self {
+        Self::new(HubType::MoveHub, "Move Hub")
+            .with_device(0x00, IoTypeId::InternalMotorTacho)
+            .with_device(0x01, IoTypeId::InternalMotorTacho)
+            .with_device(0x32, IoTypeId::HubLed)
+            .with_device(0x3a, IoTypeId::InternalTilt)
+            .with_device(0x3b, IoTypeId::Current)
+            .with_device(0x3c, IoTypeId::Voltage)
+    }
+
+    pub fn with_device(self, port_id: u8, kind: IoTypeId) -> Self {
+        self.state().ports.insert(port_id, SimPort::new(kind));
+        self
+    }
+
+    /// Report `address` as the hub's Bluetooth address, to tell several
+    /// sims of the same type apart.
+    pub fn with_address(mut self, address: [u8; 6]) -> Self {
+        self.address = address;
+        self.set_property(HubPropertyValue::PrimaryMacAddress(address));
+        self
+    }
+
+    pub fn kind(&self) -> HubType {
+        self.kind
+    }
+
+    /// Plug a device in while connected; the hub reports it as attached.
+    pub fn attach_device(&self, port_id: u8, kind: IoTypeId) {
+        let mut state = self.state();
+        state.ports.insert(port_id, SimPort::new(kind));
+        state.emit(attached_io(port_id, kind));
+    }
+
+    /// Unplug a device; the hub reports it as detached.
+    pub fn detach_device(&self, port_id: u8) {
+        let mut state = self.state();
+        if state.ports.remove(&port_id).is_some() {
+            state.emit(detached_io(port_id));
+        }
+    }
+
+    /// Lose the link without being asked to disconnect. The notification
+    /// stream ends and, like a real hub, the sim forgets the virtual ports
+    /// and port setups.
+    pub fn drop_connection(&self) {
+        self.state().disconnect();
+    }
+
+    /// Change a hub property; reported if the hub was asked for updates.
+    pub fn set_property(&self, value: HubPropertyValue) {
+        self.state().set_property(value);
+    }
+
+    /// Press or release the hub button. Like a real hub, the sim reports
+    /// it as a Button property update, if enabled, and as a connection
+    /// request.
+    pub fn set_button(&self, pressed: bool) {
+        let mut state = self.state();
+        state.set_property(HubPropertyValue::Button(pressed as u8));
+        let button = if pressed {
+            ButtonState::Up
+        } else {
+            ButtonState::Released
+        };
+        state.emit(NotificationMessage::HwNetworkCommands(
+            NetworkCommand::ConnectionRequest(button),
+        ));
+    }
+
+    /// Answer requests for `information_type` about any mode with an
+    /// error, as hubs do for information a device doesn't provide.
+    pub fn reject_mode_information(
+        &self,
+        information_type: ModeInformationType,
+    ) {
+        self.state()
+            .rejected_mode_information
+            .push(information_type);
+    }
+
+    /// Leave requests for the property's value unanswered, like a hub
+    /// whose replies get lost.
+    pub fn ignore_property_requests(&self, reference: HubPropertyRef) {
+        self.state().ignored_properties.insert(reference as u8);
+    }
+
+    /// Act on each frame written only after `latency`, like a hub at the
+    /// other end of a slow link.
+    pub fn set_latency(&self, latency: Duration) {
+        self.state().latency = latency;
+    }
+
+    /// Set the raw values a sensor mode reports.
+    pub fn set_value(
+        &self,
+        port_id: u8,
+        mode: u8,
+        values: &[i32],
+    ) -> Result<()> {
+        let mut state = self.state();
+        let port = state.port_mut(port_id)?;
+        let slot = port.values.get_mut(mode as usize).ok_or_else(|| {
+            Error::HubError(format!("No mode {} on port {}", mode, port_id))
+        })?;
+        *slot = values.to_vec();
+        state.report(port_id);
+        Ok(())
+    }
+
+    /// Raw values currently reported by a mode.
+    pub fn value(&self, port_id: u8, mode: u8) -> Option<Vec<i32>> {
+        let state = self.state();
+        state
+            .ports
+            .get(&port_id)?
+            .values
+            .get(mode as usize)
+            .cloned()
+    }
+
+    /// Every value output commands wrote to a mode, oldest first.
+    pub fn written(&self, port_id: u8, mode: u8) -> Vec<Vec<i32>> {
+        let state = self.state();
+        let Some(port) = state.ports.get(&port_id) else {
+            return Vec::new();
+        };
+        port.written
+            .iter()
+            .filter(|(m, _)| *m == mode)
+            .map(|(_, value)| value.clone())
+            .collect()
+    }
+
+    /// Encoder position of a simulated motor, in degrees.
+    pub fn position(&self, port_id: u8) -> Option<i32> {
+        let state = self.state();
+        let motor = state.ports.get(&port_id)?.motor.as_ref()?;
+        Some(motor.position.round() as i32)
+    }
+
+    /// Current speed of a simulated motor, -100..100.
+    pub fn speed(&self, port_id: u8) -> Option<i8> {
+        let state = self.state();
+        Some(state.ports.get(&port_id)?.motor.as_ref()?.speed)
+    }
+
+    fn state(&self) -> MutexGuard<'_, SimState> {
+        self.state.lock().expect("Simulated hub state poisoned")
+    }
+}
+
+impl Drop for SimHub {
+    fn drop(&mut self) {
+        if let Ok(state) = self.state.lock() {
+            state.session.cancel();
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for SimHub {
+    async fn write(&self, frame: &[u8]) -> Result<()> {
+        let latency = self.state().latency;
+        if !latency.is_zero() {
+            tokio::time::sleep(latency).await;
+        }
+        let mut state = self.state();
+        if !state.connected {
+            return Err(Error::HubError(String::from(
+                "Simulated hub is not connected",
+            )));
+        }
+        match NotificationMessage::parse(frame) {
+            Ok(msg) => state.handle(frame[2], msg),
+            Err(_) => {
+                let command_type = frame.get(2).copied().unwrap_or_default();
+                state.emit(generic_error(
+                    command_type,
+                    ErrorCode::CommandNotRecognized,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    async fn notifications(&self) -> Result<FrameStream> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        {
+            let mut state = self.state();
+            state.sender = Some(tx);
+            let attached: Vec<(u8, IoTypeId)> =
+                state.ports.iter().map(|(id, p)| (*id, p.kind)).collect();
+            for (port_id, kind) in attached {
+                state.emit(attached_io(port_id, kind));
+            }
+            if !state.ticking {
+                state.ticking = true;
+                spawn_ticker(self.state.clone(), state.session.clone());
+            }
+        }
+        Ok(Box::pin(futures::stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|frame| (frame, rx))
+        })))
+    }
+
+    async fn connect(&self) -> Result<()> {
+        let mut state = self.state();
+        if !state.connected {
+            state.connected = true;
+            state.session = CancellationToken::new();
+            state.ticking = false;
+        }
+        Ok(())
+    }
+
+    async fn disconnect(&self) -> Result<()> {
+        self.state().disconnect();
+        Ok(())
+    }
+
+    async fn is_connected(&self) -> Result<bool> {
+        Ok(self.state().connected)
+    }
+
+    async fn properties(&self) -> Result<HubProperties> {
+        Ok(HubProperties {
+            name: self.name.clone(),
+            mac_address: self
+                .address
+                .iter()
+                .map(|b| format!("{:02X}", b))
+                .collect::<Vec<_>>()
+                .join(":"),
+            ..Default::default()
+        })
+    }
+}
+
+fn spawn_ticker(state: Arc<Mutex<SimState>>, session: CancellationToken) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(TICK);
+        loop {
+            tokio::select! {
+                _ = session.cancelled() => break,
+                _ = interval.tick() => {
+                    let Ok(mut state) = state.lock() else {
+                        break;
+                    };
+                    state.tick(TICK.as_secs_f64());
+                }
+            }
+        }
+    });
+}
+
+#[derive(Debug, Default)]
+struct SimState {
+    ports: BTreeMap<u8, SimPort>,
+    connected: bool,
+    ticking: bool,
+    session: CancellationToken,
+    sender: Option<mpsc::UnboundedSender<Vec<u8>>>,
+    /// Hub properties by reference
+    properties: BTreeMap<u8, HubPropertyValue>,
+    /// Properties with updates enabled
+    property_updates: BTreeSet<u8>,
+    /// Properties whose requests go unanswered
+    ignored_properties: BTreeSet<u8>,
+    /// Mode information answered with an error
+    rejected_mode_information: Vec<ModeInformationType>,
+    /// Time between a frame being written and the hub acting on it
+    latency: Duration,
+}
+
+impl SimState {
+    fn emit(&self, msg: NotificationMessage) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(msg.serialise());
+        }
+    }
+
+    fn port_mut(&mut self, port_id: u8) -> Result<&mut SimPort> {
+        self.ports.get_mut(&port_id).ok_or_else(|| {
+            Error::HubError(format!("No simulated device on port {}", port_id))
+        })
+    }
+
+    fn disconnect(&mut self) {
+        self.connected = false;
+        self.sender = None;
+        self.session.cancel();
+        self.property_updates.clear();
+        self.ports.retain(|_, port| port.pair.is_none());
+        for port in self.ports.values_mut() {
+            port.reset();
+        }
+    }
+
+    fn handle(&mut self, command_type: u8, msg: NotificationMessage) {
+        use NotificationMessage::*;
+        let handled = match msg {
+            PortInformationRequest(req) => self.port_information(req),
+            PortModeInformationRequest(req) => self.mode_information(req),
+            PortInputFormatSetupSingle(setup) => self.input_setup(setup),
+            PortInputFormatSetupCombinedmode(setup) => {
+                self.input_setup_combined(setup)
+            }
+            PortOutputCommand(cmd) => self.output_command(cmd),
+            HubActions(action) => self.hub_action(action),
+            HubProperties(property) => self.hub_property(property),
+            VirtualPortSetup(setup) => self.virtual_port(setup),
+            // Accepted but nothing to simulate
+            HubAlerts(_) => Ok(()),
+            _ => Err(ErrorCode::CommandNotRecognized),
+        };
+        if let Err(code) = handled {
+            self.emit(generic_error(command_type, code));
+        }
+    }
+
+    fn port_information(
+        &mut self,
+        req: InformationRequest,
+    ) -> std::result::Result<(), ErrorCode> {
+        let port_id = req.port_id;
+        let port = self.ports.get(&port_id).ok_or(ErrorCode::InvalidUse)?;
+        let information_type = match req.information_type {
+            InformationType::PortValue => {
+                let mode = port.single.as_ref().map(|s| s.mode).unwrap_or(0);
+                let bytes = port.encode(mode).ok_or(ErrorCode::InvalidUse)?;
+                self.emit(value_single(port_id, &bytes));
+                return Ok(());
+            }
+            information_type => port
+                .device
+                .port_information(information_type)
+                .ok_or(ErrorCode::InvalidUse)?,
+        };
+        self.emit(NotificationMessage::PortInformation(PortInformationValue {
+            port_id,
+            information_type,
+        }));
+        Ok(())
+    }
+
+    fn mode_information(
+        &mut self,
+        req: ModeInformationRequest,
+    ) -> std::result::Result<(), ErrorCode> {
+        if self
+            .rejected_mode_information
+            .contains(&req.information_type)
+        {
+            return Err(ErrorCode::InvalidUse);
+        }
+        let port = self.ports.get(&req.port_id).ok_or(ErrorCode::InvalidUse)?;
+        let information_type = port
+            .device
+            .mode_information(req.mode, req.information_type)
+            .ok_or(ErrorCode::InvalidUse)?;
+        self.emit(NotificationMessage::PortModeInformation(
+            PortModeInformationValue {
+                port_id: req.port_id,
+                mode: req.mode,
+                information_type,
+            },
+        ));
+        Ok(())
+    }
+
+    fn input_setup(
+        &mut self,
+        setup: InputSetupSingle,
+    ) -> std::result::Result<(), ErrorCode> {
+        let port_id = setup.port_id;
+        let port = self.ports.get_mut(&port_id).ok_or(ErrorCode::InvalidUse)?;
+        if setup.mode >= port.device.mode_count() {
+            return Err(ErrorCode::InvalidUse);
+        }
+        port.deltas.insert(setup.mode, setup.delta);
+        if !port.combined.locked {
+            port.combined.enabled = false;
+            port.single = Some(SingleSetup {
+                mode: setup.mode,
+                delta: setup.delta,
+                enabled: setup.notification_enabled,
+                last: None,
+            });
+        }
+        self.emit(NotificationMessage::PortInputFormatSingle(
+            PortInputFormatSingleFormat {
+                port_id,
+                mode: setup.mode,
+                delta: setup.delta,
+                notification_enabled: setup.notification_enabled,
+            },
+        ));
+        self.report(port_id);
+        Ok(())
+    }
+
+    fn input_setup_combined(
+        &mut self,
+        setup: InputSetupCombined,
+    ) -> std::result::Result<(), ErrorCode> {
+        use InputSetupCombinedSubcommand::*;
+        let port_id = setup.port_id;
+        let port = self.ports.get_mut(&port_id).ok_or(ErrorCode::InvalidUse)?;
+        match setup.subcommand {
+            SetModeanddatasetCombinations { mode_dataset, .. } => {
+                port.combined.entries = mode_dataset
+                    .iter()
+                    .take_while(|b| **b != 255)
+                    .map(|b| (b >> 4, b & 0x0f))
+                    .collect();
+            }
+            LockLpf2DeviceForSetup => {
+                port.combined.locked = true;
+            }
+            UnlockAndStartMultiEnabled | UnlockAndStartMultiDisabled => {
+                port.combined.locked = false;
+                port.combined.enabled =
+                    setup.subcommand == UnlockAndStartMultiEnabled;
+                port.combined.last = None;
+                port.single = None;
+            }
+            ResetSensor => port.reset(),
+            NotUsed => return Err(ErrorCode::InvalidUse),
+        }
+        self.report(port_id);
+        Ok(())
+    }
+
+    fn output_command(
+        &mut self,
+        cmd: PortOutputCommandFormat,
+    ) -> std::result::Result<(), ErrorCode> {
+        use PortOutputSubcommand::*;
+        let port_id = cmd.port_id;
+        let feedback = cmd.completion_info == CompletionInfo::CommandFeedback;
+        let port = self.ports.get_mut(&port_id).ok_or(ErrorCode::InvalidUse)?;
+        if let Some(pair) = port.pair.as_ref() {
+            let ports = pair.ports;
+            return self.pair_command(port_id, ports, cmd);
+        }
+
+        let status = match (&cmd.subcommand, port.motor.as_mut()) {
+            (WriteDirectModeData(payload), motor) => match (payload, motor) {
+                (WriteDirectModeDataPayload::StartPower(power), Some(m)) => {
+                    m.run(power_to_speed(*power), None, feedback)
+                }
+                (WriteDirectModeDataPayload::PresetEncoder(pos), Some(m)) => {
+                    m.position = *pos as f64;
+                    COMPLETED | IDLE
+                }
+                // SetHubColor shares mode 0 with StartPower and parses as such
+                (WriteDirectModeDataPayload::StartPower(c), None)
+                    if port.kind == IoTypeId::HubLed =>
+                {
+                    port.write(0, vec![c.to_u8() as i32]);
+                    COMPLETED | IDLE
+                }
+                (WriteDirectModeDataPayload::StartPower(power), None)
+                    if port.kind == IoTypeId::DuploTrainBaseMotor =>
+                {
+                    port.write(
+                        modes::DuploTrainBaseMotor::T_MOT,
+                        vec![power_to_speed(*power) as i32],
+                    );
+                    COMPLETED | IDLE
+                }
+                (
+                    WriteDirectModeDataPayload::SetHubRgb { red, green, blue },
+                    None,
+                ) if port.kind == IoTypeId::HubLed => {
+                    port.write(
+                        1,
+                        vec![*red as i32, *green as i32, *blue as i32],
+                    );
+                    COMPLETED | IDLE
+                }
+                (WriteDirectModeDataPayload::TiltImpactPreset(count), None)
+                    if port.kind == IoTypeId::InternalTilt =>
+                {
+                    port.write(modes::InternalTilt::IMPCT, vec![*count]);
+                    COMPLETED | IDLE
+                }
+                (
+                    WriteDirectModeDataPayload::TiltConfigOrientation(_)
+                    | WriteDirectModeDataPayload::TiltConfigImpact { .. }
+                    | WriteDirectModeDataPayload::TiltFactoryCalibration(_),
+                    None,
+                ) if port.kind == IoTypeId::InternalTilt => COMPLETED | IDLE,
+                // Commands no other variant parses as, like the light
+                // matrix and Duplo speaker ones, set the mode's value
+                (WriteDirectModeDataPayload::Raw { mode, data }, None)
+                    if (*mode as usize) < port.values.len() =>
+                {
+                    port.write(*mode, data.iter().map(|b| *b as i32).collect());
+                    COMPLETED | IDLE
+                }
+                _ => return Err(ErrorCode::InvalidUse),
+            },
+            (SetAccTime { .. } | SetDecTime { .. }, Some(_)) => {
+                COMPLETED | IDLE
+            }
+            (StartSpeed { speed, .. }, Some(m)) => {
+                m.run(*speed, None, feedback)
+            }
+            (StartSpeedForTime { time, speed, .. }, Some(m)) => {
+                m.run(*speed, Some(Goal::Time(*time as f64 / 1000.0)), feedback)
+            }
+            (StartSpeedForDegrees { degrees, speed, .. }, Some(m)) => {
+                let direction =
+                    (*degrees as f64).signum() * (*speed as f64).signum();
+                let target = m.position + direction * (*degrees as f64).abs();
+                m.run(speed.abs(), Some(Goal::Position(target)), feedback)
+            }
+            (GotoAbsolutePosition { abs_pos, speed, .. }, Some(m)) => {
+                let target = *abs_pos as f64;
+                m.run(speed.abs(), Some(Goal::Position(target)), feedback)
+            }
+            _ => return Err(ErrorCode::InvalidUse),
+        };
+        if feedback {
+            self.emit(command_feedback(port_id, status));
+        }
+        self.report(port_id);
+        Ok(())
+    }
+
+    /// Commands on a virtual port drive both of its motors
+    fn pair_command(
+        &mut self,
+        port_id: u8,
+        (port_a, port_b): (u8, u8),
+        cmd: PortOutputCommandFormat,
+    ) -> std::result::Result<(), ErrorCode> {
+        use PortOutputSubcommand::*;
+        let feedback = cmd.completion_info == CompletionInfo::CommandFeedback;
+        let position = |state: &mut Self, port_id: u8| {
+            state
+                .ports
+                .get_mut(&port_id)
+                .and_then(|p| p.motor.as_mut())
+                .map(|m| m.position)
+                .ok_or(ErrorCode::InvalidUse)
+        };
+        let (pos_a, pos_b) = (position(self, port_a)?, position(self, port_b)?);
+        let runs = match cmd.subcommand {
+            StartPower2 { power1, power2 } => [
+                (power_to_speed(power1), None),
+                (power_to_speed(power2), None),
+            ],
+            StartSpeed2 { speed1, speed2, .. } => {
+                [(speed1, None), (speed2, None)]
+            }
+            StartSpeedForTime2 {
+                time,
+                speed_l,
+                speed_r,
+                ..
+            } => {
+                let goal = Some(Goal::Time(time as f64 / 1000.0));
+                [(speed_l, goal), (speed_r, goal)]
+            }
+            StartSpeedForDegrees2 {
+                degrees,
+                speed_l,
+                speed_r,
+                ..
+            } => {
+                // The faster motor turns the given degrees, the other one
+                // in proportion to its speed
+                let fastest =
+                    speed_l.unsigned_abs().max(speed_r.unsigned_abs());
+                let target = |position: f64, speed: i8| {
+                    let share = speed as f64 / fastest.max(1) as f64;
+                    Some(Goal::Position(position + degrees as f64 * share))
+                };
+                [
+                    (speed_l.saturating_abs(), target(pos_a, speed_l)),
+                    (speed_r.saturating_abs(), target(pos_b, speed_r)),
+                ]
+            }
+            GotoAbsolutePosition2 {
+                abs_pos1,
+                abs_pos2,
+                speed,
+                ..
+            } => [
+                (
+                    speed.saturating_abs(),
+                    Some(Goal::Position(abs_pos1 as f64)),
+                ),
+                (
+                    speed.saturating_abs(),
+                    Some(Goal::Position(abs_pos2 as f64)),
+                ),
+            ],
+            PresetEncoder2 {
+                left_position,
+                right_position,
+            } => {
+                for (port, position) in
+                    [(port_a, left_position), (port_b, right_position)]
+                {
+                    if let Some(m) = self.port_motor(port) {
+                        m.position = position as f64;
+                    }
+                }
+                if feedback {
+                    self.emit(command_feedback(port_id, COMPLETED | IDLE));
+                }
+                return Ok(());
+            }
+            _ => return Err(ErrorCode::InvalidUse),
+        };
+
+        let mut running = false;
+        for (port, (speed, goal)) in [port_a, port_b].into_iter().zip(runs) {
+            if let Some(m) = self.port_motor(port) {
+                m.run(speed, goal, false);
+                running |= m.goal.is_some();
+            }
+        }
+        let Some(pair) =
+            self.ports.get_mut(&port_id).and_then(|p| p.pair.as_mut())
+        else {
+            return Err(ErrorCode::InvalidUse);
+        };
+        let discarded = if pair.running { DISCARDED } else { 0 };
+        pair.running = running;
+        pair.feedback = feedback;
+        if feedback {
+            let status = if running {
+                discarded | IN_PROGRESS
+            } else {
+                discarded | COMPLETED | IDLE
+            };
+            self.emit(command_feedback(port_id, status));
+        }
+        self.report(port_a);
+        self.report(port_b);
+        Ok(())
+    }
+
+    fn port_motor(&mut self, port_id: u8) -> Option<&mut SimMotor> {
+        self.ports.get_mut(&port_id).and_then(|p| p.motor.as_mut())
+    }
+
+    fn virtual_port(
+        &mut self,
+        setup: VirtualPortSetupFormat,
+    ) -> std::result::Result<(), ErrorCode> {
+        match setup {
+            VirtualPortSetupFormat::Connect { port_a, port_b } => {
+                let motor_kind = |port_id: u8| {
+                    self.ports
+                        .get(&port_id)
+                        .filter(|p| p.motor.is_some())
+                        .map(|p| p.kind)
+                        .ok_or(ErrorCode::InvalidUse)
+                };
+                let kind = motor_kind(port_a)?;
+                if port_a == port_b || motor_kind(port_b)? != kind {
+                    return Err(ErrorCode::InvalidUse);
+                }
+                let existing = self.ports.iter().find(|(_, p)| {
+                    p.pair.as_ref().map(|pair| pair.ports)
+                        == Some((port_a, port_b))
+                });
+                if let Some((port_id, _)) = existing {
+                    let port_id = *port_id;
+                    self.emit(attached_virtual_io(
+                        port_id, kind, port_a, port_b,
+                    ));
+                    return Ok(());
+                }
+                let port_id = (VIRTUAL_PORT_BASE..=u8::MAX)
+                    .find(|id| !self.ports.contains_key(id))
+                    .ok_or(ErrorCode::InvalidUse)?;
+                let mut port = SimPort::new(kind);
+                port.motor = None;
+                port.pair = Some(SimPair {
+                    ports: (port_a, port_b),
+                    feedback: false,
+                    running: false,
+                });
+                self.ports.insert(port_id, port);
+                self.emit(attached_virtual_io(port_id, kind, port_a, port_b));
+            }
+            VirtualPortSetupFormat::Disconnect { port_id } => {
+                match self.ports.get(&port_id) {
+                    Some(port) if port.pair.is_some() => {
+                        self.ports.remove(&port_id);
+                        self.emit(detached_io(port_id));
+                    }
+                    _ => return Err(ErrorCode::InvalidUse),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn hub_action(
+        &mut self,
+        action: HubActionRequest,
+    ) -> std::result::Result<(), ErrorCode> {
+        let reply = match action.action_type {
+            HubAction::SwitchOffHub => HubAction::HubWillSwitchOff,
+            HubAction::Disconnect => HubAction::HubWillDisconnect,
+            _ => return Ok(()),
+        };
+        self.emit(NotificationMessage::HubActions(HubActionRequest {
+            action_type: reply,
+        }));
+        self.disconnect();
+        Ok(())
+    }
+
+    fn hub_property(
+        &mut self,
+        property: HubProperty,
+    ) -> std::result::Result<(), ErrorCode> {
+        let reference = property.reference as u8;
+        if !self.properties.contains_key(&reference) {
+            return Err(ErrorCode::InvalidUse);
+        }
+        match property.operation {
+            HubPropertyOperation::SetDownstream => {
+                self.set_property(
+                    property.property.ok_or(ErrorCode::InvalidUse)?,
+                );
+            }
+            HubPropertyOperation::EnableUpdatesDownstream => {
+                self.property_updates.insert(reference);
+                self.emit_property(reference);
+            }
+            HubPropertyOperation::DisableUpdatesDownstream => {
+                self.property_updates.remove(&reference);
+            }
+            HubPropertyOperation::RequestUpdateDownstream => {
+                if !self.ignored_properties.contains(&reference) {
+                    self.emit_property(reference);
+                }
+            }
+            _ => return Err(ErrorCode::InvalidUse),
+        }
+        Ok(())
+    }
+
+    /// Store a property value, reporting it if updates are enabled
+    fn set_property(&mut self, value: HubPropertyValue) {
+        let reference = value.reference() as u8;
+        self.properties.insert(reference, value);
+        if self.property_updates.contains(&reference) {
+            self.emit_property(reference);
+        }
+    }
+
+    fn emit_property(&self, reference: u8) {
+        let Some(value) = self.properties.get(&reference) else {
+            return;
+        };
+        self.emit(NotificationMessage::HubProperties(HubProperty {
+            reference: value.reference(),
+            operation: HubPropertyOperation::UpdateUpstream,
+            property: Some(value.clone()),
+        }));
+    }
+
+    fn tick(&mut self, dt: f64) {
+        let ids: Vec<u8> = self.ports.keys().copied().collect();
+        for port_id in ids {
+            let Some(port) = self.ports.get_mut(&port_id) else {
+                continue;
+            };
+            if let Some(motor) = port.motor.as_mut() {
+                if let Some(feedback) = motor.integrate(dt) {
+                    if feedback {
+                        self.emit(command_feedback(port_id, COMPLETED | IDLE));
+                    }
+                }
+            }
+            self.report(port_id);
+        }
+
+        // A virtual port's command completes when both motors are done
+        let mut completed = Vec::new();
+        for (port_id, port) in self.ports.iter() {
+            let Some(pair) = port.pair.as_ref().filter(|p| p.running) else {
+                continue;
+            };
+            let (a, b) = pair.ports;
+            let busy = |id| {
+                self.ports
+                    .get(&id)
+                    .and_then(|p| p.motor.as_ref())
+                    .is_some_and(|m| m.goal.is_some())
+            };
+            if !busy(a) && !busy(b) {
+                completed.push(*port_id);
+            }
+        }
+        for port_id in completed {
+            let Some(pair) =
+                self.ports.get_mut(&port_id).and_then(|p| p.pair.as_mut())
+            else {
+                continue;
+            };
+            pair.running = false;
+            if pair.feedback {
+                self.emit(command_feedback(port_id, COMPLETED | IDLE));
+            }
+        }
+    }
+
+    /// Send value notifications for whatever changed on the port since
+    /// the last report.
+    fn report(&mut self, port_id: u8) {
+        let Some(port) = self.ports.get_mut(&port_id) else {
+            return;
+        };
+        port.sync_motor_values();
+        let mut messages = Vec::new();
+
+        if let Some(single) = port.single.as_ref().filter(|s| s.enabled) {
+            let current = &port.values[single.mode as usize];
+            if changed(single.last.as_deref(), current, single.delta) {
+                if let Some(bytes) = port.encode(single.mode) {
+                    messages.push(value_single(port_id, &bytes));
+                }
+                let current = current.clone();
+                if let Some(single) = port.single.as_mut() {
+                    single.last = Some(current);
+                }
+            }
+        }
+
+        if port.combined.enabled && !port.combined.locked {
+            let current: Vec<i32> = port
+                .combined
+                .entries
+                .iter()
+                .map(|(mode, dataset)| {
+                    port.values
+                        .get(*mode as usize)
+                        .and_then(|v| v.get(*dataset as usize))
+                        .copied()
+                        .unwrap_or_default()
+                })
+                .collect();
+            let mut pointer: u16 = 0;
+            for (i, (mode, _)) in port.combined.entries.iter().enumerate() {
+                let delta = port.deltas.get(mode).copied().unwrap_or(1);
+                let last = port.combined.last.as_ref().map(|l| &l[i..=i]);
+                if changed(last, &current[i..=i], delta) {
+                    pointer |= 1 << i;
+                }
+            }
+            // A change in the primary value reports the whole combination
+            if pointer & 1 == 1 {
+                pointer = (1 << current.len()) - 1;
+            }
+            if pointer != 0 {
+                let mut bytes = pointer.to_le_bytes().to_vec();
+                for (i, (mode, dataset)) in
+                    port.combined.entries.iter().enumerate()
+                {
+                    if pointer & (1 << i) != 0 {
+                        let value =
+                            port.values[*mode as usize][*dataset as usize];
+                        let format = &port.device.modes[*mode as usize];
+                        bytes.extend(encode(format.dataset_type, value));
+                    }
+                }
+                messages.push(NotificationMessage::PortValueCombined(
+                    PortValueCombinedFormat {
+                        port_id,
+                        data: bytes,
+                    },
+                ));
+                port.combined.last = Some(current);
+            }
+        }
+
+        for msg in messages {
+            self.emit(msg);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct SimPort {
+    kind: IoTypeId,
+    device: KnownDevice,
+    /// Raw dataset values, one entry per mode
+    values: Vec<Vec<i32>>,
+    deltas: BTreeMap<u8, u32>,
+    single: Option<SingleSetup>,
+    combined: CombinedSetup,
+    motor: Option<SimMotor>,
+    /// Set on virtual ports
+    pair: Option<SimPair>,
+    /// Values written by output commands, by mode, oldest first
+    written: Vec<(u8, Vec<i32>)>,
+}
+
+impl SimPort {
+    fn new(kind: IoTypeId) -> Self {
+        let device = known_devices::device(kind);
+        let values = device
+            .modes
+            .iter()
+            .map(|m| devices::initial_value(kind, m))
+            .collect();
+        Self {
+            kind,
+            device,
+            values,
+            deltas: Default::default(),
+            single: None,
+            combined: Default::default(),
+            motor: device.is_motor().then(SimMotor::default),
+            pair: None,
+            written: Vec::new(),
+        }
+    }
+
+    /// Set a mode's value from an output command
+    fn write(&mut self, mode: u8, value: Vec<i32>) {
+        self.values[mode as usize] = value.clone();
+        self.written.push((mode, value));
+    }
+
+    fn reset(&mut self) {
+        self.single = None;
+        self.combined = Default::default();
+        self.deltas.clear();
+        if let Some(motor) = self.motor.as_mut() {
+            motor.speed = 0;
+            motor.goal = None;
+        }
+        if let Some(pair) = self.pair.as_mut() {
+            pair.running = false;
+        }
+    }
+
+    fn mode_index(&self, name: &str) -> Option<usize> {
+        self.device.modes.iter().position(|m| m.name == name)
+    }
+
+    fn sync_motor_values(&mut self) {
+        let Some(motor) = self.motor.as_ref() else {
+            return;
+        };
+        let position = motor.position.round() as i32;
+        let speed = motor.speed as i32;
+        let apos = (position + 180).rem_euclid(360) - 180;
+        for (name, value) in [
+            ("POWER", speed),
+            ("SPEED", speed),
+            ("POS", position),
+            ("APOS", apos),
+        ] {
+            if let Some(i) = self.mode_index(name) {
+                self.values[i] = vec![value];
+            }
+        }
+    }
+
+    fn encode(&self, mode: u8) -> Option<Vec<u8>> {
+        let format = self.device.modes.get(mode as usize)?;
+        let values = self.values.get(mode as usize)?;
+        Some(
+            values
+                .iter()
+                .flat_map(|v| encode(format.dataset_type, *v))
+                .collect(),
+        )
+    }
+}
+
+/// Motors driven together through a virtual port
+#[derive(Debug)]
+struct SimPair {
+    ports: (u8, u8),
+    feedback: bool,
+    running: bool,
+}
+
+#[derive(Debug)]
+struct SingleSetup {
+    mode: u8,
+    delta: u32,
+    enabled: bool,
+    last: Option<Vec<i32>>,
+}
+
+#[derive(Debug, Default)]
+struct CombinedSetup {
+    locked: bool,
+    enabled: bool,
+    entries: Vec<(u8, u8)>,
+    last: Option<Vec<i32>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Goal {
+    /// Absolute encoder position to stop at
+    Position(f64),
+    /// Seconds left to run
+    Time(f64),
+}
+
+#[derive(Debug, Default)]
+struct SimMotor {
+    position: f64,
+    speed: i8,
+    goal: Option<Goal>,
+    feedback: bool,
+}
+
+impl SimMotor {
+    /// Start a new command, returning the feedback status to report
+    fn run(&mut self, speed: i8, goal: Option<Goal>, feedback: bool) -> u8 {
+        let discarded = if self.goal.is_some() { DISCARDED } else { 0 };
+        self.goal = goal;
+        self.feedback = feedback;
+        match goal {
+            Some(Goal::Position(target)) => {
+                let direction = (target - self.position).signum();
+                self.speed = (direction * speed as f64) as i8;
+            }
+            _ => self.speed = speed,
+        }
+        if self.goal.is_some() && self.speed != 0 {
+            discarded | IN_PROGRESS
+        } else {
+            self.goal = None;
+            discarded | COMPLETED | IDLE
+        }
+    }
+
+    /// Advance by dt seconds. Returns Some(feedback requested) when the
+    /// running command completed during this step.
+    fn integrate(&mut self, dt: f64) -> Option<bool> {
+        let step = self.speed as f64 / 100.0 * DEGREES_PER_SECOND * dt;
+        match self.goal {
+            None => {
+                self.position += step;
+                None
+            }
+            Some(Goal::Position(target)) => {
+                let remaining = target - self.position;
+                if remaining.abs() <= step.abs() || remaining * step <= 0.0 {
+                    self.position = target;
+                    Some(self.stop())
+                } else {
+                    self.position += step;
+                    None
+                }
+            }
+            Some(Goal::Time(left)) => {
+                let run = left.min(dt);
+                self.position += step * run / dt;
+                if left <= dt {
+                    Some(self.stop())
+                } else {
+                    self.goal = Some(Goal::Time(left - dt));
+                    None
+                }
+            }
+        }
+    }
+
+    fn stop(&mut self) -> bool {
+        self.speed = 0;
+        self.goal = None;
+        self.feedback
+    }
+}
+
+fn changed(last: Option<&[i32]>, current: &[i32], delta: u32) -> bool {
+    let Some(last) = last else {
+        return true;
+    };
+    let delta = delta.max(1) as i64;
+    last.iter()
+        .zip(current)
+        .any(|(l, c)| (*l as i64 - *c as i64).abs() >= delta)
+}
+
+fn power_to_speed(power: Power) -> i8 {
+    match power {
+        Power::Cw(p) => p as i8,
+        Power::Ccw(p) => -(p as i8),
+        Power::Float | Power::Brake => 0,
+    }
+}
+
+fn encode(dataset_type: DatasetType, value: i32) -> Vec<u8> {
+    match dataset_type {
+        DatasetType::Bits8 => vec![value as i8 as u8],
+        DatasetType::Bits16 => (value as i16).to_le_bytes().to_vec(),
+        DatasetType::Bits32 => value.to_le_bytes().to_vec(),
+        DatasetType::Float => (value as f32).to_le_bytes().to_vec(),
+    }
+}
+
+fn attached_io(port_id: u8, kind: IoTypeId) -> NotificationMessage {
+    let version = VersionNumber {
+        major: 1,
+        minor: 0,
+        bugfix: 0,
+        build: 0,
+    };
+    NotificationMessage::HubAttachedIo(AttachedIo {
+        port: port_id,
+        event: IoAttachEvent::AttachedIo {
+            io_type_id: kind,
+            hw_rev: version,
+            fw_rev: version,
+        },
+    })
+}
+
+fn attached_virtual_io(
+    port_id: u8,
+    kind: IoTypeId,
+    port_a: u8,
+    port_b: u8,
+) -> NotificationMessage {
+    NotificationMessage::HubAttachedIo(AttachedIo {
+        port: port_id,
+        event: IoAttachEvent::AttachedVirtualIo {
+            io_type_id: kind,
+            port_a,
+            port_b,
+        },
+    })
+}
+
+fn detached_io(port_id: u8) -> NotificationMessage {
+    NotificationMessage::HubAttachedIo(AttachedIo {
+        port: port_id,
+        event: IoAttachEvent::DetachedIo {},
+    })
+}
+
+fn value_single(port_id: u8, bytes: &[u8]) -> NotificationMessage {
+    NotificationMessage::PortValueSingle(PortValueSingleFormat {
+        port_id,
+        data: bytes.iter().map(|b| *b as i8).collect(),
+    })
+}
+
+fn command_feedback(port_id: u8, status: u8) -> NotificationMessage {
+    NotificationMessage::PortOutputCommandFeedback(
+        PortOutputCommandFeedbackFormat {
+            msg1: FeedbackMessage {
+                port_id,
+                empty_cmd_in_progress: status & IN_PROGRESS != 0,
+                empty_cmd_completed: status & COMPLETED != 0,
+                discarded: status & DISCARDED != 0,
+                idle: status & IDLE != 0,
+                busy_full: false,
+            },
+            msg2: None,
+            msg3: None,
+        },
+    )
+}
+
+fn generic_error(
+    command_type: u8,
+    error_code: ErrorCode,
+) -> NotificationMessage {
+    NotificationMessage::GenericErrorMessages(ErrorMessageFormat {
+        command_type,
+        error_code,
+    })
+}
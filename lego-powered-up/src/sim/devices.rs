@@ -0,0 +1,23 @@
+//! Values the simulated devices start with. Their modes are described by
+//! the tables in `iodevice::known_devices`.
+
+use crate::iodevice::known_devices::KnownMode;
+use crate::IoTypeId;
+
+/// Value reported before anything has been set, in raw units.
+pub fn initial_value(kind: IoTypeId, mode: &KnownMode) -> Vec<i32> {
+    use IoTypeId::*;
+    let single = match (kind, mode.name) {
+        (Voltage, _) => 3500,
+        (Current, _) => 150,
+        (TechnicHubTemperatureSensor, _) => 250,
+        (DuploTrainBaseColorSensor, "COLOR") => -1,
+        // Neither a barcode nor a color
+        (MarioBarcodeSensor, "TAG") => -1,
+        (TechnicHubAccelerometer, "GRV") => {
+            return vec![0, 0, 4096];
+        }
+        _ => 0,
+    };
+    vec![single; mode.datasets as usize]
+}
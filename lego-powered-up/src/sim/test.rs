@@ -0,0 +1,1098 @@
+use super::*;
+use crate::consts::HubPropertyRef;
+use crate::hubs::HubEvent;
+use crate::iodevice::basic::{Basic, CombinedValue};
+use crate::iodevice::colorsensor::{Color, ColorSensor, Hsv};
+use crate::iodevice::distancesensor::DistanceSensor;
+use crate::iodevice::duplo::{DuploMotor, Speedometer, TrackSensor};
+use crate::iodevice::forcesensor::ForceSensor;
+use crate::iodevice::imu::{
+    Accelerometer, Gyro, Temperature, TiltSensor, Vector3,
+};
+use crate::iodevice::lightmatrix::{Frame, LightMatrix, Pixel};
+use crate::iodevice::mario::{
+    MarioAccelerometer, MarioColor, MarioPants, MarioPantsType, MarioScanner,
+};
+use crate::iodevice::modes::Technic3x3ColorLightMatrix::{PIX_O, TRANS};
+use crate::iodevice::motor::{
+    BufferState, CommandOutcome, EncoderMotor, EndState, SyncedMotorPair,
+};
+use crate::iodevice::remote::{
+    RcDevice, RemoteButton, RemoteConfig, RemoteEvent, Remotes,
+    TaggedRemoteEvent,
+};
+use crate::iodevice::sensor::GenericSensor;
+use crate::iodevice::speaker::{DuploTrainBaseSound, Speaker};
+use crate::iodevice::tilt::{InternalTilt, TiltAngle};
+use crate::iodevice::IoDevice;
+use crate::notifications::{Orientation, StartupInfo, TypedValue};
+use crate::pool::{HubPool, PoolEvent};
+use crate::ConnectionEvent;
+use tokio::sync::broadcast;
+use tokio::time::timeout;
+
+const WAIT: Duration = Duration::from_secs(2);
+
+async fn technic_hub_with_motor() -> (Arc<SimHub>, ConnectedHub) {
+    let sim = Arc::new(
+        SimHub::technic_hub().with_device(0, IoTypeId::TechnicLargeLinearMotor),
+    );
+    let hub = connect(sim.clone()).await.unwrap();
+    (sim, hub)
+}
+
+#[tokio::test]
+async fn attached_devices_are_described() {
+    let (_sim, hub) = technic_hub_with_motor().await;
+    let lock = hub.mutex.lock().await;
+    let motor = lock.io_from_port(0).unwrap();
+    assert_eq!(*motor.kind(), IoTypeId::TechnicLargeLinearMotor);
+    assert_eq!(*motor.def().mode_count(), 6);
+    let names: Vec<&str> =
+        motor.def().modes().values().map(|m| m.name()).collect();
+    assert!(names.contains(&"POS"));
+    assert!(lock.io_from_kind(IoTypeId::Voltage).is_ok());
+}
+
+#[tokio::test]
+async fn setup_finishes_once_devices_are_described() {
+    let started = std::time::Instant::now();
+    let (_sim, hub) = technic_hub_with_motor().await;
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert!(hub.wait_until_ready(WAIT).await.is_ok());
+}
+
+#[tokio::test]
+async fn setup_finishes_when_hub_rejects_mode_information() {
+    let sim = Arc::new(
+        SimHub::technic_hub().with_device(0, IoTypeId::TechnicLargeLinearMotor),
+    );
+    sim.reject_mode_information(ModeInformationType::Symbol);
+    let started = std::time::Instant::now();
+    let hub = connect(sim.clone()).await.unwrap();
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert!(hub.wait_until_ready(WAIT).await.is_ok());
+    let lock = hub.mutex.lock().await;
+    let motor = lock.io_from_port(0).unwrap();
+    let names: Vec<&str> =
+        motor.def().modes().values().map(|m| m.name()).collect();
+    assert!(names.contains(&"POS"));
+}
+
+#[tokio::test]
+async fn waits_for_device_attached_later() {
+    let (sim, hub) = technic_hub_with_motor().await;
+    let waiting =
+        hub.wait_for_device(IoTypeId::TechnicLargeAngularMotorGrey, 1);
+    let attach = async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        sim.attach_device(1, IoTypeId::TechnicLargeAngularMotorGrey);
+    };
+    let (device, ()) = tokio::join!(waiting, attach);
+    let device = device.unwrap();
+    assert_eq!(*device.kind(), IoTypeId::TechnicLargeAngularMotorGrey);
+    assert_eq!(*device.def().mode_count(), 6);
+}
+
+#[tokio::test]
+async fn wait_for_device_times_out() {
+    let sim = Arc::new(SimHub::technic_hub());
+    sim.connect().await.unwrap();
+    let hub =
+        GenericHub::init(sim.clone(), sim.kind(), CancellationToken::new())
+            .await
+            .unwrap();
+    let hub = ConnectedHub::setup_hub_with_timeout(
+        Box::new(hub),
+        Duration::from_millis(200),
+    )
+    .await
+    .unwrap();
+    let result = hub
+        .wait_for_device(IoTypeId::TechnicLargeLinearMotor, 0)
+        .await;
+    assert!(matches!(result, Err(Error::TimeoutError(_))));
+}
+
+#[tokio::test]
+async fn hub_not_ready_in_time_is_disconnected() {
+    let sim = Arc::new(
+        SimHub::technic_hub().with_device(0, IoTypeId::TechnicLargeLinearMotor),
+    );
+    sim.connect().await.unwrap();
+    let hub =
+        GenericHub::init(sim.clone(), sim.kind(), CancellationToken::new())
+            .await
+            .unwrap();
+    // The hub never answers the request readiness waits for
+    sim.ignore_property_requests(HubPropertyRef::FwVersion);
+    let result = ConnectedHub::setup_hub_with_timeout(
+        Box::new(hub),
+        Duration::from_millis(50),
+    )
+    .await;
+    assert!(matches!(result, Err(Error::TimeoutError(_))));
+    assert!(!sim.is_connected().await.unwrap());
+}
+
+#[tokio::test]
+async fn motor_commands_can_be_awaited() {
+    let (sim, hub) = technic_hub_with_motor().await;
+    let motor = hub.mutex.lock().await.io_from_port(0).unwrap();
+
+    let outcome = motor
+        .start_speed_for_degrees_wait(90, 50, 100, EndState::Brake, WAIT)
+        .await
+        .unwrap();
+    assert_eq!(outcome, CommandOutcome::Completed);
+    assert_eq!(sim.position(0), Some(90));
+    let outcome = motor
+        .goto_absolute_position_wait(0, 50, 100, EndState::Brake, WAIT)
+        .await
+        .unwrap();
+    assert_eq!(outcome, CommandOutcome::Completed);
+    assert_eq!(sim.position(0), Some(0));
+
+    // Replaced by a later command
+    let (first, second) = tokio::join!(
+        motor.start_speed_for_degrees_wait(720, 50, 100, EndState::Brake, WAIT),
+        async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            motor
+                .goto_absolute_position_wait(0, 50, 100, EndState::Brake, WAIT)
+                .await
+        }
+    );
+    assert_eq!(first.unwrap(), CommandOutcome::Discarded);
+    assert_eq!(second.unwrap(), CommandOutcome::Completed);
+
+    let result = motor
+        .start_speed_for_time_wait(
+            10_000,
+            50,
+            100,
+            EndState::Brake,
+            Duration::from_millis(100),
+        )
+        .await;
+    assert!(matches!(result, Err(Error::TimeoutError(_))));
+}
+
+#[tokio::test]
+async fn command_completed_at_once_is_awaited() {
+    let (sim, hub) = technic_hub_with_motor().await;
+    let motor = hub.mutex.lock().await.io_from_port(0).unwrap();
+
+    // Nothing to run: the only feedback is the command completed
+    let outcome = motor
+        .start_speed_for_time_wait(1000, 0, 100, EndState::Brake, WAIT)
+        .await
+        .unwrap();
+    assert_eq!(outcome, CommandOutcome::Completed);
+    assert_eq!(sim.speed(0), Some(0));
+}
+
+#[tokio::test]
+async fn synced_motor_pair() {
+    let sim = Arc::new(
+        SimHub::technic_hub()
+            .with_device(0, IoTypeId::TechnicLargeLinearMotor)
+            .with_device(1, IoTypeId::TechnicLargeLinearMotor)
+            .with_device(2, IoTypeId::TechnicLargeAngularMotorGrey),
+    );
+    let hub = connect(sim.clone()).await.unwrap();
+    let pair = hub.create_virtual_port(0, 1).await.unwrap();
+    assert_eq!(pair.motor_ports().unwrap(), (0, 1));
+    assert_eq!(*pair.kind(), IoTypeId::TechnicLargeLinearMotor);
+    assert_eq!(*pair.def().mode_count(), 6);
+    let again = hub.create_virtual_port(0, 1).await.unwrap();
+    assert_eq!(again.port(), pair.port());
+
+    let outcome = pair
+        .start_speed_for_degrees2_wait(180, 50, -25, 100, EndState::Brake, WAIT)
+        .await
+        .unwrap();
+    assert_eq!(outcome, CommandOutcome::Completed);
+    assert_eq!(sim.position(0), Some(180));
+    assert_eq!(sim.position(1), Some(-90));
+    let outcome = pair
+        .goto_absolute_position2_wait(0, 45, 50, 100, EndState::Brake, WAIT)
+        .await
+        .unwrap();
+    assert_eq!(outcome, CommandOutcome::Completed);
+    assert_eq!(sim.position(0), Some(0));
+    assert_eq!(sim.position(1), Some(45));
+
+    // Motors of different kinds can't be combined
+    assert!(hub.create_virtual_port(1, 2).await.is_err());
+    // Single motors aren't pairs
+    let single = hub.mutex.lock().await.io_from_port(0).unwrap();
+    assert!(single.start_speed2(10, 10, 100).await.is_err());
+
+    let mut events = hub.events();
+    hub.mutex
+        .lock()
+        .await
+        .disconnect_virtual_port(pair.port())
+        .await
+        .unwrap();
+    let event = timeout(WAIT, events.recv()).await.unwrap().unwrap();
+    assert!(
+        matches!(event, HubEvent::Detached { port_id, .. } if port_id == pair.port())
+    );
+}
+
+#[tokio::test]
+async fn hub_properties_are_reported() {
+    let (sim, hub) = technic_hub_with_motor().await;
+    let properties = hub.properties();
+    assert_eq!(properties.name().await.unwrap(), "Technic Hub");
+    assert_eq!(properties.fw_version().await.unwrap(), "1.1.00.0000");
+    assert_eq!(properties.mac_address().await.unwrap(), "00:16:53:00:00:00");
+    assert_eq!(properties.battery_level().await.unwrap(), 100);
+    assert_eq!(properties.lwp_version().await.unwrap(), 0x0300);
+
+    let mut battery = properties.watch_battery_level();
+    sim.set_property(HubPropertyValue::BatteryVoltage(42));
+    timeout(WAIT, battery.wait_for(|b| *b == Some(42)))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(hub.mutex.lock().await.properties().battery_level, 42);
+
+    sim.set_property(HubPropertyValue::Button(1));
+    timeout(
+        WAIT,
+        properties.watch_button().wait_for(|b| *b == Some(true)),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+}
+
+#[tokio::test]
+async fn reports_hub_events() {
+    let (sim, hub) = technic_hub_with_motor().await;
+    let mut events = hub.events();
+
+    sim.detach_device(0);
+    let event = timeout(WAIT, events.recv()).await.unwrap().unwrap();
+    assert_eq!(
+        event,
+        HubEvent::Detached {
+            port_id: 0,
+            io_type_id: Some(IoTypeId::TechnicLargeLinearMotor)
+        }
+    );
+    sim.attach_device(1, IoTypeId::TechnicLargeAngularMotorGrey);
+    let event = timeout(WAIT, events.recv()).await.unwrap().unwrap();
+    assert_eq!(
+        event,
+        HubEvent::Attached {
+            port_id: 1,
+            io_type_id: IoTypeId::TechnicLargeAngularMotorGrey
+        }
+    );
+
+    sim.set_property(HubPropertyValue::Button(1));
+    let event = timeout(WAIT, events.recv()).await.unwrap().unwrap();
+    assert_eq!(event, HubEvent::Button { pressed: true });
+    let event = timeout(WAIT, events.recv()).await.unwrap().unwrap();
+    assert_eq!(event, HubEvent::Property(HubPropertyValue::Button(1)));
+
+    let lock = hub.mutex.lock().await;
+    lock.hub_props(
+        HubPropertyRef::SecondaryMacAddress,
+        HubPropertyOperation::RequestUpdateDownstream,
+    )
+    .await
+    .unwrap();
+    lock.hub_action(HubAction::Disconnect).await.unwrap();
+    drop(lock);
+    let event = timeout(WAIT, events.recv()).await.unwrap().unwrap();
+    assert!(matches!(
+        event,
+        HubEvent::Error(ErrorMessageFormat {
+            error_code: ErrorCode::InvalidUse,
+            ..
+        })
+    ));
+    let event = timeout(WAIT, events.recv()).await.unwrap().unwrap();
+    assert_eq!(event, HubEvent::Action(HubAction::HubWillDisconnect));
+}
+
+#[tokio::test]
+async fn motor_reports_feedback_and_position() {
+    let (sim, hub) = technic_hub_with_motor().await;
+    let motor = hub.mutex.lock().await.io_from_port(0).unwrap();
+    let (mut feedback, _task) = motor.cmd_feedback_handler().unwrap();
+
+    motor
+        .start_speed_for_degrees_soc(
+            90,
+            50,
+            100,
+            EndState::Brake,
+            StartupInfo::ExecuteImmediately,
+            CompletionInfo::CommandFeedback,
+        )
+        .await
+        .unwrap();
+    let started = timeout(WAIT, feedback.recv()).await.unwrap().unwrap();
+    assert_eq!(started.state, BufferState::BusyEmpty);
+    let done = timeout(WAIT, feedback.recv()).await.unwrap().unwrap();
+    assert_eq!(done.state, BufferState::Idle);
+    assert_eq!(sim.position(0), Some(90));
+
+    motor
+        .goto_absolute_position_soc(
+            -45,
+            50,
+            100,
+            EndState::Brake,
+            StartupInfo::ExecuteImmediately,
+            CompletionInfo::NoAction,
+        )
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(sim.position(0), Some(-45));
+    assert_eq!(sim.speed(0), Some(0));
+}
+
+#[tokio::test]
+async fn combined_motor_values() {
+    let (_sim, hub) = technic_hub_with_motor().await;
+    let motor = hub.mutex.lock().await.io_from_port(0).unwrap();
+    let (mut values, _task) =
+        motor.motor_combined_sensor_enable(1, 1).await.unwrap();
+    assert_eq!(timeout(WAIT, values.recv()).await.unwrap().unwrap(), (0, 0));
+
+    motor.start_speed(20, 100).await.unwrap();
+    let (speed, position) = loop {
+        let (speed, position) =
+            timeout(WAIT, values.recv()).await.unwrap().unwrap();
+        if position > 0 {
+            break (speed, position);
+        }
+    };
+    assert_eq!(speed, 20);
+    assert!(position > 0);
+}
+
+#[tokio::test]
+async fn combined_values_are_typed() {
+    let (_sim, hub) = technic_hub_with_motor().await;
+    let motor = hub.mutex.lock().await.io_from_port(0).unwrap();
+    assert_eq!(motor.def().valid_combos(), &vec![vec![1, 2, 3]]);
+    assert!(motor.enable_combined(&[(0, 0), (1, 0)], 1).await.is_err());
+    assert!(motor.enable_combined(&[(1, 1)], 1).await.is_err());
+
+    let (mut values, _task) =
+        motor.enable_combined(&[(2, 0), (1, 0)], 1).await.unwrap();
+    let first = timeout(WAIT, values.recv()).await.unwrap().unwrap();
+    assert_eq!(
+        first,
+        vec![
+            CombinedValue {
+                mode: 2,
+                dataset: 0,
+                value: TypedValue::Bits32(0),
+            },
+            CombinedValue {
+                mode: 1,
+                dataset: 0,
+                value: TypedValue::Bits8(0),
+            },
+        ]
+    );
+
+    motor.start_speed(20, 100).await.unwrap();
+    loop {
+        let update = timeout(WAIT, values.recv()).await.unwrap().unwrap();
+        let speed = update.iter().find(|v| v.mode == 1);
+        if let Some(speed) = speed {
+            assert_eq!(speed.value, TypedValue::Bits8(20));
+            break;
+        }
+    }
+}
+
+#[tokio::test]
+async fn sensor_values_follow_the_sim() {
+    let (sim, hub) = technic_hub_with_motor().await;
+    let voltage = hub
+        .mutex
+        .lock()
+        .await
+        .io_from_kind(IoTypeId::Voltage)
+        .unwrap();
+    let (mut values, _task) = voltage.enable_16bit_sensor(0, 1).await.unwrap();
+    assert_eq!(timeout(WAIT, values.recv()).await.unwrap().unwrap(), [3500]);
+
+    sim.set_value(0x3c, 0, &[3400]).unwrap();
+    assert_eq!(timeout(WAIT, values.recv()).await.unwrap().unwrap(), [3400]);
+}
+
+#[tokio::test]
+async fn sensor_values_are_scaled() {
+    let sim = Arc::new(SimHub::technic_hub());
+    let hub = connect(sim.clone()).await.unwrap();
+    let temperature = hub.mutex.lock().await.io_from_port(0x3d).unwrap();
+    let (mut values, _task) =
+        temperature.enable_scaled_sensor(0, 1).await.unwrap();
+    sim.set_value(0x3d, 0, &[255]).unwrap();
+    let value = loop {
+        let value = timeout(WAIT, values.recv()).await.unwrap().unwrap();
+        if value.values[0].raw == TypedValue::Bits16(255) {
+            break value;
+        }
+    };
+    assert_eq!(value.mode, 0);
+    assert_eq!(value.symbol, "DEG");
+    assert!((value.values[0].si - 25.5).abs() < 1e-4);
+    assert!((value.values[0].pct - 28.333).abs() < 1e-3);
+    assert_eq!(value.to_string(), "25.5 DEG");
+}
+
+#[tokio::test]
+async fn technic_hub_imu_readings() {
+    let sim = Arc::new(SimHub::technic_hub());
+    let hub = connect(sim.clone()).await.unwrap();
+    let (accelerometer, gyro, tilt, temperature) = {
+        let lock = hub.mutex.lock().await;
+        (
+            lock.io_from_port(0x61).unwrap(),
+            lock.io_from_port(0x62).unwrap(),
+            lock.io_from_port(0x63).unwrap(),
+            lock.io_from_port(0x3d).unwrap(),
+        )
+    };
+    assert!(Accelerometer::check(&tilt).is_err());
+
+    sim.set_value(0x61, 0, &[4096, 0, -4096]).unwrap();
+    let (mut acceleration, _task) =
+        accelerometer.acceleration(1).await.unwrap();
+    let value = timeout(WAIT, acceleration.recv()).await.unwrap().unwrap();
+    assert!((value.x - 1000.0).abs() < 0.1);
+    assert!((value.z + 1000.0).abs() < 0.1);
+
+    sim.set_value(0x62, 0, &[0, 28571, 0]).unwrap();
+    let (mut rotation, _task) = gyro.rotation(1).await.unwrap();
+    let value = timeout(WAIT, rotation.recv()).await.unwrap().unwrap();
+    assert_eq!(
+        value,
+        Vector3 {
+            x: 0.0,
+            y: 2000.0,
+            z: 0.0
+        }
+    );
+
+    sim.set_value(0x63, 0, &[10, 20, 30]).unwrap();
+    let (mut angles, _task) = tilt.tilt(1).await.unwrap();
+    let value = timeout(WAIT, angles.recv()).await.unwrap().unwrap();
+    assert_eq!(
+        value,
+        Vector3 {
+            x: 30.0,
+            y: 20.0,
+            z: -10.0
+        }
+    );
+
+    sim.set_value(0x3d, 0, &[-55]).unwrap();
+    let (mut degrees, _task) = temperature.temperature(1).await.unwrap();
+    let value = timeout(WAIT, degrees.recv()).await.unwrap().unwrap();
+    assert!((value + 5.5).abs() < 1e-4);
+}
+
+#[tokio::test]
+async fn move_hub_tilt_sensor() {
+    let sim = Arc::new(SimHub::move_hub());
+    let hub = connect(sim.clone()).await.unwrap();
+    let tilt = hub.mutex.lock().await.io_from_port(0x3a).unwrap();
+
+    sim.set_value(0x3a, 0, &[-30, 45]).unwrap();
+    let (mut angles, _task) = tilt.tilt_angle(1).await.unwrap();
+    let value = timeout(WAIT, angles.recv()).await.unwrap().unwrap();
+    assert_eq!(value, TiltAngle { x: -30.0, y: 45.0 });
+
+    sim.set_value(0x3a, 2, &[5]).unwrap();
+    let (mut orientation, _task) = tilt.tilt_orientation().await.unwrap();
+    let value = timeout(WAIT, orientation.recv()).await.unwrap().unwrap();
+    assert_eq!(value, Orientation::Top);
+
+    let (mut impacts, _task) = tilt.impact_count(1).await.unwrap();
+    assert_eq!(timeout(WAIT, impacts.recv()).await.unwrap().unwrap(), 0);
+    tilt.preset_impact_count(7).await.unwrap();
+    assert_eq!(timeout(WAIT, impacts.recv()).await.unwrap().unwrap(), 7);
+    assert_eq!(sim.value(0x3a, 3), Some(vec![7]));
+
+    sim.set_value(0x3a, 4, &[65, 0, -65]).unwrap();
+    let (mut acceleration, _task) = tilt.tilt_acceleration(1).await.unwrap();
+    let value = timeout(WAIT, acceleration.recv()).await.unwrap().unwrap();
+    assert!((value.x - 1.6).abs() < 1e-4);
+    assert!((value.z + 1.6).abs() < 1e-4);
+}
+
+#[tokio::test]
+async fn technic_sensors() {
+    let sim = Arc::new(
+        SimHub::technic_hub()
+            .with_device(0, IoTypeId::TechnicColorSensor)
+            .with_device(1, IoTypeId::TechnicDistanceSensor)
+            .with_device(2, IoTypeId::TechnicForceSensor),
+    );
+    let hub = connect(sim.clone()).await.unwrap();
+    let (color, distance, force) = {
+        let lock = hub.mutex.lock().await;
+        (
+            lock.io_from_port(0).unwrap(),
+            lock.io_from_port(1).unwrap(),
+            lock.io_from_port(2).unwrap(),
+        )
+    };
+    assert!(DistanceSensor::check(&color).is_err());
+
+    let (mut colors, _task) = color.color().await.unwrap();
+    let value = timeout(WAIT, colors.recv()).await.unwrap().unwrap();
+    assert_eq!(value, Some(Color::Black));
+    sim.set_value(0, 0, &[9]).unwrap();
+    let value = timeout(WAIT, colors.recv()).await.unwrap().unwrap();
+    assert_eq!(value, Some(Color::Red));
+    sim.set_value(0, 0, &[-1]).unwrap();
+    assert_eq!(timeout(WAIT, colors.recv()).await.unwrap().unwrap(), None);
+
+    sim.set_value(0, 6, &[120, 50, 80]).unwrap();
+    let (mut hsv, _task) = color.hsv(1).await.unwrap();
+    let value = timeout(WAIT, hsv.recv()).await.unwrap().unwrap();
+    assert_eq!(
+        value,
+        Hsv {
+            hue: 120,
+            saturation: 50,
+            value: 80
+        }
+    );
+
+    sim.set_value(1, 0, &[-1]).unwrap();
+    let (mut mm, _task) = distance.distance(1).await.unwrap();
+    assert_eq!(timeout(WAIT, mm.recv()).await.unwrap().unwrap(), None);
+    sim.set_value(1, 0, &[123]).unwrap();
+    assert_eq!(timeout(WAIT, mm.recv()).await.unwrap().unwrap(), Some(123));
+
+    let (mut pressed, _task) = force.pressed(3.0).await.unwrap();
+    assert!(!timeout(WAIT, pressed.recv()).await.unwrap().unwrap());
+    sim.set_value(2, 0, &[10]).unwrap();
+    sim.set_value(2, 0, &[50]).unwrap();
+    assert!(timeout(WAIT, pressed.recv()).await.unwrap().unwrap());
+    let (mut newtons, _task) = force.force(1).await.unwrap();
+    let value = timeout(WAIT, newtons.recv()).await.unwrap().unwrap();
+    assert!((value - 5.0).abs() < 1e-4);
+}
+
+async fn light_matrix() -> (Arc<SimHub>, ConnectedHub, IoDevice) {
+    let sim = Arc::new(
+        SimHub::new(HubType::Hub, "SPIKE Essential")
+            .with_device(0, IoTypeId::Technic3x3ColorLightMatrix),
+    );
+    let hub = connect(sim.clone()).await.unwrap();
+    let matrix = hub.mutex.lock().await.io_from_port(0).unwrap();
+    (sim, hub, matrix)
+}
+
+fn pixels(frame: &Frame) -> Vec<i32> {
+    frame.to_bytes().iter().map(|b| *b as i32).collect()
+}
+
+#[tokio::test]
+async fn light_matrix_sets_pixels() {
+    let (sim, _hub, matrix) = light_matrix().await;
+    let mut frame = Frame::filled(Pixel::new(Color::Red));
+    matrix
+        .set_pixel(&mut frame, 1, 2, Pixel::new(Color::Blue))
+        .await
+        .unwrap();
+    assert_eq!(frame.0[5], Pixel::new(Color::Blue));
+    assert_eq!(sim.value(0, PIX_O), Some(pixels(&frame)));
+
+    // Out of the matrix: nothing changes or is written
+    let shown = frame;
+    assert!(matrix
+        .set_pixel(&mut frame, 3, 0, Pixel::OFF)
+        .await
+        .is_err());
+    assert_eq!(frame, shown);
+    assert_eq!(sim.written(0, PIX_O).len(), 1);
+
+    matrix.set_transition(1).await.unwrap();
+    assert_eq!(sim.value(0, TRANS), Some(vec![1]));
+}
+
+#[tokio::test]
+async fn light_matrix_plays_animations() {
+    let (sim, hub, matrix) = light_matrix().await;
+    let frames = vec![
+        Frame::filled(Pixel::new(Color::Red)),
+        Frame::filled(Pixel::new(Color::Green)),
+        Frame::filled(Pixel::new(Color::Blue)),
+    ];
+    let expected: Vec<Vec<i32>> = frames.iter().map(pixels).collect();
+
+    let task = matrix
+        .play_animation(frames.clone(), Duration::from_millis(10), false)
+        .unwrap();
+    timeout(WAIT, task).await.unwrap().unwrap().unwrap();
+    assert_eq!(sim.written(0, PIX_O), expected);
+
+    // Repeated until aborted
+    let task = matrix
+        .play_animation(frames[..2].to_vec(), Duration::from_millis(10), true)
+        .unwrap();
+    timeout(WAIT, async {
+        while sim.written(0, PIX_O).len() < 8 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    task.abort();
+    let written = sim.written(0, PIX_O);
+    assert_eq!(written[3..8], [0, 1, 0, 1, 0].map(|i| expected[i].clone()));
+
+    // A failed write ends the animation with the error
+    let task = matrix
+        .play_animation(frames, Duration::from_millis(10), true)
+        .unwrap();
+    hub.mutex.lock().await.disconnect().await.unwrap();
+    let result = timeout(WAIT, task).await.unwrap().unwrap();
+    assert!(matches!(result, Err(Error::HubError(_))));
+}
+
+#[tokio::test]
+async fn reconnects_and_restores_port_setup() {
+    let (sim, hub) = technic_hub_with_motor().await;
+    let mut events = hub.connection_events();
+    let voltage = hub
+        .mutex
+        .lock()
+        .await
+        .io_from_kind(IoTypeId::Voltage)
+        .unwrap();
+    let (mut values, _task) = voltage.enable_16bit_sensor(0, 1).await.unwrap();
+    assert_eq!(timeout(WAIT, values.recv()).await.unwrap().unwrap(), [3500]);
+
+    sim.drop_connection();
+    let event = timeout(WAIT, events.recv()).await.unwrap().unwrap();
+    assert_eq!(event, ConnectionEvent::Disconnected);
+    let event = timeout(WAIT * 2, events.recv()).await.unwrap().unwrap();
+    assert_eq!(event, ConnectionEvent::Reconnected);
+
+    // The sim forgot the mode on disconnect, so values only arrive if the
+    // setup was replayed
+    sim.set_value(0x3c, 0, &[3300]).unwrap();
+    let value = loop {
+        let value = timeout(WAIT, values.recv()).await.unwrap().unwrap();
+        if value != [3500] {
+            break value;
+        }
+    };
+    assert_eq!(value, [3300]);
+}
+
+#[tokio::test]
+async fn reconnects_and_restores_virtual_ports() {
+    let sim = Arc::new(
+        SimHub::technic_hub()
+            .with_device(0, IoTypeId::TechnicLargeLinearMotor)
+            .with_device(1, IoTypeId::TechnicLargeLinearMotor)
+            .with_device(2, IoTypeId::TechnicLargeLinearMotor)
+            .with_device(3, IoTypeId::TechnicLargeLinearMotor),
+    );
+    let hub = connect(sim.clone()).await.unwrap();
+    let first = hub.create_virtual_port(0, 1).await.unwrap();
+    let pair = hub.create_virtual_port(2, 3).await.unwrap();
+    hub.mutex
+        .lock()
+        .await
+        .disconnect_virtual_port(first.port())
+        .await
+        .unwrap();
+
+    let mut events = hub.connection_events();
+    sim.drop_connection();
+    let event = timeout(WAIT, events.recv()).await.unwrap().unwrap();
+    assert_eq!(event, ConnectionEvent::Disconnected);
+    let event = timeout(WAIT * 2, events.recv()).await.unwrap().unwrap();
+    assert_eq!(event, ConnectionEvent::Reconnected);
+
+    // Set up again, the pair gets the port the first one had, and is
+    // reported detached from its own before being attached there
+    let moved = hub
+        .wait_for_device(IoTypeId::TechnicLargeLinearMotor, first.port())
+        .await
+        .unwrap();
+    assert_eq!(moved.motor_ports().unwrap(), (2, 3));
+    assert!(hub.mutex.lock().await.io_from_port(pair.port()).is_err());
+    let outcome = moved
+        .start_speed_for_degrees2_wait(90, 50, 50, 100, EndState::Brake, WAIT)
+        .await
+        .unwrap();
+    assert_eq!(outcome, CommandOutcome::Completed);
+    assert_eq!(sim.position(2), Some(90));
+    assert_eq!(sim.position(3), Some(90));
+}
+
+#[tokio::test]
+async fn duplo_train_stops_at_red_and_honks_at_yellow() {
+    let sim = Arc::new(SimHub::duplo_train_base());
+    let hub = connect(sim.clone()).await.unwrap();
+    let (motor, speaker, sensor, speedometer) = {
+        let lock = hub.mutex.lock().await;
+        (
+            lock.io_from_port(0x00).unwrap(),
+            lock.io_from_port(0x01).unwrap(),
+            lock.io_from_port(0x12).unwrap(),
+            lock.io_from_port(0x13).unwrap(),
+        )
+    };
+    assert!(TrackSensor::check(&motor).is_err());
+
+    let (mut markers, _task) = sensor.markers().await.unwrap();
+    motor.drive(50).await.unwrap();
+    let automation = tokio::spawn(async move {
+        while let Ok(color) = markers.recv().await {
+            match color {
+                Color::Red => return motor.stop().await,
+                Color::Yellow => {
+                    speaker.play_sound(DuploTrainBaseSound::Horn).await?
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(sim.value(0x00, 0), Some(vec![50]));
+
+    sim.set_value(0x12, 0, &[Color::Yellow as i32]).unwrap();
+    timeout(WAIT, async {
+        while sim.value(0x01, 1) != Some(vec![DuploTrainBaseSound::Horn as i32])
+        {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(sim.value(0x00, 0), Some(vec![50]));
+
+    sim.set_value(0x12, 0, &[-1]).unwrap();
+    sim.set_value(0x12, 0, &[Color::Red as i32]).unwrap();
+    timeout(WAIT, automation).await.unwrap().unwrap().unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(sim.value(0x00, 0), Some(vec![0]));
+
+    let (mut speed, _task) = speedometer.train_speed(1).await.unwrap();
+    sim.set_value(0x13, 0, &[-30]).unwrap();
+    let value = timeout(WAIT, async {
+        loop {
+            let value = speed.recv().await.unwrap();
+            if value != 0 {
+                return value;
+            }
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(value, -30);
+    sim.set_value(0x13, 1, &[1234]).unwrap();
+    let (mut odometer, _task) = speedometer.odometer(1).await.unwrap();
+    let value = timeout(WAIT, odometer.recv()).await.unwrap().unwrap();
+    assert_eq!(value, 1234);
+}
+
+#[tokio::test]
+async fn mario_sensors() {
+    let sim = Arc::new(SimHub::mario());
+    let hub = connect(sim.clone()).await.unwrap();
+    let (accelerometer, scanner, pants) = {
+        let lock = hub.mutex.lock().await;
+        (
+            lock.io_from_port(0x00).unwrap(),
+            lock.io_from_port(0x01).unwrap(),
+            lock.io_from_port(0x02).unwrap(),
+        )
+    };
+    assert!(MarioPants::check(&scanner).is_err());
+
+    let (mut outfits, _task) = pants.pants().await.unwrap();
+    let value = timeout(WAIT, outfits.recv()).await.unwrap().unwrap();
+    assert_eq!(value, MarioPantsType::None);
+    sim.set_value(0x02, 0, &[0x12]).unwrap();
+    let value = timeout(WAIT, outfits.recv()).await.unwrap().unwrap();
+    assert_eq!(value, MarioPantsType::Fire);
+
+    let (mut colors, _task) = scanner.scanned_colors().await.unwrap();
+    let (mut barcodes, _task) = scanner.barcodes().await.unwrap();
+    sim.set_value(0x01, 0, &[-1, MarioColor::Red as i32])
+        .unwrap();
+    let value = timeout(WAIT, colors.recv()).await.unwrap().unwrap();
+    assert_eq!(value, MarioColor::Red);
+    sim.set_value(0x01, 0, &[0x0029, -1]).unwrap();
+    let value = timeout(WAIT, barcodes.recv()).await.unwrap().unwrap();
+    assert_eq!(value, 0x0029);
+    assert!(colors.try_recv().is_err());
+
+    let (mut acceleration, _task) =
+        accelerometer.mario_acceleration(1).await.unwrap();
+    sim.set_value(0x00, 0, &[10, -20, 30]).unwrap();
+    let value = timeout(WAIT, async {
+        loop {
+            let value = acceleration.recv().await.unwrap();
+            if value.x != 0.0 {
+                return value;
+            }
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!((value.x, value.y, value.z), (10.0, -20.0, 30.0));
+
+    let (mut gestures, _task) = accelerometer.gestures().await.unwrap();
+    sim.set_value(0x00, 1, &[0x0010, 0x0010]).unwrap();
+    let value = timeout(WAIT, async {
+        loop {
+            let value = gestures.recv().await.unwrap();
+            if value != 0 {
+                return value;
+            }
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(value, 0x0010);
+}
+
+async fn next<T: Clone>(rx: &mut broadcast::Receiver<T>) -> T {
+    timeout(WAIT, rx.recv()).await.unwrap().unwrap()
+}
+
+#[tokio::test]
+async fn remote_buttons_report_presses_holds_and_chords() {
+    use RemoteButton::*;
+    use RemoteEvent::*;
+    let sim = Arc::new(SimHub::remote());
+    let hub = connect(sim.clone()).await.unwrap();
+    let buttons = hub.mutex.lock().await.io_from_port(0x00).unwrap();
+    let config = RemoteConfig {
+        hold: Duration::from_millis(200),
+        double_click: Duration::from_millis(150),
+        ..Default::default()
+    };
+    let (mut events, _task) = buttons.remote_events(config).await.unwrap();
+    let keysd = modes::RemoteButtons::KEYSD;
+
+    sim.set_value(0x00, keysd, &[1, 0, 0]).unwrap();
+    assert_eq!(next(&mut events).await, Pressed(APlus));
+    sim.set_value(0x01, keysd, &[0, 1, 0]).unwrap();
+    assert_eq!(next(&mut events).await, Pressed(BRed));
+    assert_eq!(next(&mut events).await, Chord([APlus, BRed].into()));
+    let Held(button, held) = next(&mut events).await else {
+        panic!("not held");
+    };
+    assert_eq!(button, APlus);
+    assert!(held >= config.hold);
+    assert!(matches!(next(&mut events).await, Held(BRed, _)));
+    sim.set_value(0x00, keysd, &[0, 0, 0]).unwrap();
+    assert_eq!(next(&mut events).await, Released(APlus));
+    sim.set_value(0x01, keysd, &[0, 0, 0]).unwrap();
+    assert_eq!(next(&mut events).await, Released(BRed));
+
+    sim.set_value(0x00, keysd, &[0, 0, 1]).unwrap();
+    assert_eq!(next(&mut events).await, Pressed(AMinus));
+    sim.set_value(0x00, keysd, &[0, 0, 0]).unwrap();
+    assert_eq!(next(&mut events).await, Released(AMinus));
+    sim.set_value(0x00, keysd, &[0, 0, 1]).unwrap();
+    assert_eq!(next(&mut events).await, Pressed(AMinus));
+    assert_eq!(next(&mut events).await, DoubleClick(AMinus));
+    sim.set_value(0x00, keysd, &[0, 0, 0]).unwrap();
+    assert_eq!(next(&mut events).await, Released(AMinus));
+
+    sim.set_button(true);
+    assert_eq!(next(&mut events).await, Pressed(Green));
+    sim.set_button(false);
+    assert_eq!(next(&mut events).await, Released(Green));
+}
+
+#[tokio::test]
+async fn remote_reports_no_holds_without_a_hold_time() {
+    use RemoteButton::*;
+    use RemoteEvent::*;
+    let sim = Arc::new(SimHub::remote());
+    let hub = connect(sim.clone()).await.unwrap();
+    let buttons = hub.mutex.lock().await.io_from_port(0x00).unwrap();
+    let config = RemoteConfig {
+        hold: Duration::ZERO,
+        ..Default::default()
+    };
+    let (mut events, _task) = buttons.remote_events(config).await.unwrap();
+    let keysd = modes::RemoteButtons::KEYSD;
+
+    sim.set_value(0x00, keysd, &[0, 1, 0]).unwrap();
+    assert_eq!(next(&mut events).await, Pressed(ARed));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    sim.set_value(0x00, keysd, &[0, 0, 0]).unwrap();
+    assert_eq!(next(&mut events).await, Released(ARed));
+}
+
+#[tokio::test]
+async fn remote_reports_buttons_already_down() {
+    let sim = Arc::new(SimHub::remote());
+    let hub = connect(sim.clone()).await.unwrap();
+    let buttons = hub.mutex.lock().await.io_from_port(0x00).unwrap();
+    let keysd = modes::RemoteButtons::KEYSD;
+    sim.set_value(0x00, keysd, &[1, 0, 0]).unwrap();
+    // Side A reports the button while side B is still being enabled
+    sim.set_latency(Duration::from_millis(50));
+
+    let (mut events, _task) =
+        buttons.remote_events(Default::default()).await.unwrap();
+    assert_eq!(
+        next(&mut events).await,
+        RemoteEvent::Pressed(RemoteButton::APlus)
+    );
+}
+
+#[tokio::test]
+async fn remote_releases_buttons_after_missing_values() {
+    use RemoteButton::*;
+    use RemoteEvent::*;
+    let sim = Arc::new(SimHub::remote());
+    let hub = connect(sim.clone()).await.unwrap();
+    let buttons = hub.mutex.lock().await.io_from_port(0x00).unwrap();
+    let config = RemoteConfig {
+        hold: Duration::ZERO,
+        ..Default::default()
+    };
+    let (mut events, _task) = buttons.remote_events(config).await.unwrap();
+    let keysd = modes::RemoteButtons::KEYSD;
+    sim.set_value(0x00, keysd, &[1, 0, 0]).unwrap();
+    assert_eq!(next(&mut events).await, Pressed(APlus));
+
+    // The release is lost among more values than the device keeps
+    sim.set_value(0x00, keysd, &[0, 0, 0]).unwrap();
+    for i in 0..64 {
+        sim.set_value(0x01, keysd, &[0, (i + 1) % 2, 0]).unwrap();
+    }
+    while next(&mut events).await != Released(APlus) {}
+}
+
+#[tokio::test]
+async fn remotes_are_merged_and_tagged() {
+    let left = Arc::new(SimHub::remote());
+    let right = Arc::new(SimHub::remote());
+    let rckey = modes::RemoteButtons::RCKEY;
+    let mut remotes = Remotes::new(RemoteConfig {
+        mode: rckey,
+        ..Default::default()
+    });
+    let mut hubs = Vec::new();
+    for (name, sim) in [("left", &left), ("right", &right)] {
+        let hub = connect(sim.clone()).await.unwrap();
+        let buttons = hub.mutex.lock().await.io_from_port(0x00).unwrap();
+        remotes.add(name, &buttons).await.unwrap();
+        hubs.push(hub);
+    }
+    let mut events = remotes.subscribe();
+    let tagged = |remote: &str, event| TaggedRemoteEvent {
+        remote: remote.to_string(),
+        event,
+    };
+
+    right.set_value(0x01, rckey, &[-1]).unwrap();
+    assert_eq!(
+        next(&mut events).await,
+        tagged("right", RemoteEvent::Pressed(RemoteButton::BMinus))
+    );
+    left.set_value(0x00, rckey, &[127]).unwrap();
+    assert_eq!(
+        next(&mut events).await,
+        tagged("left", RemoteEvent::Pressed(RemoteButton::ARed))
+    );
+    // RCKEY reports one button at a time
+    left.set_value(0x00, rckey, &[1]).unwrap();
+    assert_eq!(
+        next(&mut events).await,
+        tagged("left", RemoteEvent::Released(RemoteButton::ARed))
+    );
+    assert_eq!(
+        next(&mut events).await,
+        tagged("left", RemoteEvent::Pressed(RemoteButton::APlus))
+    );
+}
+
+#[tokio::test]
+async fn pool_indexes_hubs_and_merges_their_events() {
+    let front = Arc::new(
+        SimHub::technic_hub().with_address([0x90, 0x84, 0x2b, 0, 0, 1]),
+    );
+    let rear = Arc::new(
+        SimHub::technic_hub().with_address([0x90, 0x84, 0x2b, 0, 0, 2]),
+    );
+    let handset = Arc::new(SimHub::remote());
+    let mut pool = HubPool::new();
+    pool.insert("front", connect(front.clone()).await.unwrap())
+        .await
+        .unwrap();
+    pool.insert("rear", connect(rear.clone()).await.unwrap())
+        .await
+        .unwrap();
+    pool.insert("rc", connect(handset.clone()).await.unwrap())
+        .await
+        .unwrap();
+    assert!(pool
+        .insert("rc", connect(Arc::new(SimHub::remote())).await.unwrap())
+        .await
+        .is_err());
+    assert_eq!(pool.len(), 3);
+
+    assert_eq!(pool.get("rc").unwrap().kind, HubType::RemoteControl);
+    assert_eq!(
+        pool.by_name("Handset").unwrap().kind,
+        HubType::RemoteControl
+    );
+    assert_eq!(pool.by_kind(HubType::TechnicMediumHub).count(), 2);
+    let rear_hub = pool.by_address("90:84:2b:00:00:02").unwrap();
+    assert!(Arc::ptr_eq(
+        &rear_hub.mutex,
+        &pool.get("rear").unwrap().mutex
+    ));
+
+    let mut events = pool.events();
+    handset.set_button(true);
+    let event = loop {
+        let event = next(&mut events).await;
+        if event.event == PoolEvent::Hub(HubEvent::Button { pressed: true }) {
+            break event;
+        }
+    };
+    assert_eq!(event.hub.role, "rc");
+
+    rear.drop_connection();
+    let event = loop {
+        let event = next(&mut events).await;
+        if let PoolEvent::Connection(connection) = event.event {
+            break (event.hub.role, connection);
+        }
+    };
+    assert_eq!(event, (String::from("rear"), ConnectionEvent::Disconnected));
+
+    let front_hub = pool.remove("front").unwrap();
+    assert_eq!(pool.len(), 2);
+
+    pool.disconnect().await.unwrap();
+    assert!(!handset.is_connected().await.unwrap());
+    assert!(!rear.is_connected().await.unwrap());
+    assert!(front_hub.mutex.lock().await.is_connected().await.unwrap());
+}
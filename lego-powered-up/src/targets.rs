@@ -0,0 +1,24 @@
+//! Log targets, for filtering the crate's log output by category, e.g.
+//! `RUST_LOG=lego_powered_up::attached=info,lego_powered_up::hub=debug`.
+//!
+//! Everything is logged through `tracing`, which also emits `log` records
+//! when no `tracing` subscriber is installed. The IO event handler runs in
+//! a `hub` span carrying the hub's name, and messages about a port are
+//! handled in a `port` span inside it.
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/// Devices attached to and detached from the hub
+pub const ATTACHED: &str = "lego_powered_up::attached";
+/// Hub properties, actions, alerts and errors
+pub const HUB: &str = "lego_powered_up::hub";
+/// Port input format and value notifications
+pub const INPUT: &str = "lego_powered_up::input";
+/// Output command feedback
+pub const OUTPUT: &str = "lego_powered_up::output";
+/// Discovering hubs
+pub const SCAN: &str = "lego_powered_up::scan";
+/// Connecting to and setting up hubs, reconnecting
+pub const CONNECTION: &str = "lego_powered_up::connection";
@@ -0,0 +1,197 @@
+//! The link between the crate and a hub.
+//!
+//! Hubs and devices never talk to btleplug directly, they write frames
+//! to and read notifications from a `Transport`. The BLE implementation
+//! used by `PoweredUp::create_hub` is `BtleTransport`; other links (or
+//! test doubles) can be plugged in by implementing the trait and passing
+//! it to `GenericHub::init`.
+//!
+//! The WeDo 2.0 Smart Hub doesn't speak LWP3; `Wedo2Transport` translates
+//! for it.
+//!
+//! `GenericHub` wraps the transport it is given in a `ResumableTransport`,
+//! which remembers the port setups sent to the hub and sends them again
+//! when notifications are resubscribed after a reconnect.
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use async_trait::async_trait;
+use btleplug::api::{
+    Central, CentralEvent, Characteristic, Peripheral as _, WriteType,
+};
+use btleplug::platform::{Adapter, Peripheral};
+use core::fmt::Debug;
+use core::pin::Pin;
+use core::time::Duration;
+use futures::stream::{Stream, StreamExt};
+
+use crate::error::{Error, OptionContext, Result};
+use crate::hubs::HubProperties;
+
+pub use self::resumable::ResumableTransport;
+pub use self::wedo2::Wedo2Transport;
+pub mod resumable;
+pub mod wedo2;
+
+/// How long a reconnect waits for the hub to show up in a rescan
+const RESCAN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Stream of raw frames received from the hub, one LWP3 message per item.
+pub type FrameStream = Pin<Box<dyn Stream<Item = Vec<u8>> + Send>>;
+
+/// Trait describing a link to a hub.
+#[async_trait]
+pub trait Transport: Debug + Send + Sync {
+    /// Write a single serialised message to the hub.
+    async fn write(&self, frame: &[u8]) -> Result<()>;
+    /// Start receiving notifications from the hub. May be called again
+    /// after a reconnect to resubscribe.
+    async fn notifications(&self) -> Result<FrameStream>;
+    async fn connect(&self) -> Result<()>;
+    async fn disconnect(&self) -> Result<()>;
+    async fn is_connected(&self) -> Result<bool>;
+    /// Re-establish a link that was lost. Notifications have to be
+    /// resubscribed afterwards.
+    async fn reconnect(&self) -> Result<()> {
+        self.connect().await
+    }
+    /// Properties known at the link level, i.e. before any hub property
+    /// has been requested over LWP3.
+    async fn properties(&self) -> Result<HubProperties>;
+}
+
+/// Transport over Bluetooth LE using the LPF2 characteristic.
+#[derive(Debug, Clone)]
+pub struct BtleTransport {
+    peripheral: Peripheral,
+    characteristic: Characteristic,
+    adapter: Option<Adapter>,
+}
+
+impl BtleTransport {
+    pub fn new(peripheral: Peripheral, characteristic: Characteristic) -> Self {
+        Self {
+            peripheral,
+            characteristic,
+            adapter: None,
+        }
+    }
+    /// Adapter to rescan on when reconnecting. Without one, reconnecting
+    /// only retries the connection to the known peripheral.
+    pub fn with_adapter(mut self, adapter: Adapter) -> Self {
+        self.adapter = Some(adapter);
+        self
+    }
+    pub fn peripheral(&self) -> &Peripheral {
+        &self.peripheral
+    }
+    pub fn characteristic(&self) -> &Characteristic {
+        &self.characteristic
+    }
+
+    /// Scan until the hub advertises again. A hub that dropped the link
+    /// has to be seen by the adapter before it can be connected to. The
+    /// adapter goes on scanning if another scan is running, like a
+    /// `Discovery`.
+    async fn rescan(&self, adapter: &Adapter) -> Result<()> {
+        let id = self.peripheral.id();
+        let mut events = adapter.events().await?;
+        crate::start_scan(adapter, crate::scanfilter()).await?;
+        let found = tokio::time::timeout(RESCAN_TIMEOUT, async {
+            while let Some(event) = events.next().await {
+                match event {
+                    CentralEvent::DeviceDiscovered(seen)
+                    | CentralEvent::DeviceUpdated(seen)
+                        if seen == id =>
+                    {
+                        return true;
+                    }
+                    _ => (),
+                }
+            }
+            false
+        })
+        .await;
+        crate::stop_scan(adapter).await?;
+        match found {
+            Ok(true) => Ok(()),
+            _ => Err(Error::TimeoutError(format!(
+                "Hub {} not found when rescanning",
+                id
+            ))),
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for BtleTransport {
+    async fn write(&self, frame: &[u8]) -> Result<()> {
+        self.peripheral
+            .write(&self.characteristic, frame, WriteType::WithoutResponse)
+            .await?;
+        Ok(())
+    }
+
+    async fn notifications(&self) -> Result<FrameStream> {
+        // Get the stream before subscribing so that no notification
+        // sent right after subscription is lost.
+        let uuid = self.characteristic.uuid;
+        let stream = self.peripheral.notifications().await?;
+        match self.peripheral.subscribe(&self.characteristic).await {
+            Ok(()) => (),
+            // We got a peri connection but can't subscribe. Can happen if the hub has almost timed out
+            // waiting for a connection; it seemingly connects but then turns off. On Windows the error
+            // returned was a HRESULT: Operation aborted
+            Err(e) => {
+                warn!(
+                    target: crate::targets::CONNECTION,
+                    "Error subscribing to peripheral notifications: {:#?}", e
+                )
+            }
+        }
+        Ok(Box::pin(stream.filter_map(move |n| async move {
+            if n.uuid == uuid {
+                Some(n.value)
+            } else {
+                None
+            }
+        })))
+    }
+
+    async fn connect(&self) -> Result<()> {
+        self.peripheral.connect().await?;
+        self.peripheral.discover_services().await?;
+        Ok(())
+    }
+
+    async fn disconnect(&self) -> Result<()> {
+        Ok(self.peripheral.disconnect().await?)
+    }
+
+    async fn is_connected(&self) -> Result<bool> {
+        Ok(self.peripheral.is_connected().await?)
+    }
+
+    async fn reconnect(&self) -> Result<()> {
+        if let Some(adapter) = &self.adapter {
+            self.rescan(adapter).await?;
+        }
+        self.connect().await
+    }
+
+    async fn properties(&self) -> Result<HubProperties> {
+        let props = self
+            .peripheral
+            .properties()
+            .await?
+            .context("No properties found for hub")?;
+        Ok(HubProperties {
+            mac_address: props.address.to_string(),
+            name: props.local_name.unwrap_or_default(),
+            rssi: props.tx_power_level.unwrap_or_default(),
+            ..Default::default()
+        })
+    }
+}
@@ -0,0 +1,279 @@
+//! Transport wrapper that can restore a hub's setup after a reconnect.
+//!
+//! A hub forgets its virtual ports, the input formats set on its ports,
+//! and which property and alert updates were enabled, when the link drops.
+//! The wrapper keeps the latest of these messages sent through it and
+//! sends them again on every call to `notifications` after the first, i.e.
+//! when resubscribing after a reconnect.
+//!
+//! The hub may give a virtual port set up again another id than before.
+//! The old one is then reported detached, ahead of the hub announcing the
+//! new one.
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use async_trait::async_trait;
+use futures::stream::StreamExt;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use super::{FrameStream, Transport};
+use crate::consts::HubPropertyOperation;
+use crate::error::Result;
+use crate::hubs::{HubProperties, Tokens};
+use crate::notifications::{
+    AlertOperation, AttachedIo, InputSetupCombinedSubcommand, IoAttachEvent,
+    NotificationMessage, VirtualPortSetupFormat,
+};
+
+#[derive(Debug)]
+pub struct ResumableTransport {
+    inner: Tokens,
+    setup: Arc<Mutex<Setup>>,
+    subscribed: AtomicBool,
+}
+
+/// Messages to replay, as sent
+#[derive(Debug, Default)]
+struct Setup {
+    /// Virtual ports, in the order they were set up
+    virtual_ports: Vec<VirtualPort>,
+    ports: BTreeMap<u8, PortSetup>,
+    /// Property update subscriptions, by property reference
+    properties: BTreeMap<u8, Vec<u8>>,
+    /// Alert update subscriptions, by alert type
+    alerts: BTreeMap<u8, Vec<u8>>,
+}
+
+#[derive(Debug, Default)]
+struct PortSetup {
+    /// Latest single mode setup
+    single: Option<Vec<u8>>,
+    /// Combined mode setup from lock to unlock, including the single
+    /// mode setups sent in between
+    combined: Vec<Vec<u8>>,
+    locked: bool,
+}
+
+#[derive(Debug)]
+struct VirtualPort {
+    /// The motor ports combined
+    ports: (u8, u8),
+    /// The port the hub last announced for it
+    port_id: Option<u8>,
+    /// The Connect setup
+    connect: Vec<u8>,
+}
+
+impl ResumableTransport {
+    pub fn new(inner: Tokens) -> Self {
+        Self {
+            inner,
+            setup: Default::default(),
+            subscribed: AtomicBool::new(false),
+        }
+    }
+
+    pub fn inner(&self) -> &Tokens {
+        &self.inner
+    }
+
+    /// Frames that would be replayed if notifications were resubscribed now
+    pub fn replay_frames(&self) -> Vec<Vec<u8>> {
+        let setup = self.setup();
+        let mut frames: Vec<Vec<u8>> = setup
+            .virtual_ports
+            .iter()
+            .map(|port| port.connect.clone())
+            .collect();
+        for port in setup.ports.values() {
+            if !port.combined.is_empty() {
+                frames.extend(port.combined.iter().cloned());
+            } else if let Some(single) = &port.single {
+                frames.push(single.clone());
+            }
+        }
+        frames.extend(setup.properties.values().cloned());
+        frames.extend(setup.alerts.values().cloned());
+        frames
+    }
+
+    fn record(&self, frame: &[u8]) {
+        use InputSetupCombinedSubcommand::*;
+        use NotificationMessage::*;
+        let Ok(msg) = NotificationMessage::parse(frame) else {
+            return;
+        };
+        let mut setup = self.setup();
+        match msg {
+            PortInputFormatSetupSingle(single) => {
+                let port = setup.ports.entry(single.port_id).or_default();
+                if port.locked {
+                    port.combined.push(frame.to_vec());
+                } else {
+                    port.single = Some(frame.to_vec());
+                    port.combined.clear();
+                }
+            }
+            PortInputFormatSetupCombinedmode(combined) => {
+                let port = setup.ports.entry(combined.port_id).or_default();
+                match combined.subcommand {
+                    LockLpf2DeviceForSetup => {
+                        port.locked = true;
+                        port.combined = vec![frame.to_vec()];
+                    }
+                    SetModeanddatasetCombinations { .. } => {
+                        port.combined.push(frame.to_vec());
+                    }
+                    UnlockAndStartMultiEnabled
+                    | UnlockAndStartMultiDisabled => {
+                        port.locked = false;
+                        port.single = None;
+                        port.combined.push(frame.to_vec());
+                    }
+                    ResetSensor => {
+                        setup.ports.remove(&combined.port_id);
+                    }
+                    NotUsed => (),
+                }
+            }
+            HubProperties(property) => {
+                let reference = property.reference as u8;
+                match property.operation {
+                    HubPropertyOperation::EnableUpdatesDownstream => {
+                        setup.properties.insert(reference, frame.to_vec());
+                    }
+                    HubPropertyOperation::DisableUpdatesDownstream => {
+                        setup.properties.remove(&reference);
+                    }
+                    _ => (),
+                }
+            }
+            VirtualPortSetup(VirtualPortSetupFormat::Connect {
+                port_a,
+                port_b,
+            }) => {
+                let ports = (port_a, port_b);
+                if !setup.virtual_ports.iter().any(|p| p.ports == ports) {
+                    setup.virtual_ports.push(VirtualPort {
+                        ports,
+                        port_id: None,
+                        connect: frame.to_vec(),
+                    });
+                }
+            }
+            VirtualPortSetup(VirtualPortSetupFormat::Disconnect {
+                port_id,
+            }) => {
+                setup.virtual_ports.retain(|p| p.port_id != Some(port_id));
+            }
+            HubAlerts(alert) => {
+                let alert_type = alert.alert_type as u8;
+                match alert.operation {
+                    AlertOperation::EnableUpdates => {
+                        setup.alerts.insert(alert_type, frame.to_vec());
+                    }
+                    AlertOperation::DisableUpdates => {
+                        setup.alerts.remove(&alert_type);
+                    }
+                    _ => (),
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn setup(&self) -> MutexGuard<'_, Setup> {
+        lock(&self.setup)
+    }
+}
+
+fn lock(setup: &Mutex<Setup>) -> MutexGuard<'_, Setup> {
+    // The setup is plain data, a panic elsewhere doesn't invalidate it
+    setup.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Track the virtual ports the hub announces in a frame received, and
+/// return the frames to pass on: a virtual port set up again on another
+/// port is reported detached from the old one first.
+fn announced(setup: &Mutex<Setup>, frame: Vec<u8>) -> Vec<Vec<u8>> {
+    let Ok(NotificationMessage::HubAttachedIo(AttachedIo { port, event })) =
+        NotificationMessage::parse(&frame)
+    else {
+        return vec![frame];
+    };
+    let mut setup = lock(setup);
+    match event {
+        IoAttachEvent::AttachedVirtualIo { port_a, port_b, .. } => {
+            let Some(virtual_port) = setup
+                .virtual_ports
+                .iter_mut()
+                .find(|p| p.ports == (port_a, port_b))
+            else {
+                return vec![frame];
+            };
+            let old = virtual_port.port_id.replace(port);
+            match old {
+                Some(old) if old != port => {
+                    let detached =
+                        NotificationMessage::HubAttachedIo(AttachedIo {
+                            port: old,
+                            event: IoAttachEvent::DetachedIo {},
+                        });
+                    vec![detached.serialise(), frame]
+                }
+                _ => vec![frame],
+            }
+        }
+        IoAttachEvent::DetachedIo {} => {
+            setup.virtual_ports.retain(|p| p.port_id != Some(port));
+            vec![frame]
+        }
+        IoAttachEvent::AttachedIo { .. } => vec![frame],
+    }
+}
+
+#[async_trait]
+impl Transport for ResumableTransport {
+    async fn write(&self, frame: &[u8]) -> Result<()> {
+        self.inner.write(frame).await?;
+        self.record(frame);
+        Ok(())
+    }
+
+    async fn notifications(&self) -> Result<FrameStream> {
+        let setup = self.setup.clone();
+        let stream = self.inner.notifications().await?.flat_map(move |frame| {
+            futures::stream::iter(announced(&setup, frame))
+        });
+        if self.subscribed.swap(true, Ordering::SeqCst) {
+            for frame in self.replay_frames() {
+                self.inner.write(&frame).await?;
+            }
+        }
+        Ok(Box::pin(stream))
+    }
+
+    async fn connect(&self) -> Result<()> {
+        self.inner.connect().await
+    }
+
+    async fn disconnect(&self) -> Result<()> {
+        self.inner.disconnect().await
+    }
+
+    async fn is_connected(&self) -> Result<bool> {
+        self.inner.is_connected().await
+    }
+
+    async fn reconnect(&self) -> Result<()> {
+        self.inner.reconnect().await
+    }
+
+    async fn properties(&self) -> Result<HubProperties> {
+        self.inner.properties().await
+    }
+}
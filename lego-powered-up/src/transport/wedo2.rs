@@ -0,0 +1,567 @@
+//! Transport for the WeDo 2.0 Smart Hub.
+//!
+//! The WeDo hub predates the LEGO Wireless Protocol 3.0: instead of one
+//! characteristic carrying LWP3 messages, it has a characteristic per
+//! concern. `Wedo2Transport` translates between the two, so the hub and
+//! its devices are driven by the same code as the LWP3 hubs:
+//!
+//! - devices plugged in or out on the port type characteristic become
+//!   HubAttachedIo messages, sensor values become PortValueSingle and
+//!   the button becomes a Button property update
+//! - port input setups are written to the port type characteristic, and
+//!   motor power, hub LED colors and buzzer tones to the motor value one
+//! - WeDo devices can't describe themselves, so port and mode
+//!   information requests are answered from the tables in
+//!   `iodevice::known_devices`
+//! - the firmware version, battery level and name are read from their
+//!   characteristics when requested
+//!
+//! Commands without a WeDo counterpart are answered with a generic error,
+//! as an LWP3 hub would.
+//!
+//! The hub numbers its ports from 1: ports 1 and 2 are the external ones,
+//! then come the current and voltage sensors, the piezo buzzer on port 5
+//! and the hub LED on port 6.
+
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use async_trait::async_trait;
+use btleplug::api::bleuuid::uuid_from_u16;
+use btleplug::api::{Characteristic, Peripheral as _, WriteType};
+use btleplug::platform::{Adapter, Peripheral};
+use futures::stream::StreamExt;
+use lazy_static::lazy_static;
+use num_traits::FromPrimitive;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use super::{BtleTransport, FrameStream, Transport};
+use crate::consts::{blecharacteristic, HubPropertyOperation, HubPropertyRef};
+use crate::error::{OptionContext, Result};
+use crate::hubs::HubProperties;
+use crate::iodevice::{known_devices, modes};
+use crate::notifications::*;
+use crate::IoTypeId;
+
+#[cfg(test)]
+mod test;
+
+// Command bytes of the motor value characteristic
+const MOTOR_POWER: [u8; 2] = [0x01, 0x02];
+const LED_COLOR: [u8; 2] = [0x04, 0x01];
+const LED_RGB: [u8; 2] = [0x04, 0x03];
+const TONE: [u8; 2] = [0x02, 0x04];
+
+/// Characteristic UUID from its constant, which is either a 16-bit
+/// Bluetooth SIG number or a full UUID
+fn uuid(characteristic: &str) -> Uuid {
+    match u16::from_str_radix(characteristic, 16) {
+        Ok(short) if characteristic.len() == 4 => uuid_from_u16(short),
+        _ => Uuid::parse_str(characteristic).unwrap(),
+    }
+}
+
+lazy_static! {
+    static ref BATTERY: Uuid = uuid(blecharacteristic::WEDO2_BATTERY);
+    static ref FIRMWARE_REVISION: Uuid =
+        uuid(blecharacteristic::WEDO2_FIRMWARE_REVISION);
+    static ref BUTTON: Uuid = uuid(blecharacteristic::WEDO2_BUTTON);
+    static ref PORT_TYPE: Uuid = uuid(blecharacteristic::WEDO2_PORT_TYPE);
+    static ref DISCONNECT: Uuid = uuid(blecharacteristic::WEDO2_DISCONNECT);
+    static ref SENSOR_VALUE: Uuid = uuid(blecharacteristic::WEDO2_SENSOR_VALUE);
+    static ref PORT_TYPE_WRITE: Uuid =
+        uuid(blecharacteristic::WEDO2_PORT_TYPE_WRITE);
+    static ref MOTOR_VALUE_WRITE: Uuid =
+        uuid(blecharacteristic::WEDO2_MOTOR_VALUE_WRITE);
+    static ref NAME_ID: Uuid = uuid(blecharacteristic::WEDO2_NAME_ID);
+
+    /// Characteristics the hub notifies on
+    static ref NOTIFYING: [Uuid; 4] =
+        [*PORT_TYPE, *SENSOR_VALUE, *BUTTON, *BATTERY];
+}
+
+/// What a message written by the crate takes on the WeDo hub
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// Write the bytes to the characteristic
+    Write(Uuid, Vec<u8>),
+    /// Answer the crate as an LWP3 hub would
+    Reply(NotificationMessage),
+    /// Read the characteristic holding the property and report it
+    ReadProperty(HubPropertyRef, Uuid),
+}
+
+/// Translation between LWP3 messages and the WeDo characteristics. Keeps
+/// the attached devices, which WeDo commands have to name.
+#[derive(Debug, Default)]
+pub struct Wedo2Protocol {
+    ports: BTreeMap<u8, IoTypeId>,
+}
+
+impl Wedo2Protocol {
+    /// The message for a notification on one of the hub's
+    /// characteristics, if it has an LWP3 counterpart
+    pub fn notification(
+        &mut self,
+        characteristic: Uuid,
+        value: &[u8],
+    ) -> Option<NotificationMessage> {
+        if characteristic == *PORT_TYPE {
+            self.port_type(value)
+        } else if characteristic == *SENSOR_VALUE {
+            let [_, port_id, data @ ..] = value else {
+                return None;
+            };
+            Some(NotificationMessage::PortValueSingle(
+                PortValueSingleFormat {
+                    port_id: *port_id,
+                    data: data.iter().map(|b| *b as i8).collect(),
+                },
+            ))
+        } else if characteristic == *BUTTON {
+            Some(property_update(HubPropertyValue::Button(*value.first()?)))
+        } else if characteristic == *BATTERY {
+            Some(property_update(HubPropertyValue::BatteryVoltage(
+                *value.first()?,
+            )))
+        } else {
+            None
+        }
+    }
+
+    fn port_type(&mut self, value: &[u8]) -> Option<NotificationMessage> {
+        let event = match *value {
+            [port, 0x00, ..] => {
+                self.ports.remove(&port);
+                AttachedIo {
+                    port,
+                    event: IoAttachEvent::DetachedIo {},
+                }
+            }
+            [port, 0x01, _, kind, ..] => {
+                let Some(io_type_id) = IoTypeId::from_u8(kind) else {
+                    warn!(
+                        target: crate::targets::HUB,
+                        "Unknown WeDo device {kind:#04x} on port {port}"
+                    );
+                    return None;
+                };
+                self.ports.insert(port, io_type_id);
+                let version = VersionNumber {
+                    major: 1,
+                    minor: 0,
+                    bugfix: 0,
+                    build: 0,
+                };
+                AttachedIo {
+                    port,
+                    event: IoAttachEvent::AttachedIo {
+                        io_type_id,
+                        hw_rev: version,
+                        fw_rev: version,
+                    },
+                }
+            }
+            _ => return None,
+        };
+        Some(NotificationMessage::HubAttachedIo(event))
+    }
+
+    /// What to do for a message written by the crate
+    pub fn command(&mut self, frame: &[u8]) -> Vec<Action> {
+        let command_type = frame.get(2).copied().unwrap_or_default();
+        let unsupported = || {
+            debug!(
+                target: crate::targets::HUB,
+                "No WeDo counterpart for {frame:02x?}"
+            );
+            vec![reply_error(command_type, ErrorCode::CommandNotRecognized)]
+        };
+        let Ok(msg) = NotificationMessage::parse(frame) else {
+            return unsupported();
+        };
+        use NotificationMessage::*;
+        let actions = match msg {
+            PortInformationRequest(req) => self.port_information(req),
+            PortModeInformationRequest(req) => self.mode_information(req),
+            PortInputFormatSetupSingle(setup) => self.input_setup(setup),
+            PortOutputCommand(cmd) => self.output_command(cmd),
+            HubProperties(property) => Some(property_request(property)),
+            HubActions(HubActionRequest {
+                action_type: HubAction::SwitchOffHub,
+            }) => Some(vec![Action::Write(*DISCONNECT, vec![0x00])]),
+            // Accepted but the hub has no alerts to report
+            HubAlerts(_) => Some(vec![]),
+            _ => None,
+        };
+        actions.unwrap_or_else(unsupported)
+    }
+
+    fn port_information(&self, req: InformationRequest) -> Option<Vec<Action>> {
+        let kind = self.ports.get(&req.port_id)?;
+        let information_type = known_devices::device(*kind)
+            .port_information(req.information_type)?;
+        Some(vec![Action::Reply(NotificationMessage::PortInformation(
+            PortInformationValue {
+                port_id: req.port_id,
+                information_type,
+            },
+        ))])
+    }
+
+    fn mode_information(
+        &self,
+        req: ModeInformationRequest,
+    ) -> Option<Vec<Action>> {
+        let kind = self.ports.get(&req.port_id)?;
+        let information_type = known_devices::device(*kind)
+            .mode_information(req.mode, req.information_type)?;
+        Some(vec![Action::Reply(
+            NotificationMessage::PortModeInformation(
+                PortModeInformationValue {
+                    port_id: req.port_id,
+                    mode: req.mode,
+                    information_type,
+                },
+            ),
+        )])
+    }
+
+    fn input_setup(&self, setup: InputSetupSingle) -> Option<Vec<Action>> {
+        let kind = self.ports.get(&setup.port_id)?;
+        let mut data = vec![0x01, 0x02, setup.port_id, *kind as u8, setup.mode];
+        data.extend(setup.delta.to_le_bytes());
+        // Raw values, which is what the mode tables describe
+        data.push(0x00);
+        data.push(setup.notification_enabled as u8);
+        Some(vec![
+            Action::Write(*PORT_TYPE_WRITE, data),
+            Action::Reply(NotificationMessage::PortInputFormatSingle(
+                PortInputFormatSingleFormat {
+                    port_id: setup.port_id,
+                    mode: setup.mode,
+                    delta: setup.delta,
+                    notification_enabled: setup.notification_enabled,
+                },
+            )),
+        ])
+    }
+
+    fn output_command(
+        &self,
+        cmd: PortOutputCommandFormat,
+    ) -> Option<Vec<Action>> {
+        use PortOutputSubcommand::*;
+        use WriteDirectModeDataPayload::*;
+        let port_id = cmd.port_id;
+        let kind = self.ports.get(&port_id)?;
+        let (command, value) = match (kind, cmd.subcommand) {
+            (IoTypeId::Motor, WriteDirectModeData(StartPower(power))) => {
+                (MOTOR_POWER, vec![power.to_u8()])
+            }
+            // No speed regulation, the speed is taken for power
+            (IoTypeId::Motor, StartSpeed { speed, .. }) => {
+                (MOTOR_POWER, vec![speed as u8])
+            }
+            // SetHubColor parses as StartPower
+            (IoTypeId::HubLed, WriteDirectModeData(StartPower(color))) => {
+                (LED_COLOR, vec![color.to_u8()])
+            }
+            (
+                IoTypeId::HubLed,
+                WriteDirectModeData(SetHubRgb { red, green, blue }),
+            ) => (LED_RGB, vec![red, green, blue]),
+            // Frequency and duration, cf. `iodevice::wedo2::Buzzer`
+            (
+                IoTypeId::PiezoToneSound,
+                WriteDirectModeData(Raw {
+                    mode: modes::PiezoToneSound::TONE,
+                    data,
+                }),
+            ) if data.len() == 4 => (TONE, data),
+            _ => return None,
+        };
+        Some(vec![motor_value(port_id, command, &value)])
+    }
+
+    /// The property update for a value read from the characteristic
+    /// holding it
+    pub fn property(
+        reference: HubPropertyRef,
+        value: &[u8],
+    ) -> Option<NotificationMessage> {
+        let property = match reference {
+            HubPropertyRef::FwVersion => {
+                HubPropertyValue::FwVersion(firmware_version(value)?)
+            }
+            HubPropertyRef::BatteryVoltage => {
+                HubPropertyValue::BatteryVoltage(*value.first()?)
+            }
+            HubPropertyRef::AdvertisingName => {
+                HubPropertyValue::AdvertisingName(value.to_vec())
+            }
+            _ => return None,
+        };
+        Some(property_update(property))
+    }
+}
+
+fn property_request(property: HubProperty) -> Vec<Action> {
+    if property.operation != HubPropertyOperation::RequestUpdateDownstream {
+        // The hub notifies whatever it notifies
+        return vec![];
+    }
+    let characteristic = match property.reference {
+        HubPropertyRef::FwVersion => *FIRMWARE_REVISION,
+        HubPropertyRef::BatteryVoltage => *BATTERY,
+        HubPropertyRef::AdvertisingName => *NAME_ID,
+        _ => return vec![],
+    };
+    vec![Action::ReadProperty(property.reference, characteristic)]
+}
+
+fn property_update(property: HubPropertyValue) -> NotificationMessage {
+    NotificationMessage::HubProperties(HubProperty {
+        reference: property.reference(),
+        operation: HubPropertyOperation::UpdateUpstream,
+        property: Some(property),
+    })
+}
+
+/// The hub reports its firmware as text, e.g. "1.0.00.0224". LWP3 packs
+/// the parts as binary coded decimals.
+fn firmware_version(value: &[u8]) -> Option<i32> {
+    let text = std::str::from_utf8(value).ok()?;
+    let parts = text
+        .trim_end_matches('\0')
+        .split('.')
+        .map(|part| i32::from_str_radix(part, 16).ok())
+        .collect::<Option<Vec<_>>>()?;
+    let [major, minor, bugfix, build] = parts[..] else {
+        return None;
+    };
+    Some(
+        (major & 0x07) << 28
+            | (minor & 0x0f) << 24
+            | (bugfix & 0xff) << 16
+            | (build & 0xffff),
+    )
+}
+
+fn motor_value(port_id: u8, command: [u8; 2], value: &[u8]) -> Action {
+    let mut data = vec![port_id];
+    data.extend(command);
+    data.extend(value);
+    Action::Write(*MOTOR_VALUE_WRITE, data)
+}
+
+fn reply_error(command_type: u8, error_code: ErrorCode) -> Action {
+    Action::Reply(NotificationMessage::GenericErrorMessages(
+        ErrorMessageFormat {
+            command_type,
+            error_code,
+        },
+    ))
+}
+
+#[derive(Debug, Default)]
+struct State {
+    protocol: Wedo2Protocol,
+    /// Feeds the notification stream last handed out
+    sender: Option<mpsc::UnboundedSender<Vec<u8>>>,
+    /// Feeds the property updates read to the same stream
+    properties: Option<mpsc::UnboundedSender<NotificationMessage>>,
+}
+
+impl State {
+    fn emit(&self, msg: NotificationMessage) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(msg.serialise());
+        }
+    }
+}
+
+/// Transport over Bluetooth LE to a WeDo 2.0 Smart Hub. Connecting and
+/// reconnecting work as for `BtleTransport`.
+#[derive(Debug)]
+pub struct Wedo2Transport {
+    link: BtleTransport,
+    state: Arc<Mutex<State>>,
+}
+
+impl Wedo2Transport {
+    /// Transport for a connected hub whose services have been discovered
+    pub fn new(peripheral: Peripheral) -> Result<Self> {
+        let write = find(&peripheral, *PORT_TYPE_WRITE)?;
+        Ok(Self {
+            link: BtleTransport::new(peripheral, write),
+            state: Default::default(),
+        })
+    }
+    /// Adapter to rescan on when reconnecting, cf. `BtleTransport`
+    pub fn with_adapter(mut self, adapter: Adapter) -> Self {
+        self.link = self.link.with_adapter(adapter);
+        self
+    }
+    pub fn peripheral(&self) -> &Peripheral {
+        self.link.peripheral()
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        lock(&self.state)
+    }
+
+    /// Read a property and report it
+    fn read_property(&self, reference: HubPropertyRef, characteristic: Uuid) {
+        let peripheral = self.peripheral().clone();
+        let Some(properties) = self.state().properties.clone() else {
+            // Nothing to report to
+            return;
+        };
+        tokio::spawn(async move {
+            let read = match find(&peripheral, characteristic) {
+                Ok(c) => peripheral.read(&c).await.map_err(Into::into),
+                Err(e) => Err(e),
+            };
+            match read {
+                Ok(value) => {
+                    if let Some(msg) =
+                        Wedo2Protocol::property(reference, &value)
+                    {
+                        let _ = properties.send(msg);
+                    }
+                }
+                Err(e) => warn!(
+                    target: crate::targets::HUB,
+                    "Error reading {reference:?} from WeDo hub: {e:?}"
+                ),
+            }
+        });
+    }
+}
+
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    // The state is plain data, a panic elsewhere doesn't invalidate it
+    state.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn find(peripheral: &Peripheral, uuid: Uuid) -> Result<Characteristic> {
+    peripheral
+        .characteristics()
+        .into_iter()
+        .find(|c| c.uuid == uuid)
+        .context(format!("WeDo hub lacks characteristic {uuid}"))
+}
+
+#[async_trait]
+impl Transport for Wedo2Transport {
+    async fn write(&self, frame: &[u8]) -> Result<()> {
+        let actions = self.state().protocol.command(frame);
+        for action in actions {
+            match action {
+                Action::Write(characteristic, data) => {
+                    let characteristic =
+                        find(self.peripheral(), characteristic)?;
+                    self.peripheral()
+                        .write(&characteristic, &data, WriteType::WithResponse)
+                        .await?;
+                }
+                Action::Reply(msg) => self.state().emit(msg),
+                Action::ReadProperty(reference, characteristic) => {
+                    self.read_property(reference, characteristic)
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn notifications(&self) -> Result<FrameStream> {
+        // Get the stream before subscribing so that no notification
+        // sent right after subscription is lost.
+        let mut stream = self.peripheral().notifications().await?;
+        for characteristic in *NOTIFYING {
+            let subscribed = match find(self.peripheral(), characteristic) {
+                Ok(c) => {
+                    self.peripheral().subscribe(&c).await.map_err(Into::into)
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = subscribed {
+                warn!(
+                    target: crate::targets::CONNECTION,
+                    "Error subscribing to WeDo characteristic {characteristic}: {e:?}"
+                )
+            }
+        }
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (properties, mut read) = mpsc::unbounded_channel();
+        {
+            let mut state = self.state();
+            state.sender = Some(tx.clone());
+            state.properties = Some(properties);
+        }
+        let state = self.state.clone();
+        tokio::spawn(async move {
+            loop {
+                // The hub announces its devices on subscribing, before
+                // answering any read. Forwarding the notifications received
+                // first keeps a property read after subscribing, like the
+                // firmware version `ConnectedHub::setup_hub` waits for,
+                // behind the announcements.
+                let msg = tokio::select! {
+                    biased;
+                    n = stream.next() => match n {
+                        Some(n) => {
+                            lock(&state).protocol.notification(n.uuid, &n.value)
+                        }
+                        None => break,
+                    },
+                    Some(msg) = read.recv() => Some(msg),
+                };
+                if let Some(msg) = msg {
+                    if tx.send(msg.serialise()).is_err() {
+                        return;
+                    }
+                }
+            }
+            // The link is gone; end the stream unless it was replaced
+            let mut state = lock(&state);
+            if state.sender.as_ref().is_some_and(|s| s.same_channel(&tx)) {
+                state.sender = None;
+                state.properties = None;
+            }
+        });
+        Ok(Box::pin(futures::stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|frame| (frame, rx))
+        })))
+    }
+
+    async fn connect(&self) -> Result<()> {
+        self.link.connect().await
+    }
+
+    async fn disconnect(&self) -> Result<()> {
+        {
+            let mut state = self.state();
+            state.sender = None;
+            state.properties = None;
+        }
+        self.link.disconnect().await
+    }
+
+    async fn is_connected(&self) -> Result<bool> {
+        self.link.is_connected().await
+    }
+
+    async fn reconnect(&self) -> Result<()> {
+        self.link.reconnect().await
+    }
+
+    async fn properties(&self) -> Result<HubProperties> {
+        self.link.properties().await
+    }
+}
@@ -0,0 +1,378 @@
+use super::*;
+use crate::consts::Color;
+use crate::iodevice::modes;
+use crate::iodevice::speaker::Note;
+use crate::iodevice::wedo2::Buzzer;
+use crate::iodevice::IoDevice;
+use log::LevelFilter;
+use std::time::Duration;
+use tokio::time::Instant;
+
+fn init() {
+    let _ = env_logger::builder()
+        .is_test(true)
+        .filter(None, LevelFilter::Trace)
+        .try_init();
+}
+
+/// A protocol with a motor on port 1, a motion sensor on port 2 and the
+/// piezo buzzer and hub LED the hub has built in
+fn protocol() -> Wedo2Protocol {
+    let mut protocol = Wedo2Protocol::default();
+    let port_type = *PORT_TYPE;
+    for (port, kind) in [
+        (1, IoTypeId::Motor),
+        (2, IoTypeId::MotionSensor),
+        (5, IoTypeId::PiezoToneSound),
+        (6, IoTypeId::HubLed),
+    ] {
+        protocol.notification(port_type, &[port, 0x01, 0x00, kind as u8]);
+    }
+    protocol
+}
+
+fn command(
+    protocol: &mut Wedo2Protocol,
+    msg: NotificationMessage,
+) -> Vec<Action> {
+    protocol.command(&msg.serialise())
+}
+
+fn output(
+    port_id: u8,
+    subcommand: PortOutputSubcommand,
+) -> NotificationMessage {
+    NotificationMessage::PortOutputCommand(PortOutputCommandFormat {
+        port_id,
+        startup_info: StartupInfo::ExecuteImmediately,
+        completion_info: CompletionInfo::NoAction,
+        subcommand,
+    })
+}
+
+fn motor_write(data: &[u8]) -> Vec<Action> {
+    vec![Action::Write(*MOTOR_VALUE_WRITE, data.to_vec())]
+}
+
+/// Takes the writes of a `Wedo2Transport` without a hub: the protocol
+/// translates them, and the characteristic writes are kept
+#[derive(Debug, Default)]
+struct Recorder {
+    protocol: Mutex<Wedo2Protocol>,
+    written: Mutex<Vec<(Instant, Action)>>,
+}
+
+#[async_trait]
+impl Transport for Recorder {
+    async fn write(&self, frame: &[u8]) -> Result<()> {
+        let actions = self.protocol.lock().unwrap().command(frame);
+        let now = Instant::now();
+        self.written.lock().unwrap().extend(
+            actions
+                .into_iter()
+                .filter(|action| matches!(action, Action::Write(..)))
+                .map(|action| (now, action)),
+        );
+        Ok(())
+    }
+    async fn notifications(&self) -> Result<FrameStream> {
+        Ok(Box::pin(futures::stream::pending()))
+    }
+    async fn connect(&self) -> Result<()> {
+        Ok(())
+    }
+    async fn disconnect(&self) -> Result<()> {
+        Ok(())
+    }
+    async fn is_connected(&self) -> Result<bool> {
+        Ok(true)
+    }
+    async fn properties(&self) -> Result<HubProperties> {
+        Ok(Default::default())
+    }
+}
+
+#[test]
+fn characteristic_uuids() {
+    init();
+    assert_eq!(
+        uuid(blecharacteristic::WEDO2_BATTERY).to_string(),
+        "00002a19-0000-1000-8000-00805f9b34fb"
+    );
+    assert_eq!(
+        uuid(blecharacteristic::WEDO2_PORT_TYPE).to_string(),
+        blecharacteristic::WEDO2_PORT_TYPE
+    );
+}
+
+#[test]
+fn devices_attach_and_detach() {
+    init();
+    let mut protocol = Wedo2Protocol::default();
+    let port_type = *PORT_TYPE;
+    let attached = protocol.notification(port_type, &[1, 0x01, 0x00, 0x22]);
+    assert!(matches!(
+        attached,
+        Some(NotificationMessage::HubAttachedIo(AttachedIo {
+            port: 1,
+            event: IoAttachEvent::AttachedIo {
+                io_type_id: IoTypeId::ExternalTiltSensor,
+                ..
+            },
+        }))
+    ));
+    let detached = protocol.notification(port_type, &[1, 0x00]);
+    assert!(matches!(
+        detached,
+        Some(NotificationMessage::HubAttachedIo(AttachedIo {
+            port: 1,
+            event: IoAttachEvent::DetachedIo {},
+        }))
+    ));
+    // Nothing is left to describe
+    let request =
+        NotificationMessage::PortInformationRequest(InformationRequest {
+            port_id: 1,
+            information_type: InformationType::ModeInfo,
+        });
+    assert!(matches!(
+        command(&mut protocol, request)[..],
+        [Action::Reply(NotificationMessage::GenericErrorMessages(_))]
+    ));
+    assert_eq!(
+        protocol.notification(port_type, &[2, 0x01, 0x00, 0xee]),
+        None
+    );
+}
+
+#[test]
+fn devices_are_described_from_the_tables() {
+    init();
+    let mut protocol = protocol();
+    let request =
+        NotificationMessage::PortInformationRequest(InformationRequest {
+            port_id: 2,
+            information_type: InformationType::ModeInfo,
+        });
+    let [Action::Reply(NotificationMessage::PortInformation(info))] =
+        &command(&mut protocol, request)[..]
+    else {
+        panic!("no port information");
+    };
+    assert!(matches!(
+        info.information_type,
+        PortInformationType::ModeInfo { mode_count: 3, .. }
+    ));
+
+    let request = NotificationMessage::PortModeInformationRequest(
+        ModeInformationRequest {
+            port_id: 2,
+            mode: modes::MotionSensor::COUNT,
+            information_type: ModeInformationType::Name,
+        },
+    );
+    let [Action::Reply(NotificationMessage::PortModeInformation(info))] =
+        &command(&mut protocol, request)[..]
+    else {
+        panic!("no mode information");
+    };
+    assert_eq!(
+        info.information_type,
+        PortModeInformationType::Name(b"LPF2-COUNT".to_vec())
+    );
+}
+
+#[test]
+fn input_setup_is_written_to_the_port_type() {
+    init();
+    let mut protocol = protocol();
+    let setup =
+        NotificationMessage::PortInputFormatSetupSingle(InputSetupSingle {
+            port_id: 2,
+            mode: modes::MotionSensor::DETECT,
+            delta: 1,
+            notification_enabled: true,
+        });
+    let actions = command(&mut protocol, setup);
+    assert_eq!(
+        actions[0],
+        Action::Write(
+            *PORT_TYPE_WRITE,
+            vec![0x01, 0x02, 2, 0x23, 0, 1, 0, 0, 0, 0x00, 0x01]
+        )
+    );
+    assert!(matches!(
+        actions[1],
+        Action::Reply(NotificationMessage::PortInputFormatSingle(_))
+    ));
+
+    let value = protocol.notification(*SENSOR_VALUE, &[0x00, 2, 7]);
+    assert_eq!(
+        value,
+        Some(NotificationMessage::PortValueSingle(
+            PortValueSingleFormat {
+                port_id: 2,
+                data: vec![7],
+            }
+        ))
+    );
+}
+
+#[test]
+fn outputs_are_written_to_the_motor_value() {
+    init();
+    let mut protocol = protocol();
+    let power = output(
+        1,
+        PortOutputSubcommand::WriteDirectModeData(
+            WriteDirectModeDataPayload::StartPower(Power::Ccw(50)),
+        ),
+    );
+    assert_eq!(
+        command(&mut protocol, power),
+        motor_write(&[1, 0x01, 0x02, 0xce])
+    );
+
+    let color = output(
+        6,
+        PortOutputSubcommand::WriteDirectModeData(
+            WriteDirectModeDataPayload::SetHubColor(Color::Red as i8),
+        ),
+    );
+    assert_eq!(
+        command(&mut protocol, color),
+        motor_write(&[6, 0x04, 0x01, 9])
+    );
+
+    let rgb = output(
+        6,
+        PortOutputSubcommand::WriteDirectModeData(
+            WriteDirectModeDataPayload::SetHubRgb {
+                red: 1,
+                green: 2,
+                blue: 3,
+            },
+        ),
+    );
+    assert_eq!(
+        command(&mut protocol, rgb),
+        motor_write(&[6, 0x04, 0x03, 1, 2, 3])
+    );
+
+    let tone = output(
+        5,
+        PortOutputSubcommand::WriteDirectModeData(
+            WriteDirectModeDataPayload::Raw {
+                mode: modes::PiezoToneSound::TONE,
+                data: vec![0xb8, 0x01, 0xf4, 0x01],
+            },
+        ),
+    );
+    assert_eq!(
+        command(&mut protocol, tone),
+        motor_write(&[5, 0x02, 0x04, 0xb8, 0x01, 0xf4, 0x01])
+    );
+
+    // The sensor has no outputs
+    let power = output(
+        2,
+        PortOutputSubcommand::WriteDirectModeData(
+            WriteDirectModeDataPayload::StartPower(Power::Cw(50)),
+        ),
+    );
+    assert!(matches!(
+        command(&mut protocol, power)[..],
+        [Action::Reply(NotificationMessage::GenericErrorMessages(
+            ErrorMessageFormat {
+                error_code: ErrorCode::CommandNotRecognized,
+                ..
+            }
+        ))]
+    ));
+}
+
+#[test]
+fn hub_properties_and_actions() {
+    init();
+    let mut protocol = protocol();
+    let request = NotificationMessage::HubProperties(HubProperty {
+        reference: HubPropertyRef::FwVersion,
+        operation: HubPropertyOperation::RequestUpdateDownstream,
+        property: None,
+    });
+    assert_eq!(
+        command(&mut protocol, request),
+        vec![Action::ReadProperty(
+            HubPropertyRef::FwVersion,
+            *FIRMWARE_REVISION
+        )]
+    );
+    assert_eq!(
+        Wedo2Protocol::property(HubPropertyRef::FwVersion, b"1.0.00.0224"),
+        Some(property_update(HubPropertyValue::FwVersion(0x1000_0224)))
+    );
+
+    let button = protocol.notification(*BUTTON, &[0x01]);
+    assert_eq!(button, Some(property_update(HubPropertyValue::Button(1))));
+
+    let off = NotificationMessage::HubActions(HubActionRequest {
+        action_type: HubAction::SwitchOffHub,
+    });
+    assert_eq!(
+        command(&mut protocol, off),
+        vec![Action::Write(*DISCONNECT, vec![0x00])]
+    );
+}
+
+#[tokio::test]
+async fn buzzer_plays_notes_as_hub_tones() {
+    init();
+    let recorder = Arc::new(Recorder {
+        protocol: Mutex::new(protocol()),
+        ..Default::default()
+    });
+    let buzzer = IoDevice::new(IoTypeId::PiezoToneSound, 5, recorder.clone());
+    assert!(Buzzer::check(&IoDevice::new(
+        IoTypeId::Motor,
+        1,
+        recorder.clone()
+    ))
+    .is_err());
+
+    let note = Duration::from_millis(50);
+    buzzer.play_note(Note::new(440, note * 10)).await.unwrap();
+    buzzer.play_note(Note::rest(note)).await.unwrap();
+    let start = Instant::now();
+    let sequencer = buzzer.sequencer().unwrap();
+    sequencer
+        .queue_all([
+            Note::new(880, note),
+            Note::rest(note),
+            Note::new(u16::MAX, Duration::from_secs(100)),
+        ])
+        .unwrap();
+    assert!(start.elapsed() < note);
+    tokio::time::timeout(Duration::from_secs(1), async {
+        while recorder.written.lock().unwrap().len() < 3 {
+            tokio::time::sleep(note / 5).await;
+        }
+    })
+    .await
+    .unwrap();
+    sequencer.stop();
+
+    let written = recorder.written.lock().unwrap();
+    let writes: Vec<Action> =
+        written.iter().map(|(_, action)| action.clone()).collect();
+    let expected: Vec<Action> = [
+        [5, 0x02, 0x04, 0xb8, 0x01, 0xf4, 0x01],
+        [5, 0x02, 0x04, 0x70, 0x03, 0x32, 0x00],
+        [5, 0x02, 0x04, 0xff, 0xff, 0xff, 0xff],
+    ]
+    .iter()
+    .flat_map(|data| motor_write(data))
+    .collect();
+    assert_eq!(writes, expected);
+    // The last note waited for the one and the rest before it
+    assert!(written[2].0 - written[1].0 >= note * 2);
+}
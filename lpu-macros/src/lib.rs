@@ -18,7 +18,7 @@ pub fn parse_derive(input: TokenStream) -> TokenStream {
             pub fn parse<'a>(mut msg: impl Iterator<Item = &'a u8>) ->
                 Result<Self> {
                 let val = next!(msg);
-                log::trace!(#trace_msg, val);
+                tracing::trace!(#trace_msg, val);
                 Ok(ok!(Self::from_u8(val)))
             }
         }